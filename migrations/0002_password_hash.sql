@@ -0,0 +1,2 @@
+-- scrypt hashes don't fit the 32 characters of an md5 hex digest
+ALTER TABLE `user` MODIFY COLUMN `password` varchar(128) COLLATE utf8mb4_unicode_ci NOT NULL;
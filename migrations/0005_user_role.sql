@@ -0,0 +1,2 @@
+-- moderators and admins, see constant::USER::ROLE
+ALTER TABLE `user` ADD COLUMN `role` tinyint(4) NOT NULL DEFAULT '0' AFTER `salt`;
@@ -0,0 +1,4 @@
+-- the comment a reply answers, if any
+ALTER TABLE `comment` ADD COLUMN `parent_id` int(11) DEFAULT NULL AFTER `user_id`;
+ALTER TABLE `comment` ADD KEY `parent_id` (`parent_id`);
+ALTER TABLE `comment` ADD CONSTRAINT `comment_ibfk_3` FOREIGN KEY (`parent_id`) REFERENCES `comment` (`id`);
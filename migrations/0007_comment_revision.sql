@@ -0,0 +1,12 @@
+-- comments can be edited and deleted; earlier contents are kept
+ALTER TABLE `comment` ADD COLUMN `status` tinyint(4) NOT NULL DEFAULT '0' AFTER `content`;
+ALTER TABLE `comment` ADD COLUMN `update_time` datetime DEFAULT NULL AFTER `create_time`;
+CREATE TABLE `comment_revision` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `comment_id` int(11) NOT NULL,
+  `content` mediumtext COLLATE utf8mb4_unicode_ci NOT NULL,
+  `create_time` datetime NOT NULL,
+  PRIMARY KEY (`id`),
+  KEY `comment_id` (`comment_id`),
+  CONSTRAINT `comment_revision_ibfk_1` FOREIGN KEY (`comment_id`) REFERENCES `comment` (`id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
@@ -0,0 +1,4 @@
+-- search; the ngram parser needs MySQL 5.7.6 or later
+ALTER TABLE `article` ADD FULLTEXT KEY `ft_title` (`title`) WITH PARSER ngram;
+ALTER TABLE `article` ADD FULLTEXT KEY `ft_title_content` (`title`, `content`) WITH PARSER ngram;
+ALTER TABLE `comment` ADD FULLTEXT KEY `ft_content` (`content`) WITH PARSER ngram;
@@ -0,0 +1,17 @@
+-- items of the news sources shown on /news, one row per link
+CREATE TABLE `news` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `source` varchar(32) COLLATE utf8mb4_unicode_ci NOT NULL,
+  `title` varchar(255) COLLATE utf8mb4_unicode_ci NOT NULL,
+  `url` varchar(1024) COLLATE utf8mb4_unicode_ci NOT NULL,
+  `url_hash` char(64) COLLATE utf8mb4_unicode_ci NOT NULL,
+  `score` int(11) DEFAULT NULL,
+  `author` varchar(64) COLLATE utf8mb4_unicode_ci NOT NULL,
+  `comments_count` int(11) DEFAULT NULL,
+  `comments_url` varchar(1024) COLLATE utf8mb4_unicode_ci DEFAULT NULL,
+  `publish_time` datetime NOT NULL,
+  `fetch_time` datetime NOT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `url_hash` (`url_hash`),
+  KEY `publish_time` (`publish_time`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
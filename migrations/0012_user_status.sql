@@ -0,0 +1,2 @@
+-- lets operators ban an account from the command line
+ALTER TABLE `user` ADD COLUMN `status` tinyint(4) NOT NULL DEFAULT '0' AFTER `role`;
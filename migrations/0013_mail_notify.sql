@@ -0,0 +1,8 @@
+-- replies and mentions are also mailed, at once or as a daily digest, see
+-- constant::USER::NOTIFY; users only get mails once they turn them on
+ALTER TABLE `user` ADD COLUMN `notify` tinyint(4) NOT NULL DEFAULT '-1' AFTER `status`;
+-- when the mail of a message went out, or was skipped; NULL until then
+ALTER TABLE `message` ADD COLUMN `mail_time` datetime DEFAULT NULL AFTER `status`;
+-- nobody gets mailed about what happened before
+UPDATE `message` SET `mail_time` = `create_time`;
+ALTER TABLE `message` ADD KEY `mail_time` (`mail_time`);
@@ -0,0 +1,5 @@
+-- see ../0013_mail_notify.sql
+ALTER TABLE user ADD COLUMN notify INTEGER NOT NULL DEFAULT -1;
+ALTER TABLE message ADD COLUMN mail_time TEXT;
+UPDATE message SET mail_time = create_time;
+CREATE INDEX message_mail_time ON message (mail_time);
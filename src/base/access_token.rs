@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::fmt;
+use iron::prelude::*;
+use iron::BeforeMiddleware;
+use iron::typemap::Key;
+use iron::status;
+use chrono::*;
+use persistent::Read;
+use base::constant;
+use base::db::DbError;
+use base::error::AppError;
+use base::framework::LoginUser;
+use base::model::AccessToken;
+use base::util;
+use repo::Repos;
+
+// Personal access tokens let scripts and bots use the api with an
+// `Authorization: Bearer <token>` header. Like sessions, only the sha256 of
+// a token is stored; the token itself is shown once, when it is created.
+
+const BEARER_PREFIX: &'static str = "Bearer ";
+const TOKEN_PREFIX: &'static str = "rlc_";
+
+// Resolves bearer tokens before the handler runs, so that
+// `framework::current_user` finds the token's owner.
+pub struct BearerAuth;
+
+pub struct BearerUser;
+
+impl Key for BearerUser {
+    type Value = LoginUser;
+}
+
+#[derive(Debug)]
+struct InvalidToken;
+
+impl fmt::Display for InvalidToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid access token")
+    }
+}
+
+impl Error for InvalidToken {
+    fn description(&self) -> &str {
+        "invalid access token"
+    }
+}
+
+impl BeforeMiddleware for BearerAuth {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let token = match bearer_token(req) {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+
+        // a wrong token must not silently fall back to the cookie
+        let repos = req.get::<Read<Repos>>().unwrap();
+        match touch(&repos, &token) {
+            Ok(Some(user)) => {
+                req.extensions.insert::<BearerUser>(user);
+                Ok(())
+            }
+            Ok(None) => Err(IronError::new(InvalidToken, status::Unauthorized)),
+            Err(e) => Err(AppError::from(e).into()),
+        }
+    }
+}
+
+pub fn bearer_token(req: &Request) -> Option<String> {
+    req.headers.get_raw("Authorization")
+        .and_then(|raw| String::from_utf8(raw[0].clone()).ok())
+        .and_then(|value| if value.starts_with(BEARER_PREFIX) {
+            Some(value[BEARER_PREFIX.len()..].trim().to_owned())
+        } else {
+            None
+        })
+}
+
+pub fn create(repos: &Repos, user_id: u64, name: &str, scopes: u8) -> Result<String, DbError> {
+    let token = format!("{}{}", TOKEN_PREFIX, util::gen_random_token(40));
+    let now = Local::now().naive_local();
+    try!(repos.tokens.create(user_id, name, &util::sha256_hex(&token), scopes, now));
+    Ok(token)
+}
+
+// Returns the owner of a token, limited to the token's scopes, refreshing
+// its last used time on the way.
+fn touch(repos: &Repos, token: &str) -> Result<Option<LoginUser>, DbError> {
+    let (token_id, scopes, user_id) = match try!(repos.tokens.find(&util::sha256_hex(token))) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    let user = match try!(repos.users.find(user_id)) {
+        Some(ref user) if user.status == constant::USER::STATUS::NORMAL => user.clone(),
+        _ => return Ok(None),
+    };
+
+    let now = Local::now().naive_local();
+    let touch_before = now - Duration::minutes(constant::ACCESS_TOKEN::TOUCH_INTERVAL_MINUTES);
+    try!(repos.tokens.touch(token_id, now, touch_before));
+
+    let mut login_user = LoginUser::new(user.id, &user.username, &user.email, user.role, 0, "");
+    login_user.scopes = Some(scopes);
+    Ok(Some(login_user))
+}
+
+pub fn list(repos: &Repos, user_id: u64) -> Result<Vec<AccessToken>, DbError> {
+    repos.tokens.list(user_id)
+}
+
+pub fn delete(repos: &Repos, user_id: u64, token_id: u64) -> Result<(), DbError> {
+    repos.tokens.delete(user_id, token_id)
+}
+
+pub fn delete_all(repos: &Repos, user_id: u64) -> Result<(), DbError> {
+    repos.tokens.delete_all(user_id)
+}
@@ -1,36 +1,285 @@
 extern crate toml;
 
+use std::env;
+use std::net::IpAddr;
 use std::io::prelude::*;
 use std::path::Path;
 use std::fs::File;
-use self::toml::{Table, Value, Parser};
-use std::cmp::Ord;
-use std::borrow::Borrow;
+use std::str::FromStr;
+use self::toml::{Value, Parser, Decoder};
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{Json, Object, ToJson};
 use iron::typemap::Key;
 
-#[derive(Clone)]
-pub struct Config(Table);
+// config.toml, decoded and checked once at startup so that handlers can use
+// plain fields. Secrets may also come from RUST_LANG_CN_* environment
+// variables, which win over the file, see `ENV_OVERRIDES`.
+//
+// Only `[site]` may change while the server runs: it is kept apart in a
+// `persistent::State<SiteConfig>` and swapped on SIGHUP, see base::reload.
+
+pub const DEFAULT_PATH: &'static str = "config.toml";
+
+#[derive(Clone, RustcDecodable)]
+pub struct Config {
+    pub app_path: String,
+    pub static_path: String,
+    pub cookie_sign_key: String,
+    pub listen: String,
+    // addresses of the proxies in front of the site, e.g. nginx; only their
+    // X-Real-IP header is believed
+    pub trusted_proxies: Vec<String>,
+    // authors may edit or delete a comment within this many minutes
+    pub comment_edit_minutes: i64,
+    pub database: DatabaseConfig,
+    pub github: GithubConfig,
+    pub mail: MailConfig,
+    pub news: NewsConfig,
+    pub notify: NotifyConfig,
+    pub site: SiteConfig,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct DatabaseConfig {
+    // "mysql" or "sqlite"; sqlite only reads `path`, mysql everything else
+    pub backend: String,
+    pub path: String,
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub name: String,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct GithubConfig {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct MailConfig {
+    // "smtp" or "file"
+    pub transport: String,
+    pub from: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: bool,
+    pub smtp_user: String,
+    pub smtp_password: String,
+    pub file_dir: String,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct NewsConfig {
+    pub interval_minutes: u64,
+    pub sources: Vec<NewsSourceConfig>,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct NotifyConfig {
+    // how often new messages are looked for and mailed
+    pub interval_minutes: u64,
+    // local hour of the daily digest, 0 to 23
+    pub digest_hour: u32,
+}
+
+// One `[[news.sources]]` entry. `kind` is "hacker_news", "feed" (RSS or Atom)
+// or "reddit".
+#[derive(Clone, RustcDecodable)]
+pub struct NewsSourceConfig {
+    pub name: String,
+    pub title: String,
+    pub kind: String,
+    pub url: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct SiteConfig {
+    // shown in the footer
+    pub links: Vec<LinkConfig>,
+    // friend communities on the home page; a relative `image` is under
+    // static_path
+    pub friends: Vec<LinkConfig>,
+    pub ads: Vec<LinkConfig>,
+    pub features: FeaturesConfig,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct LinkConfig {
+    pub title: String,
+    pub url: String,
+    pub image: Option<String>,
+}
+
+#[derive(Clone, RustcDecodable)]
+pub struct FeaturesConfig {
+    // the /news page
+    pub news: bool,
+    pub register: bool,
+    pub github_login: bool,
+}
+
+const ENV_OVERRIDES: [&'static str; 14] = [
+    "RUST_LANG_CN_APP_PATH",
+    "RUST_LANG_CN_STATIC_PATH",
+    "RUST_LANG_CN_LISTEN",
+    "RUST_LANG_CN_COOKIE_SIGN_KEY",
+    "RUST_LANG_CN_DATABASE_BACKEND",
+    "RUST_LANG_CN_DATABASE_PATH",
+    "RUST_LANG_CN_DATABASE_HOST",
+    "RUST_LANG_CN_DATABASE_PORT",
+    "RUST_LANG_CN_DATABASE_USER",
+    "RUST_LANG_CN_DATABASE_PASSWORD",
+    "RUST_LANG_CN_DATABASE_NAME",
+    "RUST_LANG_CN_GITHUB_CLIENT_SECRET",
+    "RUST_LANG_CN_MAIL_SMTP_USER",
+    "RUST_LANG_CN_MAIL_SMTP_PASSWORD",
+];
 
 impl Config {
-    pub fn new() -> Config {
-        let path = Path::new("config.toml");
-        let mut file = File::open(&path).unwrap();
+    pub fn load(path: &str) -> Result<Config, String> {
         let mut s = String::new();
-        file.read_to_string(&mut s).unwrap();
-        let value = Parser::new(&s).parse().unwrap();
-        Config(value)
+        try!(File::open(&Path::new(path))
+             .and_then(|mut file| file.read_to_string(&mut s))
+             .map_err(|e| format!("{}: {}", path, e)));
+
+        let mut parser = Parser::new(&s);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(format!("{}:{}:{}: {}", path, line + 1, col + 1, e.desc));
+            }
+        };
+
+        let mut config: Config = try!(Decodable::decode(&mut Decoder::new(Value::Table(table)))
+                                      .map_err(|e| format!("{}: {}", path, e)));
+        for name in ENV_OVERRIDES.iter() {
+            if let Ok(value) = env::var(name) {
+                try!(config.set_env(name, value));
+            }
+        }
+        try!(config.validate());
+
+        // images of links are given relative to static_path
+        let static_path = config.static_path.clone();
+        for link in config.site.friends.iter_mut().chain(config.site.ads.iter_mut()) {
+            if let Some(ref mut image) = link.image {
+                if !image.starts_with("http://") && !image.starts_with("https://") {
+                    *image = format!("{}/{}", static_path, image.trim_left_matches('/'));
+                }
+            }
+        }
+        Ok(config)
     }
 
-    pub fn get<Q: ?Sized>(&self, key: &Q) -> &Value where String: Borrow<Q>, Q: Ord {
-        self.0.get(key).unwrap()
+    fn set_env(&mut self, name: &str, value: String) -> Result<(), String> {
+        match name {
+            "RUST_LANG_CN_APP_PATH" => self.app_path = value,
+            "RUST_LANG_CN_STATIC_PATH" => self.static_path = value,
+            "RUST_LANG_CN_LISTEN" => self.listen = value,
+            "RUST_LANG_CN_COOKIE_SIGN_KEY" => self.cookie_sign_key = value,
+            "RUST_LANG_CN_DATABASE_BACKEND" => self.database.backend = value,
+            "RUST_LANG_CN_DATABASE_PATH" => self.database.path = value,
+            "RUST_LANG_CN_DATABASE_HOST" => self.database.host = value,
+            "RUST_LANG_CN_DATABASE_PORT" => self.database.port = try!(parse_env(name, &value)),
+            "RUST_LANG_CN_DATABASE_USER" => self.database.user = value,
+            "RUST_LANG_CN_DATABASE_PASSWORD" => self.database.password = value,
+            "RUST_LANG_CN_DATABASE_NAME" => self.database.name = value,
+            "RUST_LANG_CN_GITHUB_CLIENT_SECRET" => self.github.client_secret = value,
+            "RUST_LANG_CN_MAIL_SMTP_USER" => self.mail.smtp_user = value,
+            "RUST_LANG_CN_MAIL_SMTP_PASSWORD" => self.mail.smtp_password = value,
+            _ => unreachable!(),
+        }
+        Ok(())
     }
 
-    pub fn value(&self) -> Table {
-        self.0.clone()
+    fn validate(&self) -> Result<(), String> {
+        for &(key, url) in [("app_path", &self.app_path), ("static_path", &self.static_path)].iter() {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!("{} must be an http(s) url: {}", key, url));
+            }
+            if url.ends_with('/') {
+                return Err(format!("{} must not end with a slash: {}", key, url));
+            }
+        }
+        if self.cookie_sign_key.is_empty() {
+            return Err("cookie_sign_key must not be empty".to_owned());
+        }
+        for proxy in &self.trusted_proxies {
+            if proxy.parse::<IpAddr>().is_err() {
+                return Err(format!("trusted_proxies must be ip addresses: {}", proxy));
+            }
+        }
+        if self.comment_edit_minutes < 0 {
+            return Err("comment_edit_minutes must not be negative".to_owned());
+        }
+        if self.database.backend != "mysql" && self.database.backend != "sqlite" {
+            return Err(format!("database.backend must be \"mysql\" or \"sqlite\": {}",
+                               self.database.backend));
+        }
+        if self.mail.transport != "smtp" && self.mail.transport != "file" {
+            return Err(format!("mail.transport must be \"smtp\" or \"file\": {}",
+                               self.mail.transport));
+        }
+        if self.news.interval_minutes == 0 {
+            return Err("news.interval_minutes must be positive".to_owned());
+        }
+        if self.notify.interval_minutes == 0 {
+            return Err("notify.interval_minutes must be positive".to_owned());
+        }
+        if self.notify.digest_hour > 23 {
+            return Err(format!("notify.digest_hour must be 0 to 23: {}",
+                               self.notify.digest_hour));
+        }
+        for source in &self.news.sources {
+            match &*source.kind {
+                "hacker_news" | "feed" | "reddit" => {}
+                kind => return Err(format!("unknown kind of news source {}: {}",
+                                           source.name, kind)),
+            }
+        }
+        Ok(())
     }
+}
 
+fn parse_env<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid {}: {}", name, value))
 }
 
 impl Key for Config {
     type Value = Config;
 }
+
+impl ToJson for LinkConfig {
+    fn to_json(&self) -> Json {
+        let mut object = Object::new();
+        object.insert("title".to_owned(), self.title.to_json());
+        object.insert("url".to_owned(), self.url.to_json());
+        object.insert("image".to_owned(), self.image.to_json());
+        object.to_json()
+    }
+}
+
+impl ToJson for SiteConfig {
+    fn to_json(&self) -> Json {
+        let mut features = Object::new();
+        features.insert("news".to_owned(), self.features.news.to_json());
+        features.insert("register".to_owned(), self.features.register.to_json());
+        features.insert("github_login".to_owned(), self.features.github_login.to_json());
+
+        let mut object = Object::new();
+        object.insert("links".to_owned(), self.links.to_json());
+        object.insert("friends".to_owned(), self.friends.to_json());
+        object.insert("ads".to_owned(), self.ads.to_json());
+        object.insert("features".to_owned(), features.to_json());
+        object.to_json()
+    }
+}
+
+impl Key for SiteConfig {
+    type Value = SiteConfig;
+}
@@ -5,6 +5,34 @@ lazy_static! {
         1970,1,1).and_hms(0, 0, 0).naive_local();
 }
 
+#[allow(non_snake_case)]
+#[allow(dead_code)]
+pub mod USER {
+    pub mod ROLE {
+        pub const MEMBER: i8 = 0;
+        pub const MODERATOR: i8 = 1;           // 版主，可以管理话题
+        pub const ADMIN: i8 = 2;               // 管理员
+    }
+
+    pub mod STATUS {
+        pub const NORMAL: i8 = 0;
+        pub const BANNED: i8 = -1;             // 封禁，不能登录
+    }
+
+    // how new messages reach the user by mail, see base::notify
+    pub mod NOTIFY {
+        pub const DIGEST: i8 = 0;              // 每天汇总一封
+        pub const INSTANT: i8 = 1;             // 每条消息即时发送
+        pub const OFF: i8 = -1;                // 不发邮件
+
+        pub const ALL: [(i8, &'static str); 3] = [
+            (INSTANT, "即时提醒"),
+            (DIGEST, "每日汇总"),
+            (OFF, "不发邮件"),
+        ];
+    }
+}
+
 #[allow(non_snake_case)]
 #[allow(dead_code)]
 pub mod ARTICLE {
@@ -49,14 +77,63 @@ pub mod CATEGORY {
 
 }
 
+#[allow(non_snake_case)]
+#[allow(dead_code)]
+pub mod COMMENT {
+    pub mod STATUS {
+        pub const NORMAL: i8 = 0;
+        pub const DELETED: i8 = -1;            // 删除后留下占位
+    }
+}
+
+#[allow(non_snake_case)]
+pub mod ACCESS_TOKEN {
+    pub mod SCOPE {
+        pub const READ: u8 = 1;                // 读取消息等个人数据
+        pub const WRITE_ARTICLES: u8 = 2;      // 发表、编辑话题
+        pub const WRITE_COMMENTS: u8 = 4;      // 回帖
+
+        // form field and title of each scope
+        pub const ALL: [(u8, &'static str, &'static str); 3] = [
+            (READ, "scope_read", "读取"),
+            (WRITE_ARTICLES, "scope_write_articles", "发表话题"),
+            (WRITE_COMMENTS, "scope_write_comments", "回帖"),
+        ];
+    }
+
+    pub const MAX_COUNT: usize = 20;           // 每个用户最多令牌数
+    // last used time of a token is refreshed at most once per interval
+    pub const TOUCH_INTERVAL_MINUTES: i64 = 5;
+}
+
+#[allow(non_snake_case)]
+pub mod TAG {
+    pub const MAX_COUNT: usize = 5;            // 每个话题最多标签数
+    pub const MAX_LENGTH: usize = 32;          // 单个标签最大字数
+}
+
 pub const PAGE_SIZE: usize = 15;
 
+pub const PASSWORD_RESET_EXPIRE_HOURS: i64 = 2;
+
+pub const SESSION_EXPIRE_DAYS: i64 = 30;
+// last seen info of a session is refreshed at most once per interval
+pub const SESSION_TOUCH_INTERVAL_MINUTES: i64 = 5;
+
 #[allow(non_snake_case)]
 #[allow(dead_code)]
 pub mod MESSAGE {
     pub mod MODE {
         pub const REPLY_ARTICLE: i8 = 1;       // 文章下面回复
         pub const MENTION: i8 = 2;             // 在回复中提到某人
+        pub const REPLY_COMMENT: i8 = 3;       // 回复某条评论
+
+        // tab title of each mode in the notification center
+        pub const ALL: [(i8, &'static str); 3] = [
+            (REPLY_ARTICLE, "回复我的话题"),
+            (REPLY_COMMENT, "回复我的回帖"),
+            (MENTION, "提到我的"),
+        ];
     }
 
     pub mod STATUS {
@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fmt;
+use iron::prelude::*;
+use iron::{BeforeMiddleware, AfterMiddleware};
+use iron::typemap::Key;
+use iron::method::Method;
+use iron::status;
+use hyper::header::{Cookie as CookieHeader, SetCookie};
+use cookie::Cookie;
+use urlencoded::UrlEncodedBody;
+use persistent::Read;
+use hbsi::Template;
+use rustc_serialize::json::{Object, ToJson};
+use crypto::util::fixed_time_eq;
+use base::config::Config;
+use base::framework::json_error_response;
+use base::util;
+use base::access_token::BearerUser;
+
+const COOKIE_NAME: &'static str = "csrf_token";
+const FIELD_NAME: &'static str = "csrf_token";
+const HEADER_NAME: &'static str = "X-CSRF-Token";
+
+// Double submit cookie: every browser gets a random token in a cookie, and
+// every POST, PUT or DELETE must send the same token back in the
+// `X-CSRF-Token` header or in a `csrf_token` form field. Templates get it
+// through `ResponseData`. Logging in or out gives the browser a new token,
+// see `rotate_token`.
+pub struct Csrf;
+
+pub struct CsrfToken {
+    pub value: String,
+    is_new: bool,
+}
+
+impl Key for CsrfToken {
+    type Value = CsrfToken;
+}
+
+#[derive(Debug)]
+struct CsrfError;
+
+impl fmt::Display for CsrfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("csrf token mismatch")
+    }
+}
+
+impl Error for CsrfError {
+    fn description(&self) -> &str {
+        "csrf token mismatch"
+    }
+}
+
+impl BeforeMiddleware for Csrf {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let raw_token = req.headers.get::<CookieHeader>().and_then(|cookies| {
+            cookies.iter().find(|c| c.name == COOKIE_NAME).map(|c| c.value.clone())
+        });
+
+        let token = match raw_token {
+            Some(value) => CsrfToken { value: value, is_new: false },
+            None => CsrfToken { value: util::gen_random_token(32), is_new: true },
+        };
+
+        let is_new = token.is_new;
+        let expected = token.value.clone();
+        req.extensions.insert::<CsrfToken>(token);
+
+        match req.method {
+            Method::Get | Method::Head | Method::Options => return Ok(()),
+            _ => {}
+        }
+
+        // a request BearerAuth resolved is not authenticated by its cookies;
+        // the header alone proves nothing, the site's chain never checks it
+        if req.extensions.contains::<BearerUser>() {
+            return Ok(());
+        }
+
+        if is_unsubscribe_link(req) {
+            return Ok(());
+        }
+
+        // a freshly issued token can never have been submitted
+        if !is_new {
+            if let Some(submitted) = submitted_token(req) {
+                if fixed_time_eq(submitted.as_bytes(), expected.as_bytes()) {
+                    return Ok(());
+                }
+            }
+        }
+
+        warn!("csrf check failed: {} {}", req.method, req.url);
+        Err(csrf_failure(req))
+    }
+}
+
+impl AfterMiddleware for Csrf {
+    fn after(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        set_token_cookie(req, &mut resp);
+        Ok(resp)
+    }
+
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        set_token_cookie(req, &mut err.response);
+        Err(err)
+    }
+}
+
+// Replaces the browser's token from this response on, so that a token
+// somebody learned or planted before a login doesn't carry over into it.
+pub fn rotate_token(req: &mut Request) {
+    req.extensions.insert::<CsrfToken>(CsrfToken {
+        value: util::gen_random_token(32),
+        is_new: true,
+    });
+}
+
+// Unsubscribe links carry a secret of their own, and mail clients post to
+// them without any cookie, see base::notify.
+fn is_unsubscribe_link(req: &Request) -> bool {
+    let path = req.url.path();
+    path.len() == 4 && path[0] == "user" && path[2] == "unsubscribe"
+}
+
+fn submitted_token(req: &mut Request) -> Option<String> {
+    if let Some(raw) = req.headers.get_raw(HEADER_NAME) {
+        return String::from_utf8(raw[0].clone()).ok();
+    }
+
+    req.get::<UrlEncodedBody>().ok()
+        .and_then(|body| body.get(FIELD_NAME).and_then(|v| v.first().cloned()))
+}
+
+fn set_token_cookie(req: &mut Request, resp: &mut Response) {
+    let token = match req.extensions.get::<CsrfToken>() {
+        Some(token) if token.is_new => token.value.clone(),
+        _ => return,
+    };
+
+    let mut c = Cookie::new(COOKIE_NAME.to_owned(), token);
+    c.httponly = true;
+    c.path = Some("/".to_owned());
+
+    if let Some(set_cookie) = resp.headers.get_mut::<SetCookie>() {
+        set_cookie.push(c);
+        return;
+    }
+    resp.headers.set(SetCookie(vec![c]));
+}
+
+fn csrf_failure(req: &mut Request) -> IronError {
+    if req.headers.get_raw("X-Requested-With").is_some() {
+        return IronError {
+            error: Box::new(CsrfError),
+            response: json_error_response("页面已过期，请刷新后重试").unwrap(),
+        };
+    }
+
+    // runs before the handler, so only what the base template needs
+    let config = req.get::<Read<Config>>().unwrap();
+    let mut data = Object::new();
+    data.insert("static_path".to_owned(),
+                config.static_path.to_json());
+
+    let mut resp = Response::new();
+    resp.set_mut(Template::new("forbidden", data)).set_mut(status::Forbidden);
+    IronError { error: Box::new(CsrfError), response: resp }
+}
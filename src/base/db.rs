@@ -1,34 +1,88 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 use mysql as my;
-use iron::typemap::Key;
+use rusqlite;
 use base::config::Config;
 
-pub struct MyPool(my::Pool);
+// Where the site keeps its data, as chosen by `[database] backend` of
+// config.toml. Handlers never see this, they go through `repo::Repos`.
+#[derive(Clone)]
+pub enum Database {
+    Mysql(my::Pool),
+    // sqlite writes one at a time anyway, so the site shares one connection
+    Sqlite(Arc<Mutex<rusqlite::Connection>>),
+}
+
+impl Database {
+    pub fn connect(config: &Config) -> Result<Database, DbError> {
+        let db_config = &config.database;
 
-impl MyPool {
-    pub fn new(config: &Config) -> MyPool {
-        let conf_t = config.value();
-        let db_config = conf_t.get("database").unwrap().as_table().unwrap();
-        let user = db_config.get("user").unwrap().as_str().unwrap();
-        let password = db_config.get("password").unwrap().as_str().unwrap();
-        let host = db_config.get("host").unwrap().as_str().unwrap();
-        let name = db_config.get("name").unwrap().as_str().unwrap();
-        let port = db_config.get("port").unwrap().as_integer().unwrap();
+        if db_config.backend == "sqlite" {
+            let conn = try!(rusqlite::Connection::open(&db_config.path));
+            return Ok(Database::Sqlite(Arc::new(Mutex::new(conn))));
+        }
 
         let mut builder = my::OptsBuilder::default();
-        builder.user(Some(user))
-            .pass(Some(password))
-            .ip_or_hostname(Some(host))
-            .tcp_port(port as u16)
-            .db_name(Some(name));
-        let pool = my::Pool::new(builder).unwrap();
-        MyPool(pool)
+        builder.user(Some(&*db_config.user))
+            .pass(Some(&*db_config.password))
+            .ip_or_hostname(Some(&*db_config.host))
+            .tcp_port(db_config.port)
+            .db_name(Some(&*db_config.name));
+        Ok(Database::Mysql(try!(my::Pool::new(builder))))
+    }
+
+    // an empty sqlite database that is gone once the last clone is dropped
+    pub fn sqlite_memory() -> Database {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        Database::Sqlite(Arc::new(Mutex::new(conn)))
+    }
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    Mysql(my::Error),
+    Sqlite(rusqlite::Error),
+    // a stored value that can't be read back, e.g. a malformed time
+    Value(String),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DbError::Mysql(ref e) => write!(f, "{}", e),
+            DbError::Sqlite(ref e) => write!(f, "{}", e),
+            DbError::Value(ref e) => f.write_str(e),
+        }
+    }
+}
+
+impl Error for DbError {
+    fn description(&self) -> &str {
+        match *self {
+            DbError::Mysql(ref e) => e.description(),
+            DbError::Sqlite(ref e) => e.description(),
+            DbError::Value(_) => "bad value in database",
+        }
     }
+}
+
+impl From<my::Error> for DbError {
+    fn from(e: my::Error) -> DbError {
+        DbError::Mysql(e)
+    }
+}
 
-    pub fn value(&self) -> my::Pool {
-        self.0.clone()
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> DbError {
+        DbError::Sqlite(e)
     }
 }
 
-impl Key for MyPool {
-    type Value = MyPool;
+// the number a `SELECT count(...)` query returns
+pub fn count<A, T>(pool: &my::Pool, query: A, params: T) -> Result<usize, my::Error>
+    where A: AsRef<str>, T: Into<my::Params> {
+    // a count always comes back as exactly one row
+    let row = try!(pool.prep_exec(query, params)).next().unwrap();
+    row.map(my::from_row)
 }
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use iron::prelude::*;
+use iron::{status, AfterMiddleware, AroundMiddleware, Handler};
+use hbsi::Template;
+use rustc_serialize::json::ToJson;
+use base::db::DbError;
+use base::framework::{ResponseData, current_user, json_error_response};
+use base::util;
+
+// What a handler can fail with. Handlers use `app_try!` on anything that
+// converts into an AppError, and `ErrorPages` turns the resulting IronError
+// into a logged, rendered 404 or 500.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound,
+    Db(DbError),
+    Internal(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AppError::NotFound => write!(f, "not found"),
+            AppError::Db(ref e) => write!(f, "database error: {}", e),
+            AppError::Internal(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for AppError {
+    fn description(&self) -> &str {
+        match *self {
+            AppError::NotFound => "not found",
+            AppError::Db(_) => "database error",
+            AppError::Internal(_) => "internal error",
+        }
+    }
+}
+
+impl From<DbError> for AppError {
+    fn from(e: DbError) -> AppError {
+        AppError::Db(e)
+    }
+}
+
+impl From<AppError> for IronError {
+    fn from(e: AppError) -> IronError {
+        let status = match e {
+            AppError::NotFound => status::NotFound,
+            _ => status::InternalServerError,
+        };
+        IronError::new(e, status)
+    }
+}
+
+// Renders 404 and 500 errors of the site, as json for XHR calls. Other
+// errors (e.g. the csrf 403) already carry their own response.
+pub struct ErrorPages;
+
+impl AfterMiddleware for ErrorPages {
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        let (code, message) = match err.response.status {
+            Some(status::NotFound) => (404, "页面不存在"),
+            Some(status::InternalServerError) => (500, "服务器出错了，请稍后再试"),
+            _ => return Err(err),
+        };
+
+        if code == 500 {
+            log_error(req, &err);
+        }
+
+        // the response so far may carry cookies, e.g. a new csrf token
+        let mut resp = err.response;
+        if req.headers.get_raw("X-Requested-With").is_some() {
+            // like any other refused ajax call, so the page shows the message
+            let json = json_error_response(message).unwrap();
+            resp.set_mut(mime!(Application/Json));
+            resp.body = json.body;
+            resp.status = json.status;
+        } else {
+            let mut data = ResponseData::new(req);
+            data.insert("code", code.to_json());
+            data.insert("message", message.to_json());
+            resp.set_mut(Template::new("error", &data));
+        }
+        Ok(resp)
+    }
+}
+
+// 5xx come with whatever is known about the request, for the api too
+pub fn log_error(req: &mut Request, err: &IronError) {
+    let user = current_user(req)
+        .map(|u| format!("{}({})", u.username, u.id))
+        .unwrap_or("-".to_owned());
+    error!("{} {} failed, user: {}, ip: {}: {}",
+           req.method, req.url, user, util::client_ip(req), err.error);
+}
+
+// Last line of defence for the `unwrap()`s that are left: a panicking
+// handler becomes a 500 instead of a dropped connection.
+pub struct CatchPanic;
+
+impl AroundMiddleware for CatchPanic {
+    fn around(self, handler: Box<Handler>) -> Box<Handler> {
+        Box::new(move |req: &mut Request| -> IronResult<Response> {
+            match panic::catch_unwind(AssertUnwindSafe(|| handler.handle(req))) {
+                Ok(result) => result,
+                Err(cause) => {
+                    let message = cause.downcast_ref::<String>().cloned()
+                        .or(cause.downcast_ref::<&str>().map(|s| (*s).to_owned()))
+                        .unwrap_or("unknown panic".to_owned());
+                    Err(AppError::Internal(format!("handler panicked: {}", message)).into())
+                }
+            }
+        })
+    }
+}
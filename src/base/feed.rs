@@ -0,0 +1,223 @@
+use std::cmp;
+use std::collections::HashMap;
+use iron::prelude::*;
+use iron::status;
+use iron::mime::Mime;
+use iron::typemap::Key;
+use persistent::State;
+use hyper::header::{ETag, EntityTag, LastModified, IfNoneMatch, IfModifiedSince, HttpDate};
+use rss::{Channel, Item, Rss, Guid};
+use rustc_serialize::json::{Object, Json, ToJson, encode};
+use chrono::*;
+use time;
+use base::util::{self, render_html};
+
+// One feed, written out as RSS 2.0, Atom 1.0 or JSON Feed 1.0. Entries are
+// always built through `Entry::new`, so every format gets the same html.
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    // the html page the feed is about
+    pub link: String,
+    // where the feed itself is served
+    pub feed_url: String,
+    pub entries: Vec<Entry>,
+}
+
+pub struct Entry {
+    title: String,
+    link: String,
+    author: String,
+    content_html: String,
+    published: NaiveDateTime,
+    updated: NaiveDateTime,
+}
+
+// The body hash of each feed as it was last served and since when, by feed
+// url. Times in a feed don't move when an entry is deleted, so
+// Last-Modified comes from here instead.
+pub struct FeedVersions;
+
+impl Key for FeedVersions {
+    type Value = HashMap<String, (String, NaiveDateTime)>;
+}
+
+pub enum Format {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl Entry {
+    pub fn new(title: &str, link: &str, author: &str, markdown: &str,
+               published: NaiveDateTime, updated: NaiveDateTime) -> Entry {
+        Entry {
+            title: util::safe_xml(title),
+            link: link.to_owned(),
+            author: util::safe_xml(author),
+            content_html: util::safe_xml(&render_html(markdown)),
+            published: published,
+            updated: updated,
+        }
+    }
+}
+
+impl Feed {
+    fn updated(&self) -> Option<NaiveDateTime> {
+        self.entries.iter().map(|e| e.updated).max()
+    }
+
+    pub fn to_rss(&self) -> String {
+        let items: Vec<Item> = self.entries.iter().map(|entry| {
+            Item {
+                title: Some(entry.title.clone()),
+                pub_date: Some(with_tz(entry.published).to_rfc2822()),
+                description: Some(entry.content_html.clone()),
+                author: Some(entry.author.clone()),
+                link: Some(entry.link.clone()),
+                guid: Some(Guid{is_perma_link: true, value: entry.link.clone()}),
+                ..Default::default()
+            }
+        }).collect();
+
+        let channel = Channel {
+            title: util::safe_xml(&self.title),
+            link: self.link.clone(),
+            description: util::safe_xml(&self.description),
+            language: Some(String::from("zh-cn")),
+            items: items,
+            ..Default::default()
+        };
+
+        Rss(channel).to_string()
+    }
+
+    pub fn to_atom(&self) -> String {
+        let updated = self.updated().unwrap_or(Local::now().naive_local());
+
+        let mut s = String::new();
+        s.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        s.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"zh-cn\">\n");
+        s.push_str(&format!("<title>{}</title>\n", escape(&util::safe_xml(&self.title))));
+        s.push_str(&format!("<subtitle>{}</subtitle>\n",
+                            escape(&util::safe_xml(&self.description))));
+        s.push_str(&format!("<id>{}</id>\n", escape(&self.feed_url)));
+        s.push_str(&format!("<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+                            escape(&self.link)));
+        s.push_str(&format!("<link rel=\"self\" href=\"{}\"/>\n", escape(&self.feed_url)));
+        s.push_str(&format!("<updated>{}</updated>\n", with_tz(updated).to_rfc3339()));
+
+        for entry in &self.entries {
+            s.push_str("<entry>\n");
+            s.push_str(&format!("<title>{}</title>\n", escape(&entry.title)));
+            s.push_str(&format!("<id>{}</id>\n", escape(&entry.link)));
+            s.push_str(&format!("<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+                                escape(&entry.link)));
+            s.push_str(&format!("<author><name>{}</name></author>\n", escape(&entry.author)));
+            s.push_str(&format!("<published>{}</published>\n",
+                                with_tz(entry.published).to_rfc3339()));
+            s.push_str(&format!("<updated>{}</updated>\n", with_tz(entry.updated).to_rfc3339()));
+            s.push_str(&format!("<content type=\"html\">{}</content>\n",
+                                escape(&entry.content_html)));
+            s.push_str("</entry>\n");
+        }
+
+        s.push_str("</feed>\n");
+        s
+    }
+
+    pub fn to_json_feed(&self) -> String {
+        let items: Vec<Json> = self.entries.iter().map(|entry| {
+            let mut author = Object::new();
+            author.insert("name".to_owned(), entry.author.to_json());
+
+            let mut object = Object::new();
+            object.insert("id".to_owned(), entry.link.to_json());
+            object.insert("url".to_owned(), entry.link.to_json());
+            object.insert("title".to_owned(), entry.title.to_json());
+            object.insert("content_html".to_owned(), entry.content_html.to_json());
+            object.insert("author".to_owned(), author.to_json());
+            object.insert("date_published".to_owned(),
+                          with_tz(entry.published).to_rfc3339().to_json());
+            object.insert("date_modified".to_owned(),
+                          with_tz(entry.updated).to_rfc3339().to_json());
+            object.to_json()
+        }).collect();
+
+        let mut object = Object::new();
+        object.insert("version".to_owned(), "https://jsonfeed.org/version/1".to_json());
+        object.insert("title".to_owned(), self.title.to_json());
+        object.insert("description".to_owned(), self.description.to_json());
+        object.insert("home_page_url".to_owned(), self.link.to_json());
+        object.insert("feed_url".to_owned(), self.feed_url.to_json());
+        object.insert("items".to_owned(), items.to_json());
+        encode(&object).unwrap()
+    }
+}
+
+// Renders the feed, or answers 304 when the client's copy is still fresh.
+pub fn feed_response(req: &mut Request, feed: &Feed, format: Format) -> IronResult<Response> {
+    let body = match format {
+        Format::Rss => feed.to_rss(),
+        Format::Atom => feed.to_atom(),
+        Format::Json => feed.to_json_feed(),
+    };
+
+    let hash = util::sha256_hex(&body);
+    let modified = {
+        let versions = req.get::<State<FeedVersions>>().unwrap();
+        let mut versions = versions.write().unwrap();
+        let now = Local::now().naive_local();
+        let version = versions.entry(feed.feed_url.clone()).or_insert((hash.clone(), now));
+        if version.0 != hash {
+            // If-Modified-Since only has seconds, a change must move it by one
+            *version = (hash.clone(), cmp::max(now, version.1 + Duration::seconds(1)));
+        }
+        version.1
+    };
+    let etag = EntityTag::new(false, hash);
+    let last_modified = HttpDate(
+        time::at_utc(time::Timespec::new(with_tz(modified).timestamp(), 0)));
+
+    // If-None-Match wins over If-Modified-Since when both are sent
+    let not_modified = match req.headers.get::<IfNoneMatch>() {
+        Some(&IfNoneMatch::Any) => true,
+        Some(&IfNoneMatch::Items(ref tags)) => tags.iter().any(|t| t.weak_eq(&etag)),
+        None => match req.headers.get::<IfModifiedSince>() {
+            Some(&IfModifiedSince(ref since)) => {
+                last_modified.0.to_timespec() <= since.0.to_timespec()
+            }
+            None => false,
+        },
+    };
+
+    let mut resp = Response::new();
+    resp.headers.set(ETag(etag));
+    resp.headers.set(LastModified(last_modified));
+
+    if not_modified {
+        resp.set_mut(status::NotModified);
+        return Ok(resp);
+    }
+
+    match format {
+        Format::Rss => resp.set_mut(mime!(Application/Xml)),
+        Format::Atom => resp.set_mut("application/atom+xml".parse::<Mime>().unwrap()),
+        Format::Json => resp.set_mut(mime!(Application/Json)),
+    };
+    resp.set_mut(body).set_mut(status::Ok);
+    Ok(resp)
+}
+
+// times are stored in the server's local time zone
+fn with_tz(t: NaiveDateTime) -> DateTime<Local> {
+    let offset = Local::now().offset().clone();
+    DateTime::<Local>::from_utc(t - offset.local_minus_utc(), offset)
+}
+
+fn escape(s: &str) -> String {
+    s.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+}
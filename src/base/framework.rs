@@ -1,18 +1,23 @@
 use rustc_serialize::json::{Object, Json, ToJson, encode};
-use base::config::Config;
-use base::db::MyPool;
+use base::config::{Config, SiteConfig};
+use base::db::DbError;
 use iron::prelude::*;
-use persistent::Read;
+use persistent::{Read, State};
 use hbsi::Template;
 use iron::status;
-use mysql as my;
 use iron_login::User;
 use iron::Url;
 use iron::modifiers::Redirect;
 use iron::Handler;
 use iron::modifiers::Header;
 use hyper::header::Connection;
-use router::NoRoute;
+use base::session;
+use base::util;
+use base::csrf::CsrfToken;
+use base::access_token::BearerUser;
+use base::constant;
+use base::error::AppError;
+use repo::Repos;
 
 pub struct ResponseData(Object);
 
@@ -21,7 +26,8 @@ impl ResponseData {
         let config = req.get::<Read<Config>>().unwrap();
         let mut data = Object::new();
         data.insert("static_path".to_string(),
-                    config.get("static_path").as_str().unwrap().to_string().to_json());
+                    config.static_path.to_json());
+        data.insert("site".to_owned(), site_config(req).to_json());
         let login = LoginUser::get_login(req);
         let raw_user = login.get_user();
         let mut login_user = Json::Null;
@@ -29,6 +35,9 @@ impl ResponseData {
             login_user = login_u.to_json();
         }
         data.insert("login_user".to_owned(), login_user);
+        if let Some(token) = req.extensions.get::<CsrfToken>() {
+            data.insert("csrf_token".to_owned(), token.value.to_json());
+        }
         ResponseData(data)
     }
 
@@ -86,7 +95,35 @@ pub fn temp_response(temp_name: &str, data: &ResponseData) -> IronResult<Respons
 }
 
 pub fn not_found_response() -> IronResult<Response> {
-    Err(IronError::new(NoRoute, status::NotFound))
+    Err(AppError::NotFound.into())
+}
+
+// why writing an article or a comment was refused; the web handlers and
+// the api turn it into their own kind of response
+#[derive(Debug)]
+pub enum ActionError {
+    NotFound,
+    Forbidden,
+    Invalid(String),
+    // not the user's fault, handlers pass it on as an `AppError`
+    Db(DbError),
+}
+
+impl ActionError {
+    pub fn message(&self) -> String {
+        match *self {
+            ActionError::NotFound => "内容不存在".to_owned(),
+            ActionError::Forbidden => "非法请求".to_owned(),
+            ActionError::Invalid(ref message) => message.clone(),
+            ActionError::Db(_) => "服务器出错了，请稍后再试".to_owned(),
+        }
+    }
+}
+
+impl From<DbError> for ActionError {
+    fn from(e: DbError) -> ActionError {
+        ActionError::Db(e)
+    }
 }
 
 pub fn json_response(status: JsonStatus, message: &str, data: Object, redirect_url: &str) -> IronResult<Response> {
@@ -118,17 +155,38 @@ pub fn json_redirect_response(redirect_url: &str) -> IronResult<Response> {
 pub struct LoginUser {
     pub id: u64,
     pub username: String,
-    pub email: String
+    pub email: String,
+    pub role: i8,
+    pub session_id: u64,
+    pub session_token: String,
+    // None when logged in through the browser, which may do anything
+    pub scopes: Option<u8>,
 }
 
 impl LoginUser {
-    pub fn new(user_id: u64, username: &str, email: &str) -> LoginUser {
+    pub fn new(user_id: u64, username: &str, email: &str, role: i8,
+               session_id: u64, session_token: &str) -> LoginUser {
         LoginUser{
             id: user_id,
             username: username.to_owned(),
-            email: email.to_owned()
+            email: email.to_owned(),
+            role: role,
+            session_id: session_id,
+            session_token: session_token.to_owned(),
+            scopes: None,
+        }
+    }
+
+    pub fn has_scope(&self, scope: u8) -> bool {
+        match self.scopes {
+            Some(scopes) => scopes & scope == scope,
+            None => true,
         }
     }
+
+    pub fn is_moderator(&self) -> bool {
+        self.role >= constant::USER::ROLE::MODERATOR
+    }
 }
 
 impl ToJson for LoginUser {
@@ -137,37 +195,72 @@ impl ToJson for LoginUser {
         object.insert("id".to_owned(), self.id.to_json());
         object.insert("username".to_owned(), self.username.to_json());
         object.insert("email".to_owned(), self.email.to_json());
+        object.insert("is_moderator".to_owned(), self.is_moderator().to_json());
         object.to_json()
     }
 }
 
 impl User for LoginUser {
-    fn from_user_id(req: &mut Request, user_id: &str) -> Option<LoginUser> {
-        let user_id = match user_id.parse::<u64>() {
-            Ok(u) => u,
-            _ => return None,
+    // the cookie holds a session token, or `github:<id>` while a github
+    // user is not yet bound to a local one.
+    fn from_user_id(req: &mut Request, session_token: &str) -> Option<LoginUser> {
+        if session_token.starts_with("github:") {
+            return None;
+        }
+        let repos = req.get::<Read<Repos>>().unwrap();
+        // a failing database is treated as logged out rather than failing
+        // every page, the error pages included
+        let (session_id, user_id) = match session::touch(
+            &repos, session_token, &util::client_ip(req), &util::user_agent(req)) {
+            Ok(Some(s)) => s,
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("load session failed: {}", e);
+                return None;
+            }
         };
-        let pool = req.get::<Read<MyPool>>().unwrap().value();
-        let mut result = pool.prep_exec("SELECT id, username, email from user where id=?", (&user_id,)).unwrap();
-        let row = result.next().unwrap().unwrap();
-        let (id, username, email) = my::from_row::<(u64, String, String)>(row);
-        Some(LoginUser::new(id, &username, &email))
+        let user = match repos.users.find(user_id) {
+            Ok(Some(user)) => user,
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("load user {} failed: {}", user_id, e);
+                return None;
+            }
+        };
+        if user.status != constant::USER::STATUS::NORMAL {
+            return None;
+        }
+        Some(LoginUser::new(user.id, &user.username, &user.email, user.role,
+                            session_id, session_token))
     }
 
     fn get_user_id(&self) -> String {
-        self.id.to_string()
+        self.session_token.clone()
     }
 }
 
+// the current `[site]` settings, which may be reloaded at any time
+pub fn site_config(req: &mut Request) -> SiteConfig {
+    req.get::<State<SiteConfig>>().unwrap().read().unwrap().clone()
+}
+
+// the owner of the request's access token, or else the user logged in
+// through the browser
+pub fn current_user(req: &mut Request) -> Option<LoginUser> {
+    if let Some(user) = req.extensions.get::<BearerUser>() {
+        return Some(user.clone());
+    }
+    LoginUser::get_login(req).get_user()
+}
+
 pub fn user_required<F>(handler: F) -> Box<Handler>
     where F: Send + Sync + 'static + Fn(&mut Request) -> IronResult<Response> {
 
     let new_fn = move |req: &mut Request| -> IronResult<Response> {
-        let login = LoginUser::get_login(req);
-        let user = login.get_user();
+        let user = current_user(req);
         if user.is_none() {
             let config = req.get::<Read<Config>>().unwrap();
-            let app_path = config.get("app_path").as_str().unwrap().to_owned();
+            let app_path = config.app_path.clone();
             let url_str = app_path + "/user/login";
             if req.headers.get_raw("X-Requested-With").is_some() {
                 let mut resp = json_redirect_response(&url_str).unwrap();
@@ -182,3 +275,24 @@ pub fn user_required<F>(handler: F) -> Box<Handler>
 
     Box::new(new_fn)
 }
+
+// only moderators and admins may pass, everyone else gets a 404
+pub fn admin_required<F>(handler: F) -> Box<Handler>
+    where F: Send + Sync + 'static + Fn(&mut Request) -> IronResult<Response> {
+
+    user_required(move |req: &mut Request| -> IronResult<Response> {
+        let user = current_user(req).unwrap();
+        if !user.is_moderator() {
+            if req.headers.get_raw("X-Requested-With").is_some() {
+                return json_error_response("非法请求");
+            }
+            let mut data = ResponseData::new(req);
+            data.insert("code", 403.to_json());
+            data.insert("message", "没有权限访问此页面".to_json());
+            let mut resp = try!(temp_response("error", &data));
+            resp.set_mut(status::Forbidden);
+            return Ok(resp);
+        }
+        handler(req)
+    })
+}
@@ -0,0 +1,39 @@
+use std::io::Read;
+use std::time::Duration;
+use hyper::Client;
+use hyper::header::UserAgent;
+use hyper::status::StatusCode;
+
+// What background jobs need from an http client. It is a trait so that
+// they can be pointed at a fake in tests instead of the real internet.
+pub trait HttpClient: Send + Sync {
+    fn get(&self, url: &str) -> Result<String, String>;
+}
+
+pub struct HyperClient(Client);
+
+impl HyperClient {
+    pub fn new() -> HyperClient {
+        let mut client = Client::new();
+        client.set_read_timeout(Some(Duration::from_secs(30)));
+        client.set_write_timeout(Some(Duration::from_secs(30)));
+        HyperClient(client)
+    }
+}
+
+impl HttpClient for HyperClient {
+    fn get(&self, url: &str) -> Result<String, String> {
+        let mut res = try!(self.0.get(url)
+                           .header(UserAgent("rust-lang-cn".to_owned()))
+                           .send()
+                           .map_err(|e| format!("GET {}: {}", url, e)));
+
+        if res.status != StatusCode::Ok {
+            return Err(format!("GET {}: {}", url, res.status));
+        }
+
+        let mut body = String::new();
+        try!(res.read_to_string(&mut body).map_err(|e| format!("GET {}: {}", url, e)));
+        Ok(body)
+    }
+}
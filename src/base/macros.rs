@@ -11,3 +11,14 @@ macro_rules! m_hashmap {
         }
     }
 }
+
+// `try!` for handlers: the error becomes an `AppError`, which `ErrorPages`
+// logs and renders as a 500 (or a 404 for `AppError::NotFound`).
+macro_rules! app_try {
+    ($expr:expr) => {
+        match $expr {
+            Ok(val) => val,
+            Err(e) => return Err(::base::error::AppError::from(e).into()),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use iron::typemap::Key;
+use chrono::*;
+use rand::{self, Rng};
+use rustc_serialize::base64::{ToBase64, MIME, STANDARD};
+use base::config::Config;
+use base::smtp::{self, Relay};
+
+#[derive(Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+    // (name, value) of headers besides From, To and Subject
+    pub headers: Vec<(String, String)>,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+pub struct SmtpMailer {
+    host: String,
+    port: u16,
+    tls: bool,
+    user: String,
+    password: String,
+    from: String,
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        let relay = Relay {
+            host: &self.host,
+            port: self.port,
+            tls: self.tls,
+            user: &self.user,
+            password: &self.password,
+        };
+        smtp::send(&relay, address(&self.from), address(&mail.to), &message(&self.from, mail))
+    }
+}
+
+// the mail with all its headers, as it goes to the relay; the body is
+// base64 so that no line is too long or too far from ascii
+fn message(from: &str, mail: &Mail) -> String {
+    let domain = address(from).rsplit('@').next().unwrap_or("localhost");
+    let id = rand::thread_rng().gen_ascii_chars().take(24).collect::<String>();
+
+    let mut headers = vec![
+        ("From".to_owned(), encode_name(from)),
+        ("To".to_owned(), mail.to.clone()),
+        ("Subject".to_owned(), encode_word(&mail.subject)),
+        ("Date".to_owned(), Local::now().to_rfc2822()),
+        ("Message-ID".to_owned(), format!("<{}@{}>", id, domain)),
+        ("MIME-Version".to_owned(), "1.0".to_owned()),
+        ("Content-Type".to_owned(), "text/plain; charset=utf-8".to_owned()),
+        ("Content-Transfer-Encoding".to_owned(), "base64".to_owned()),
+    ];
+    headers.extend(mail.headers.iter().cloned());
+
+    let mut message = String::new();
+    for (name, value) in headers {
+        // a value can't start a header of its own
+        let value = value.replace('\r', " ").replace('\n', " ");
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    message.push_str("\r\n");
+    message.push_str(&mail.body.as_bytes().to_base64(MIME));
+    message.push_str("\r\n");
+    message
+}
+
+// "Rust China <noreply@rust.cc>" -> "noreply@rust.cc"
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+// the display name of a mailbox as an encoded word when it isn't ascii
+fn encode_name(mailbox: &str) -> String {
+    match mailbox.rfind('<') {
+        Some(start) => format!("{} {}", encode_word(mailbox[..start].trim()), &mailbox[start..]),
+        None => mailbox.to_owned(),
+    }
+}
+
+// RFC 2047, for header text that isn't ascii
+fn encode_word(text: &str) -> String {
+    if text.bytes().all(|b| b < 128) {
+        return text.to_owned();
+    }
+    format!("=?UTF-8?B?{}?=", text.as_bytes().to_base64(STANDARD))
+}
+
+// Writes every mail into a directory instead of sending it, for local
+// development and tests.
+pub struct FileMailer {
+    dir: PathBuf,
+    from: String,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        try!(fs::create_dir_all(&self.dir).map_err(|e| e.to_string()));
+
+        let name = format!("{}-{}.eml",
+                           Local::now().format("%Y%m%d%H%M%S"),
+                           rand::thread_rng().gen_ascii_chars()
+                           .take(8).collect::<String>());
+        let mut file = try!(File::create(self.dir.join(name))
+                            .map_err(|e| e.to_string()));
+
+        let mut content = format!("From: {}\r\nTo: {}\r\nSubject: {}\r\n",
+                                  self.from, mail.to, mail.subject);
+        for &(ref name, ref value) in &mail.headers {
+            content.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        content.push_str(&format!("\r\n{}\r\n", mail.body));
+        try!(file.write_all(content.as_bytes()).map_err(|e| e.to_string()));
+        Ok(())
+    }
+}
+
+// The transport in use, shared by the site and the notify worker. Tests
+// hand in their own through `with` to see what would have been sent.
+#[derive(Clone)]
+pub struct MyMailer(Arc<Mailer>);
+
+impl MyMailer {
+    pub fn new(config: &Config) -> MyMailer {
+        let mail_config = &config.mail;
+        let from = mail_config.from.clone();
+
+        // the transport is checked when the config is loaded
+        let mailer: Arc<Mailer> = match &*mail_config.transport {
+            "smtp" => Arc::new(SmtpMailer {
+                host: mail_config.smtp_host.clone(),
+                port: mail_config.smtp_port,
+                tls: mail_config.smtp_tls,
+                user: mail_config.smtp_user.clone(),
+                password: mail_config.smtp_password.clone(),
+                from: from,
+            }),
+            _ => Arc::new(FileMailer {
+                dir: PathBuf::from(&mail_config.file_dir),
+                from: from,
+            }),
+        };
+
+        MyMailer::with(mailer)
+    }
+
+    pub fn with(mailer: Arc<Mailer>) -> MyMailer {
+        MyMailer(mailer)
+    }
+
+    pub fn send(&self, mail: &Mail) -> Result<(), String> {
+        self.0.send(mail)
+    }
+}
+
+impl Key for MyMailer {
+    type Value = MyMailer;
+}
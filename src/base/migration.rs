@@ -0,0 +1,176 @@
+use chrono::*;
+use mysql as my;
+use rusqlite;
+use base::db::Database;
+
+// Schema changes on top of tables.sql, applied in order by `rust_lang_cn
+// migrate` and recorded in `schema_version`. Append new ones at the end and
+// never edit one that has been released. Each has a MySQL and a SQLite
+// version; the SQLite one is empty up to SQLITE_BASE_VERSION.
+const MIGRATIONS: &'static [(u32, &'static str, &'static str, &'static str)] = &[
+    (1, "github_user", include_str!("../../migrations/0001_github_user.sql"), ""),
+    (2, "password_hash", include_str!("../../migrations/0002_password_hash.sql"), ""),
+    (3, "password_reset", include_str!("../../migrations/0003_password_reset.sql"), ""),
+    (4, "session", include_str!("../../migrations/0004_session.sql"), ""),
+    (5, "user_role", include_str!("../../migrations/0005_user_role.sql"), ""),
+    (6, "comment_parent", include_str!("../../migrations/0006_comment_parent.sql"), ""),
+    (7, "comment_revision", include_str!("../../migrations/0007_comment_revision.sql"), ""),
+    (8, "fulltext_search", include_str!("../../migrations/0008_fulltext_search.sql"), ""),
+    (9, "tag", include_str!("../../migrations/0009_tag.sql"), ""),
+    (10, "access_token", include_str!("../../migrations/0010_access_token.sql"), ""),
+    (11, "news", include_str!("../../migrations/0011_news.sql"), ""),
+    (12, "user_status", include_str!("../../migrations/0012_user_status.sql"), ""),
+    (13, "mail_notify", include_str!("../../migrations/0013_mail_notify.sql"),
+     include_str!("../../migrations/sqlite/0013_mail_notify.sql")),
+];
+
+// tables.sqlite.sql already has every migration up to this one
+const SQLITE_BASE_VERSION: u32 = 12;
+
+// time format of the sqlite backend, see tables.sqlite.sql
+const TIME_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.0).unwrap_or(0)
+}
+
+pub fn current_version(db: &Database) -> Result<u32, String> {
+    try!(ensure_version_table(db));
+    match *db {
+        Database::Mysql(ref pool) => {
+            let row = try!(pool.prep_exec("SELECT COALESCE(MAX(version), 0) from schema_version",
+                                          ())
+                           .map_err(|e| e.to_string()))
+                .next().unwrap().unwrap();
+            Ok(my::from_row(row))
+        },
+        Database::Sqlite(ref conn) => {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            conn.query_row_and_then("SELECT COALESCE(MAX(version), 0) from schema_version", &[],
+                                    |row| row.get_checked(0).map(|version: i64| version as u32))
+                .map_err(|e: rusqlite::Error| e.to_string())
+        },
+    }
+}
+
+// Applies every pending migration and returns the versions applied.
+pub fn run(db: &Database) -> Result<Vec<u32>, String> {
+    let current = try!(current_version(db));
+    match *db {
+        Database::Mysql(ref pool) => run_mysql(pool, current),
+        Database::Sqlite(ref conn) => {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            run_sqlite(&mut conn, current)
+        },
+    }
+}
+
+fn run_mysql(pool: &my::Pool, current: u32) -> Result<Vec<u32>, String> {
+    let mut applied = Vec::new();
+
+    for &(version, name, sql, _) in MIGRATIONS.iter().filter(|m| m.0 > current) {
+        // mysql commits DDL implicitly, so a failed migration is fixed by
+        // hand and then run again
+        for statement in statements(sql) {
+            try!(pool.prep_exec(statement, ())
+                 .map_err(|e| format!("migration {} ({}) failed: {}", version, name, e)));
+        }
+        try!(pool.prep_exec("INSERT INTO schema_version(version, name, apply_time) \
+                             VALUES (?, ?, ?)",
+                            (version, name, Local::now().naive_local()))
+             .map_err(|e| e.to_string()));
+        info!("applied migration {} ({})", version, name);
+        applied.push(version);
+    }
+
+    Ok(applied)
+}
+
+// sqlite has transactional DDL, so each migration applies entirely or not at all
+fn run_sqlite(conn: &mut rusqlite::Connection, current: u32) -> Result<Vec<u32>, String> {
+    let mut applied = Vec::new();
+
+    // an empty database starts from the whole base schema
+    if current == 0 {
+        let trans = try!(conn.transaction().map_err(|e| e.to_string()));
+        try!(trans.execute_batch(include_str!("../../tables.sqlite.sql"))
+             .map_err(|e| format!("creating the sqlite tables failed: {}", e)));
+        for &(version, name, _, _) in MIGRATIONS.iter().filter(|m| m.0 <= SQLITE_BASE_VERSION) {
+            try!(record_sqlite(&trans, version, name));
+            applied.push(version);
+        }
+        try!(trans.commit().map_err(|e| e.to_string()));
+        info!("created the sqlite tables at version {}", SQLITE_BASE_VERSION);
+    }
+
+    let current = if current == 0 { SQLITE_BASE_VERSION } else { current };
+    for &(version, name, _, sql) in MIGRATIONS.iter().filter(|m| m.0 > current) {
+        let trans = try!(conn.transaction().map_err(|e| e.to_string()));
+        try!(trans.execute_batch(sql)
+             .map_err(|e| format!("migration {} ({}) failed: {}", version, name, e)));
+        try!(record_sqlite(&trans, version, name));
+        try!(trans.commit().map_err(|e| e.to_string()));
+        info!("applied migration {} ({})", version, name);
+        applied.push(version);
+    }
+
+    Ok(applied)
+}
+
+fn record_sqlite(conn: &rusqlite::Connection, version: u32, name: &str) -> Result<(), String> {
+    let now = Local::now().naive_local().format(TIME_FORMAT).to_string();
+    conn.execute("INSERT INTO schema_version(version, name, apply_time) VALUES (?, ?, ?)",
+                 &[&(version as i64), &name, &now])
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// The server refuses to start until `migrate` has been run.
+pub fn check(db: &Database) -> Result<(), String> {
+    let current = try!(current_version(db));
+    let latest = latest_version();
+    if current < latest {
+        return Err(format!("database schema is at version {}, but {} is required; \
+                            run `rust_lang_cn migrate` first", current, latest));
+    }
+    if current > latest {
+        warn!("database schema version {} is newer than this binary ({})", current, latest);
+    }
+    Ok(())
+}
+
+fn ensure_version_table(db: &Database) -> Result<(), String> {
+    match *db {
+        Database::Mysql(ref pool) => {
+            pool.prep_exec("CREATE TABLE IF NOT EXISTS `schema_version` (\
+                            `version` int(11) NOT NULL, \
+                            `name` varchar(64) COLLATE utf8mb4_unicode_ci NOT NULL, \
+                            `apply_time` datetime NOT NULL, \
+                            PRIMARY KEY (`version`)\
+                            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 \
+                            COLLATE=utf8mb4_unicode_ci", ())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        },
+        Database::Sqlite(ref conn) => {
+            conn.lock().unwrap_or_else(|e| e.into_inner())
+                .execute_batch("CREATE TABLE IF NOT EXISTS schema_version (\
+                                version INTEGER PRIMARY KEY, \
+                                name TEXT NOT NULL, \
+                                apply_time TEXT NOT NULL)")
+                .map_err(|e| e.to_string())
+        },
+    }
+}
+
+// statements end with a semicolon at the end of a line; `--` comments are dropped
+pub fn statements(sql: &str) -> Vec<String> {
+    let sql: Vec<&str> = sql.lines()
+        .filter(|line| !line.trim_left().starts_with("--"))
+        .collect();
+    sql.join("\n")
+        .split(";\n")
+        .map(|s| s.trim().trim_right_matches(';').trim().to_owned())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
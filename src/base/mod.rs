@@ -1,8 +1,21 @@
 #[macro_use]
 pub mod macros;
 pub mod framework;
+pub mod error;
 pub mod config;
 pub mod db;
 pub mod model;
 pub mod util;
 pub mod constant;
+pub mod password;
+pub mod mail;
+pub mod smtp;
+pub mod session;
+pub mod csrf;
+pub mod access_token;
+pub mod feed;
+pub mod http;
+pub mod news;
+pub mod migration;
+pub mod reload;
+pub mod notify;
@@ -2,11 +2,16 @@ use chrono::*;
 use rustc_serialize::json::{Object, Json, ToJson};
 use base::constant;
 
+#[derive(Clone)]
 pub struct User {
     pub id: u64,
     pub username: String,
     pub email: String,
     pub avatar: String,
+    pub role: i8,
+    pub status: i8,
+    // a constant::USER::NOTIFY
+    pub notify: i8,
     pub create_time: NaiveDateTime,
 }
 
@@ -17,6 +22,9 @@ impl Default for User {
             username: Default::default(),
             email: Default::default(),
             avatar: Default::default(),
+            role: constant::USER::ROLE::MEMBER,
+            status: constant::USER::STATUS::NORMAL,
+            notify: constant::USER::NOTIFY::OFF,
             create_time: *constant::DEFAULT_DATETIME
         }
     }
@@ -34,6 +42,7 @@ impl ToJson for User {
     }
 }
 
+#[derive(Clone)]
 pub struct Article {
     pub id: u64,
     pub category: Category,
@@ -45,6 +54,9 @@ pub struct Article {
     pub update_time: NaiveDateTime,
     pub comments: Vec<Comment>,
     pub flag: u8,
+    pub priority: i8,
+    pub status: i8,
+    pub tags: Vec<String>,
 }
 
 impl Default for Article {
@@ -60,6 +72,9 @@ impl Default for Article {
             update_time: *constant::DEFAULT_DATETIME,
             comments: Default::default(),
             flag: Default::default(),
+            priority: Default::default(),
+            status: constant::ARTICLE::STATUS::NORMAL,
+            tags: Default::default(),
         }
     }
 }
@@ -82,16 +97,50 @@ impl ToJson for Article {
         object.insert("is_essence".to_owned(),
                       (self.flag & constant::ARTICLE::FLAG::ESSENCE > 0).to_json());
         object.insert("comments".to_owned(), self.comments.to_json());
+        object.insert("tags".to_owned(), self.tags.to_json());
         object.to_json()
     }
 }
 
+// the fields posted to create or edit an article, from the web form or the api
+pub struct ArticleForm {
+    pub category: i64,
+    pub title: String,
+    pub content: String,
+    // None keeps the tags an article has
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Clone)]
 pub struct Comment {
     pub id: u64,
     pub user: User,
     pub content: String,
+    pub status: i8,
     pub create_time: NaiveDateTime,
+    pub update_time: Option<NaiveDateTime>,
     pub article: Option<Article>,
+    pub parent: Option<Box<Comment>>,
+    // markdown to prefill the edit form, only set when it may be edited
+    pub source: Option<String>,
+    pub can_delete: bool,
+}
+
+impl Default for Comment {
+    fn default() -> Comment {
+        Comment {
+            id: Default::default(),
+            user: Default::default(),
+            content: Default::default(),
+            status: constant::COMMENT::STATUS::NORMAL,
+            create_time: *constant::DEFAULT_DATETIME,
+            update_time: None,
+            article: None,
+            parent: None,
+            source: None,
+            can_delete: false,
+        }
+    }
 }
 
 impl ToJson for Comment {
@@ -101,11 +150,49 @@ impl ToJson for Comment {
         object.insert("content".to_owned(), self.content.to_json());
         object.insert("user".to_owned(), self.user.to_json());
         object.insert("create_time".to_owned(), self.create_time.format("%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("is_deleted".to_owned(),
+                      (self.status == constant::COMMENT::STATUS::DELETED).to_json());
+        object.insert("is_edited".to_owned(), self.update_time.is_some().to_json());
+        object.insert("update_time".to_owned(), self.update_time.map(
+            |t| t.format("%Y-%m-%d %H:%M:%S").to_string()).to_json());
+        object.insert("source".to_owned(), self.source.to_json());
+        object.insert("can_edit".to_owned(), self.source.is_some().to_json());
+        object.insert("can_delete".to_owned(), self.can_delete.to_json());
         object.insert("article".to_owned(), self.article.to_json());
+        object.insert("parent".to_owned(), match self.parent {
+            Some(ref parent) => parent.to_json(),
+            None => Json::Null,
+        });
         object.to_json()
     }
 }
 
+// tells a user about a reply or a mention
+#[derive(Clone)]
+pub struct Message {
+    pub id: u64,
+    pub mode: i8,
+    pub status: i8,
+    pub create_time: NaiveDateTime,
+    pub from_user: User,
+    // only id and title
+    pub article: Article,
+    // only id and content
+    pub comment: Comment,
+}
+
+impl Message {
+    // what `from_user` did, shown after "<user> 在 <article title>"
+    pub fn action(&self) -> &'static str {
+        match self.mode {
+            constant::MESSAGE::MODE::MENTION => "中提到了你",
+            constant::MESSAGE::MODE::REPLY_COMMENT => "中回复了你的评论",
+            _ => "中回复了你",
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Category {
     pub value: i8,
     pub title: String,
@@ -134,3 +221,56 @@ impl Category {
         }
     }
 }
+
+#[derive(Clone)]
+pub struct Session {
+    pub id: u64,
+    pub last_seen_ip: String,
+    pub user_agent: String,
+    pub create_time: NaiveDateTime,
+    pub last_seen_time: NaiveDateTime,
+    pub is_current: bool,
+}
+
+impl ToJson for Session {
+    fn to_json(&self) -> Json {
+        let mut object = Object::new();
+        object.insert("id".to_owned(), self.id.to_json());
+        object.insert("last_seen_ip".to_owned(), self.last_seen_ip.to_json());
+        object.insert("user_agent".to_owned(), self.user_agent.to_json());
+        object.insert("create_time".to_owned(), self.create_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("last_seen_time".to_owned(), self.last_seen_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("is_current".to_owned(), self.is_current.to_json());
+        object.to_json()
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessToken {
+    pub id: u64,
+    pub name: String,
+    pub scopes: u8,
+    pub create_time: NaiveDateTime,
+    pub last_used_time: Option<NaiveDateTime>,
+}
+
+impl ToJson for AccessToken {
+    fn to_json(&self) -> Json {
+        let scopes: Vec<String> = constant::ACCESS_TOKEN::SCOPE::ALL.iter()
+            .filter(|&&(scope, _, _)| self.scopes & scope > 0)
+            .map(|&(_, _, title)| title.to_owned())
+            .collect();
+
+        let mut object = Object::new();
+        object.insert("id".to_owned(), self.id.to_json());
+        object.insert("name".to_owned(), self.name.to_json());
+        object.insert("scopes".to_owned(), scopes.to_json());
+        object.insert("create_time".to_owned(), self.create_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("last_used_time".to_owned(), self.last_used_time.map(
+            |t| t.format("%Y-%m-%d %H:%M:%S").to_string()).to_json());
+        object.to_json()
+    }
+}
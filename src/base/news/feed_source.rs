@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use chrono::*;
+use xml::{Element, ElementBuilder, Parser, Xml};
+use base::http::HttpClient;
+use super::{NewsSource, NewsItem};
+
+// An RSS 2.0 or Atom 1.0 feed, such as This Week in Rust, the official Rust
+// blog or the latest topics of users.rust-lang.org. Elements are matched by
+// local name only; the feeds we read don't mix vocabularies.
+
+pub struct FeedSource {
+    name: String,
+    client: Arc<HttpClient>,
+    url: String,
+}
+
+impl FeedSource {
+    pub fn new(name: &str, client: Arc<HttpClient>, url: &str) -> FeedSource {
+        FeedSource {
+            name: name.to_owned(),
+            client: client,
+            url: url.to_owned(),
+        }
+    }
+
+    fn rss_item(&self, item: &Element) -> Option<NewsItem> {
+        let title = child_text(item, "title");
+        let link = child_text(item, "link");
+        if title.is_none() || link.is_none() {
+            return None;
+        }
+
+        Some(NewsItem {
+            source: self.name.clone(),
+            title: title.unwrap(),
+            url: link.unwrap(),
+            // dc:creator is what most blogs use, author is meant to be an email
+            author: child_text(item, "creator").or(child_text(item, "author"))
+                .unwrap_or(String::new()),
+            score: None,
+            comments_count: None,
+            comments_url: child_text(item, "comments"),
+            publish_time: child_text(item, "pubDate")
+                .and_then(|s| DateTime::parse_from_rfc2822(&s).ok())
+                .map(|t| t.with_timezone(&Local).naive_local())
+                .unwrap_or(Local::now().naive_local()),
+        })
+    }
+
+    fn atom_entry(&self, entry: &Element) -> Option<NewsItem> {
+        let title = child_text(entry, "title");
+        let link = children(entry, "link")
+            .into_iter()
+            .find(|l| l.get_attribute("rel", None).unwrap_or("alternate") == "alternate")
+            .and_then(|l| l.get_attribute("href", None))
+            .map(|href| href.to_owned());
+        if title.is_none() || link.is_none() {
+            return None;
+        }
+
+        Some(NewsItem {
+            source: self.name.clone(),
+            title: title.unwrap(),
+            url: link.unwrap(),
+            author: child(entry, "author").and_then(|a| child_text(a, "name"))
+                .unwrap_or(String::new()),
+            score: None,
+            comments_count: None,
+            comments_url: None,
+            publish_time: child_text(entry, "published").or(child_text(entry, "updated"))
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                .map(|t| t.with_timezone(&Local).naive_local())
+                .unwrap_or(Local::now().naive_local()),
+        })
+    }
+}
+
+impl NewsSource for FeedSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fetch(&self) -> Result<Vec<NewsItem>, String> {
+        let body = try!(self.client.get(&self.url));
+        let root = try!(parse(&body));
+
+        match &*root.name {
+            "rss" => match child(&root, "channel") {
+                Some(channel) => Ok(children(channel, "item").into_iter()
+                                    .filter_map(|item| self.rss_item(item)).collect()),
+                None => Err("rss without channel".to_owned()),
+            },
+            "feed" => Ok(children(&root, "entry").into_iter()
+                         .filter_map(|entry| self.atom_entry(entry)).collect()),
+            name => Err(format!("unknown feed root <{}>", name)),
+        }
+    }
+}
+
+fn parse(body: &str) -> Result<Element, String> {
+    let mut parser = Parser::new();
+    parser.feed_str(body);
+    let mut builder = ElementBuilder::new();
+    for event in parser {
+        match builder.handle_event(event) {
+            Some(Ok(element)) => return Ok(element),
+            Some(Err(e)) => return Err(format!("bad feed: {:?}", e)),
+            None => {}
+        }
+    }
+    Err("empty feed".to_owned())
+}
+
+fn children<'a>(element: &'a Element, name: &str) -> Vec<&'a Element> {
+    element.children.iter().filter_map(|node| match *node {
+        Xml::ElementNode(ref e) if e.name == name => Some(e),
+        _ => None,
+    }).collect()
+}
+
+fn child<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    children(element, name).into_iter().next()
+}
+
+fn child_text(element: &Element, name: &str) -> Option<String> {
+    child(element, name)
+        .map(|e| e.content_str().trim().to_owned())
+        .and_then(|s| if s.is_empty() { None } else { Some(s) })
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::thread;
+use chrono::*;
+use rustc_serialize::json::Json;
+use base::http::HttpClient;
+use super::{NewsSource, NewsItem};
+
+// Polls the Hacker News firebase api for top stories about Rust. This used
+// to be tools/hacker_news.py.
+
+// top story items are fetched by this many threads at once
+const FETCH_THREADS: usize = 10;
+
+fn story_from_json(source: &str, json: &Json) -> Option<NewsItem> {
+    let object = match json.as_object() {
+        Some(object) => object,
+        None => return None,
+    };
+
+    if object.get("deleted").is_some() || object.get("dead").is_some() {
+        return None;
+    }
+
+    let id = match object.get("id").and_then(|v| v.as_u64()) {
+        Some(id) => id,
+        None => return None,
+    };
+    let title = match object.get("title").and_then(|v| v.as_string()) {
+        Some(title) => title.to_owned(),
+        None => return None,
+    };
+    let time = object.get("time").and_then(|v| v.as_i64()).unwrap_or(0);
+    let comments_url = format!("https://news.ycombinator.com/item?id={}", id);
+
+    Some(NewsItem {
+        source: source.to_owned(),
+        title: title,
+        // "Ask HN" and the like have no url of their own
+        url: object.get("url").and_then(|v| v.as_string())
+            .map(|s| s.to_owned())
+            .unwrap_or(comments_url.clone()),
+        author: object.get("by").and_then(|v| v.as_string()).unwrap_or("").to_owned(),
+        score: Some(object.get("score").and_then(|v| v.as_i64()).unwrap_or(0)),
+        comments_count: Some(object.get("descendants").and_then(|v| v.as_i64()).unwrap_or(0)),
+        comments_url: Some(comments_url),
+        publish_time: Local.timestamp(time, 0).naive_local(),
+    })
+}
+
+pub struct HackerNews {
+    name: String,
+    client: Arc<HttpClient>,
+    api_prefix: String,
+}
+
+impl HackerNews {
+    pub fn new(name: &str, client: Arc<HttpClient>, api_prefix: &str) -> HackerNews {
+        HackerNews {
+            name: name.to_owned(),
+            client: client,
+            api_prefix: api_prefix.trim_right_matches('/').to_owned(),
+        }
+    }
+}
+
+impl NewsSource for HackerNews {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // top stories whose title mentions Rust, in top stories order
+    fn fetch(&self) -> Result<Vec<NewsItem>, String> {
+        let body = try!(self.client.get(&format!("{}/topstories.json", self.api_prefix)));
+        let ids: Vec<u64> = match Json::from_str(&body) {
+            Ok(Json::Array(ids)) => ids.iter().filter_map(|id| id.as_u64()).collect(),
+            _ => return Err(format!("bad topstories.json: {}", body)),
+        };
+
+        let chunk_size = (ids.len() + FETCH_THREADS - 1) / FETCH_THREADS;
+        let handles: Vec<_> = ids.chunks(if chunk_size == 0 {1} else {chunk_size})
+            .map(|chunk| {
+                let chunk = chunk.to_vec();
+                let client = self.client.clone();
+                let api_prefix = self.api_prefix.clone();
+                let name = self.name.clone();
+                thread::spawn(move || {
+                    chunk.iter().filter_map(|id| {
+                        let url = format!("{}/item/{}.json", api_prefix, id);
+                        match client.get(&url) {
+                            Ok(body) => Json::from_str(&body).ok()
+                                .and_then(|json| story_from_json(&name, &json)),
+                            Err(e) => {
+                                warn!("fetch hacker news item failed: {}", e);
+                                None
+                            }
+                        }
+                    }).collect::<Vec<NewsItem>>()
+                })
+            }).collect();
+
+        let mut stories = Vec::new();
+        for handle in handles {
+            let chunk_stories = try!(handle.join()
+                                     .map_err(|_| "fetch thread panicked".to_owned()));
+            stories.extend(chunk_stories.into_iter().filter(|s| s.title.contains("Rust")));
+        }
+        Ok(stories)
+    }
+}
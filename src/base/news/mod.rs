@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+use chrono::*;
+use rustc_serialize::json::{Json, Object, ToJson};
+use base::config::{Config, NewsSourceConfig};
+use base::http::{HttpClient, HyperClient};
+use repo::Repos;
+
+pub mod hacker_news;
+pub mod feed_source;
+pub mod reddit;
+
+use self::hacker_news::HackerNews;
+use self::feed_source::FeedSource;
+use self::reddit::Reddit;
+
+// Everything shown on /news comes from a `NewsSource`. The sources are listed
+// in `[[news.sources]]` of config.toml and polled by one background thread,
+// which keeps their items in the `news` table.
+
+#[derive(Clone)]
+pub struct NewsItem {
+    // name of the source the item came from, see `NewsSourceConfig`
+    pub source: String,
+    pub title: String,
+    pub url: String,
+    pub author: String,
+    pub score: Option<i64>,
+    pub comments_count: Option<i64>,
+    // the discussion page, when the source has one apart from `url`
+    pub comments_url: Option<String>,
+    pub publish_time: NaiveDateTime,
+}
+
+impl ToJson for NewsItem {
+    fn to_json(&self) -> Json {
+        let mut object = Object::new();
+        object.insert("source".to_owned(), self.source.to_json());
+        object.insert("title".to_owned(), self.title.to_json());
+        object.insert("url".to_owned(), self.url.to_json());
+        object.insert("author".to_owned(), self.author.to_json());
+        object.insert("score".to_owned(), self.score.to_json());
+        object.insert("comments_count".to_owned(), self.comments_count.to_json());
+        object.insert("comments_url".to_owned(), self.comments_url.to_json());
+        object.insert("publish_time".to_owned(), self.publish_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.to_json()
+    }
+}
+
+pub trait NewsSource: Send {
+    fn name(&self) -> &str;
+    fn fetch(&self) -> Result<Vec<NewsItem>, String>;
+}
+
+// the badge titles of all configured sources, keyed by name
+pub fn source_titles(config: &Config) -> HashMap<String, String> {
+    config.news.sources.iter().map(|s| (s.name.clone(), s.title.clone())).collect()
+}
+
+pub fn build_source(source: &NewsSourceConfig, client: Arc<HttpClient>)
+                    -> Result<Box<NewsSource>, String> {
+    match &*source.kind {
+        "hacker_news" => Ok(Box::new(HackerNews::new(&source.name, client, &source.url))),
+        "feed" => Ok(Box::new(FeedSource::new(&source.name, client, &source.url))),
+        "reddit" => Ok(Box::new(Reddit::new(&source.name, client, &source.url))),
+        kind => Err(format!("unknown news source kind \"{}\" of {}", kind, source.name)),
+    }
+}
+
+// Links are compared without their fragment and trailing slash, so that the
+// same post shared by two sources is stored once.
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let url = match url.find('#') {
+        Some(i) => &url[..i],
+        None => url,
+    };
+    url.trim_right_matches('/').to_owned()
+}
+
+// Starts the polling thread for the enabled sources, if there are any.
+pub fn spawn_worker(config: &Config, repos: Repos) {
+    let interval = config.news.interval_minutes;
+
+    let client: Arc<HttpClient> = Arc::new(HyperClient::new());
+    let sources: Vec<Box<NewsSource>> = config.news.sources.iter()
+        .filter(|s| s.enabled)
+        .map(|s| build_source(s, client.clone()).unwrap())
+        .collect();
+    if sources.is_empty() {
+        return;
+    }
+
+    thread::spawn(move || {
+        loop {
+            for source in &sources {
+                match source.fetch() {
+                    Ok(items) => {
+                        info!("fetched {} news items from {}", items.len(), source.name());
+                        if let Err(e) = repos.news.save(&items) {
+                            error!("save news from {} failed: {}", source.name(), e);
+                        }
+                    }
+                    Err(e) => warn!("fetch news from {} failed: {}", source.name(), e),
+                }
+            }
+            thread::sleep(StdDuration::from_secs(interval * 60));
+        }
+    });
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use chrono::*;
+use rustc_serialize::json::Json;
+use base::http::HttpClient;
+use super::{NewsSource, NewsItem};
+
+// A reddit style json listing, e.g. https://www.reddit.com/r/rust/hot.json
+
+pub struct Reddit {
+    name: String,
+    client: Arc<HttpClient>,
+    url: String,
+}
+
+impl Reddit {
+    pub fn new(name: &str, client: Arc<HttpClient>, url: &str) -> Reddit {
+        Reddit {
+            name: name.to_owned(),
+            client: client,
+            url: url.to_owned(),
+        }
+    }
+
+    fn item_from_json(&self, json: &Json) -> Option<NewsItem> {
+        let data = match json.find("data").and_then(|d| d.as_object()) {
+            Some(data) => data,
+            None => return None,
+        };
+
+        // stickied posts are the subreddit's own announcements
+        if data.get("stickied").and_then(|v| v.as_boolean()).unwrap_or(false) {
+            return None;
+        }
+
+        let title = match data.get("title").and_then(|v| v.as_string()) {
+            Some(title) => title.to_owned(),
+            None => return None,
+        };
+        let url = match data.get("url").and_then(|v| v.as_string()) {
+            Some(url) => url.to_owned(),
+            None => return None,
+        };
+        let comments_url = data.get("permalink").and_then(|v| v.as_string())
+            .map(|p| format!("https://www.reddit.com{}", p));
+        let time = data.get("created_utc").and_then(|v| v.as_f64()).unwrap_or(0.0);
+
+        Some(NewsItem {
+            source: self.name.clone(),
+            title: title,
+            // self posts link to their own comments page
+            url: url,
+            author: data.get("author").and_then(|v| v.as_string()).unwrap_or("").to_owned(),
+            score: data.get("score").and_then(|v| v.as_i64()),
+            comments_count: data.get("num_comments").and_then(|v| v.as_i64()),
+            comments_url: comments_url,
+            publish_time: Local.timestamp(time as i64, 0).naive_local(),
+        })
+    }
+}
+
+impl NewsSource for Reddit {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fetch(&self) -> Result<Vec<NewsItem>, String> {
+        let body = try!(self.client.get(&self.url));
+        let json = try!(Json::from_str(&body).map_err(|e| format!("bad listing: {}", e)));
+        let children = match json.find_path(&["data", "children"]).and_then(|c| c.as_array()) {
+            Some(children) => children,
+            None => return Err(format!("bad listing: {}", body)),
+        };
+        Ok(children.iter().filter_map(|child| self.item_from_json(child)).collect())
+    }
+}
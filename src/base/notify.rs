@@ -0,0 +1,206 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+use chrono::*;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use hbsi::HandlebarsEngine;
+use rustc_serialize::hex::ToHex;
+use rustc_serialize::json::{Json, Object, ToJson};
+use base::config::Config;
+use base::constant;
+use base::mail::{Mail, MyMailer};
+use base::model::Message;
+use base::util;
+use repo::Repos;
+
+// Replies and mentions are also mailed, the way each user chose in
+// constant::USER::NOTIFY: right away, or as one digest a day of everything
+// before `[notify] digest_hour`. A background thread looks for messages not
+// mailed yet and sends one mail per user, rendered from mail/messages.hbs.
+// Messages read on the site in the meantime, and those of users who turned
+// mails off or are banned, are skipped; either way a message is only
+// handled once.
+
+// how much of a reply a mail quotes
+const CONTENT_CHARS: usize = 200;
+
+// Starts the thread that mails new messages every `[notify] interval_minutes`.
+pub fn spawn_worker(config: &Config, repos: Repos, mailer: MyMailer,
+                    hbse: Arc<HandlebarsEngine>) {
+    let config = config.clone();
+
+    thread::spawn(move || {
+        loop {
+            thread::sleep(StdDuration::from_secs(config.notify.interval_minutes * 60));
+            match send_mails(&config, &repos, &mailer, &hbse, Local::now().naive_local()) {
+                Ok(0) => {}
+                Ok(count) => info!("sent {} message mails", count),
+                Err(e) => error!("mailing messages failed: {}", e),
+            }
+        }
+    });
+}
+
+// Mails everything due at `now` and returns how many mails went out. A mail
+// that fails is tried again next time.
+pub fn send_mails(config: &Config, repos: &Repos, mailer: &MyMailer,
+                  hbse: &HandlebarsEngine, now: NaiveDateTime) -> Result<usize, String> {
+    // turning mails back on must not bring up what happened meanwhile
+    let off = try!(repos.messages.list_unmailed(constant::USER::NOTIFY::OFF, now)
+                   .map_err(|e| e.to_string()));
+    let ids: Vec<u64> = off.iter().map(|&(_, ref message)| message.id).collect();
+    try!(repos.messages.set_mailed(&ids, now).map_err(|e| e.to_string()));
+
+    let instant = try!(repos.messages.list_unmailed(constant::USER::NOTIFY::INSTANT, now)
+                       .map_err(|e| e.to_string()));
+    let digest = try!(repos.messages.list_unmailed(constant::USER::NOTIFY::DIGEST,
+                                                   digest_time(now, config.notify.digest_hour))
+                      .map_err(|e| e.to_string()));
+
+    let round = Round {
+        config: config,
+        repos: repos,
+        mailer: mailer,
+        hbse: hbse,
+        now: now,
+    };
+    let mut count = 0;
+    for (is_digest, messages) in vec![(false, instant), (true, digest)] {
+        for (to_user_id, messages) in by_user(messages) {
+            match round.send_mail(to_user_id, &messages, is_digest) {
+                Ok(true) => count += 1,
+                Ok(false) => {}
+                Err(e) => error!("mailing messages to user {} failed: {}", to_user_id, e),
+            }
+        }
+    }
+    Ok(count)
+}
+
+// the secret of the unsubscribe link in every mail to the user
+pub fn unsubscribe_token(config: &Config, user_id: u64) -> String {
+    let mut hmac = Hmac::new(Sha256::new(), config.cookie_sign_key.as_bytes());
+    hmac.input(format!("unsubscribe:{}", user_id).as_bytes());
+    hmac.result().code().to_hex()
+}
+
+pub fn check_unsubscribe_token(config: &Config, user_id: u64, token: &str) -> bool {
+    fixed_time_eq(unsubscribe_token(config, user_id).as_bytes(), token.as_bytes())
+}
+
+// one run of the worker
+struct Round<'a> {
+    config: &'a Config,
+    repos: &'a Repos,
+    mailer: &'a MyMailer,
+    hbse: &'a HandlebarsEngine,
+    now: NaiveDateTime,
+}
+
+impl<'a> Round<'a> {
+    // false when there was nothing to send
+    fn send_mail(&self, to_user_id: u64, messages: &[Message],
+                 is_digest: bool) -> Result<bool, String> {
+        let ids: Vec<u64> = messages.iter().map(|message| message.id).collect();
+        let unread: Vec<&Message> = messages.iter()
+            .filter(|message| message.status == constant::MESSAGE::STATUS::INIT)
+            .collect();
+
+        let user = try!(self.repos.users.find(to_user_id).map_err(|e| e.to_string()));
+        let user = match user {
+            Some(ref user) if user.status != constant::USER::STATUS::BANNED &&
+                !unread.is_empty() => user,
+            _ => {
+                try!(self.repos.messages.set_mailed(&ids, self.now).map_err(|e| e.to_string()));
+                return Ok(false);
+            }
+        };
+
+        let app_path = &self.config.app_path;
+        let items: Vec<Json> = unread.iter().map(|message| {
+            // mails are plain text, so no markdown and no mention links
+            let text = util::render_text(&message.comment.content);
+            let mut content: String = text.chars().take(CONTENT_CHARS).collect();
+            if text.chars().count() > CONTENT_CHARS {
+                content.push_str("……");
+            }
+
+            let mut object = Object::new();
+            object.insert("username".to_owned(), message.from_user.username.to_json());
+            object.insert("article_title".to_owned(), message.article.title.to_json());
+            object.insert("action".to_owned(), message.action().to_json());
+            object.insert("content".to_owned(), content.to_json());
+            object.insert("url".to_owned(), format!("{}/article/{}#comment-{}", app_path,
+                                                    message.article.id,
+                                                    message.comment.id).to_json());
+            object.to_json()
+        }).collect();
+
+        let mut data = Object::new();
+        data.insert("username".to_owned(), user.username.to_json());
+        data.insert("is_digest".to_owned(), is_digest.to_json());
+        data.insert("count".to_owned(), unread.len().to_json());
+        data.insert("messages".to_owned(), items.to_json());
+        data.insert("messages_url".to_owned(),
+                    format!("{}/user/{}/messages", app_path, user.id).to_json());
+        let unsubscribe_url = format!("{}/user/{}/unsubscribe/{}", app_path, user.id,
+                                      unsubscribe_token(self.config, user.id));
+        data.insert("unsubscribe_url".to_owned(), unsubscribe_url.to_json());
+
+        let body = try!(self.hbse.registry.read().unwrap()
+                        .render("mail/messages", &data.to_json())
+                        .map_err(|e| format!("{:?}", e)));
+
+        let subject = if is_digest {
+            format!("Rust China 每日消息汇总（{}条）", unread.len())
+        } else if unread.len() == 1 {
+            format!("{} 在「{}」{}", unread[0].from_user.username,
+                    unread[0].article.title, unread[0].action())
+        } else {
+            format!("Rust China {}条新消息", unread.len())
+        };
+
+        try!(self.mailer.send(&Mail {
+            to: user.email.clone(),
+            subject: subject,
+            body: body,
+            // one click unsubscribing, see RFC 8058
+            headers: vec![
+                ("List-Unsubscribe".to_owned(), format!("<{}>", unsubscribe_url)),
+                ("List-Unsubscribe-Post".to_owned(), "List-Unsubscribe=One-Click".to_owned()),
+            ],
+        }));
+        try!(self.repos.messages.set_mailed(&ids, self.now).map_err(|e| e.to_string()));
+        Ok(true)
+    }
+}
+
+// the last `digest_hour` o'clock up to `now`; a digest has every message
+// from before it, so nobody gets two a day
+fn digest_time(now: NaiveDateTime, digest_hour: u32) -> NaiveDateTime {
+    let today = now.date().and_hms(digest_hour, 0, 0);
+    if today <= now {
+        today
+    } else {
+        today - Duration::days(1)
+    }
+}
+
+// messages sorted by recipient, grouped by recipient
+fn by_user(messages: Vec<(u64, Message)>) -> Vec<(u64, Vec<Message>)> {
+    let mut groups: Vec<(u64, Vec<Message>)> = Vec::new();
+    for (to_user_id, message) in messages {
+        let is_new = match groups.last() {
+            Some(&(last_id, _)) => last_id != to_user_id,
+            None => true,
+        };
+        if is_new {
+            groups.push((to_user_id, Vec::new()));
+        }
+        groups.last_mut().unwrap().1.push(message);
+    }
+    groups
+}
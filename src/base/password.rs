@@ -0,0 +1,102 @@
+use crypto::md5;
+use crypto::digest::Digest;
+use crypto::scrypt::{self, ScryptParams};
+use crypto::util::fixed_time_eq;
+
+// Every hasher writes a self-describing hash starting with its own prefix,
+// so `verify` can tell which algorithm made a stored hash. The legacy MD5
+// hashes have no prefix: they are plain 32-char hex with a separate salt
+// column in `user`.
+
+pub trait PasswordHasher: Send + Sync {
+    fn prefix(&self) -> &'static str;
+    fn hash(&self, password: &str) -> String;
+    fn verify(&self, password: &str, salt: &str, hash: &str) -> bool;
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with(self.prefix())
+    }
+}
+
+pub struct Scrypt {
+    params: ScryptParams,
+}
+
+impl Default for Scrypt {
+    fn default() -> Scrypt {
+        // N=2^14, r=8, p=1, the interactive login parameters from the paper.
+        Scrypt { params: ScryptParams::new(14, 8, 1) }
+    }
+}
+
+impl PasswordHasher for Scrypt {
+    fn prefix(&self) -> &'static str {
+        "$rscrypt$"
+    }
+
+    fn hash(&self, password: &str) -> String {
+        scrypt::scrypt_simple(password, &self.params).unwrap()
+    }
+
+    fn verify(&self, password: &str, _salt: &str, hash: &str) -> bool {
+        scrypt::scrypt_check(password, hash).unwrap_or(false)
+    }
+}
+
+pub struct LegacyMd5;
+
+impl PasswordHasher for LegacyMd5 {
+    fn prefix(&self) -> &'static str {
+        ""
+    }
+
+    fn hash(&self, _password: &str) -> String {
+        panic!("md5 password hashes must not be created any more");
+    }
+
+    fn verify(&self, password: &str, salt: &str, hash: &str) -> bool {
+        let mut sh = md5::Md5::new();
+        sh.input_str(&(password.to_owned() + salt));
+        fixed_time_eq(sh.result_str().as_bytes(), hash.as_bytes())
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.len() == 32 && !hash.starts_with('$')
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verified {
+    Ok,
+    NeedsUpgrade,
+    Fail,
+}
+
+lazy_static! {
+    // The first hasher is used for new passwords, the rest are only kept
+    // around to verify hashes made before it became the default.
+    static ref HASHERS: Vec<Box<PasswordHasher>> = vec![
+        Box::new(Scrypt::default()),
+        Box::new(LegacyMd5),
+    ];
+}
+
+pub fn hash(password: &str) -> String {
+    HASHERS[0].hash(password)
+}
+
+pub fn verify(password: &str, salt: &str, hash: &str) -> Verified {
+    for (i, hasher) in HASHERS.iter().enumerate() {
+        if !hasher.recognizes(hash) {
+            continue;
+        }
+
+        if !hasher.verify(password, salt, hash) {
+            return Verified::Fail;
+        }
+
+        return if i == 0 { Verified::Ok } else { Verified::NeedsUpgrade };
+    }
+
+    Verified::Fail
+}
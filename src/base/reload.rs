@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use hbsi::HandlebarsEngine;
+use libc;
+use base::config::{Config, SiteConfig};
+
+// Picking up template and `[site]` changes without a restart: `serve --dev`
+// polls templates/ for changes, and in production `kill -HUP` re-reads both.
+
+static HUP_RECEIVED: AtomicBool = ATOMIC_BOOL_INIT;
+
+extern "C" fn on_hup(_: libc::c_int) {
+    // nothing but an atomic store is safe in a signal handler
+    HUP_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+// Reloads the templates, and `[site]` from `config_path`, on every SIGHUP.
+pub fn reload_on_hup(hbse: Arc<HandlebarsEngine>, site: Arc<RwLock<SiteConfig>>,
+                     config_path: String) {
+    unsafe {
+        libc::signal(libc::SIGHUP, on_hup as libc::sighandler_t);
+    }
+
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            if !HUP_RECEIVED.swap(false, Ordering::SeqCst) {
+                continue;
+            }
+
+            info!("SIGHUP received, reloading");
+            reload_templates(&hbse);
+            // a broken file keeps the old settings rather than half of the new
+            match Config::load(&config_path) {
+                Ok(config) => {
+                    *site.write().unwrap() = config.site;
+                    info!("reloaded [site] from {}", config_path);
+                }
+                Err(e) => error!("config not reloaded: {}", e),
+            }
+        }
+    });
+}
+
+// Reloads the templates whenever a file under `dir` changes.
+pub fn watch_templates(hbse: Arc<HandlebarsEngine>, dir: &str) {
+    let dir = dir.to_owned();
+    thread::spawn(move || {
+        let mut last = latest_change(Path::new(&dir));
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            let current = latest_change(Path::new(&dir));
+            if current != last {
+                last = current;
+                reload_templates(&hbse);
+            }
+        }
+    });
+}
+
+fn reload_templates(hbse: &HandlebarsEngine) {
+    match hbse.reload() {
+        Ok(_) => info!("templates reloaded"),
+        // the templates loaded before stay in use
+        Err(e) => error!("templates not reloaded: {:?}", e),
+    }
+}
+
+// newest modification time and file count under `dir`, so that deleting a
+// file counts as a change too
+fn latest_change(dir: &Path) -> (Option<SystemTime>, usize) {
+    let mut latest = None;
+    let mut count = 0;
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                let (sub_latest, sub_count) = latest_change(&path);
+                latest = latest.max(sub_latest);
+                count += sub_count;
+            } else if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                latest = latest.max(Some(modified));
+                count += 1;
+            }
+        }
+    }
+    (latest, count)
+}
@@ -0,0 +1,62 @@
+use chrono::*;
+use base::constant;
+use base::db::DbError;
+use base::model::Session;
+use base::util;
+use repo::Repos;
+
+// Only the sha256 of a session token is stored, the token itself lives in
+// the signed `logged_in_user` cookie.
+
+pub fn create(repos: &Repos, user_id: u64, ip: &str,
+              user_agent: &str) -> Result<String, DbError> {
+    let token = util::gen_random_token(48);
+    let now = Local::now().naive_local();
+    let expire_time = now + Duration::days(constant::SESSION_EXPIRE_DAYS);
+    try!(repos.sessions.create(user_id, &util::sha256_hex(&token), ip, user_agent,
+                               now, expire_time));
+    Ok(token)
+}
+
+// Returns (session_id, user_id) of a live session, refreshing its last
+// seen info on the way.
+pub fn touch(repos: &Repos, token: &str, ip: &str,
+             user_agent: &str) -> Result<Option<(u64, u64)>, DbError> {
+    let now = Local::now().naive_local();
+    let (session_id, user_id) = match try!(repos.sessions.find(&util::sha256_hex(token), now)) {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+
+    let touch_before = now - Duration::minutes(constant::SESSION_TOUCH_INTERVAL_MINUTES);
+    try!(repos.sessions.touch(session_id, ip, user_agent, now, touch_before));
+
+    Ok(Some((session_id, user_id)))
+}
+
+pub fn list(repos: &Repos, user_id: u64,
+            current_session_id: u64) -> Result<Vec<Session>, DbError> {
+    let now = Local::now().naive_local();
+    let sessions = try!(repos.sessions.list(user_id, now));
+    Ok(sessions.into_iter()
+       .map(|session| Session {
+           is_current: session.id == current_session_id,
+           ..session
+       }).collect())
+}
+
+pub fn delete_by_token(repos: &Repos, token: &str) -> Result<(), DbError> {
+    repos.sessions.delete_by_token(&util::sha256_hex(token))
+}
+
+pub fn delete(repos: &Repos, user_id: u64, session_id: u64) -> Result<(), DbError> {
+    repos.sessions.delete(user_id, session_id)
+}
+
+pub fn delete_all(repos: &Repos, user_id: u64) -> Result<(), DbError> {
+    repos.sessions.delete_all(user_id)
+}
+
+pub fn delete_others(repos: &Repos, user_id: u64, current_session_id: u64) -> Result<(), DbError> {
+    repos.sessions.delete_others(user_id, current_session_id)
+}
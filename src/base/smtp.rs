@@ -0,0 +1,156 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use hyper::net::{HttpStream, OpensslClient, SslClient};
+use rustc_serialize::base64::{ToBase64, STANDARD};
+
+// Just enough of SMTP (RFC 5321) to hand a mail to the relay of `[mail]`:
+// EHLO, STARTTLS or tls from the first byte, AUTH PLAIN and one recipient.
+// The tls is the openssl that hyper and mysql link already, with hyper's
+// certificate and host name checks.
+
+// tls from the first byte, every other port upgrades with STARTTLS
+pub const SUBMISSIONS_PORT: u16 = 465;
+
+pub struct Relay<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub tls: bool,
+    // no AUTH when empty
+    pub user: &'a str,
+    pub password: &'a str,
+}
+
+// `from` and `to` are bare addresses, `message` is the whole mail with its
+// headers, lines ending in CRLF
+pub fn send(relay: &Relay, from: &str, to: &str, message: &str) -> Result<(), String> {
+    let tcp = try!(TcpStream::connect((relay.host, relay.port))
+                   .map_err(|e| format!("connect to {}:{}: {}", relay.host, relay.port, e)));
+    try!(tcp.set_read_timeout(Some(Duration::from_secs(30))).map_err(|e| e.to_string()));
+    try!(tcp.set_write_timeout(Some(Duration::from_secs(30))).map_err(|e| e.to_string()));
+
+    // the name we greet with, the relay hardly cares
+    let name = from.rsplit('@').next().unwrap_or("localhost");
+
+    if relay.tls && relay.port == SUBMISSIONS_PORT {
+        let mut conn = Connection::new(try!(wrap_tls(tcp, relay.host)));
+        try!(conn.expect("connect", &[220]));
+        try!(conn.command(&format!("EHLO {}", name), &[250]));
+        return deliver(&mut conn, relay, from, to, message);
+    }
+
+    let mut conn = Connection::new(tcp);
+    try!(conn.expect("connect", &[220]));
+    let extensions = try!(conn.command(&format!("EHLO {}", name), &[250]));
+    if !relay.tls {
+        return deliver(&mut conn, relay, from, to, message);
+    }
+
+    if !extensions.iter().any(|e| e.to_uppercase() == "STARTTLS") {
+        return Err(format!("{} does not offer STARTTLS", relay.host));
+    }
+    try!(conn.command("STARTTLS", &[220]));
+    let mut conn = Connection::new(try!(wrap_tls(conn.into_inner(), relay.host)));
+    try!(conn.command(&format!("EHLO {}", name), &[250]));
+    deliver(&mut conn, relay, from, to, message)
+}
+
+fn wrap_tls(tcp: TcpStream, host: &str) -> Result<<OpensslClient as SslClient>::Stream, String> {
+    OpensslClient::default().wrap_client(HttpStream(tcp), host)
+        .map_err(|e| format!("tls with {}: {}", host, e))
+}
+
+fn deliver<S: Read + Write>(conn: &mut Connection<S>, relay: &Relay, from: &str, to: &str,
+                            message: &str) -> Result<(), String> {
+    if !relay.user.is_empty() {
+        let credentials = format!("\0{}\0{}", relay.user, relay.password);
+        try!(conn.command(&format!("AUTH PLAIN {}", credentials.as_bytes().to_base64(STANDARD)),
+                          &[235]));
+    }
+    try!(conn.command(&format!("MAIL FROM:<{}>", from), &[250]));
+    try!(conn.command(&format!("RCPT TO:<{}>", to), &[250, 251]));
+    try!(conn.command("DATA", &[354]));
+
+    // a line starting with a dot gets another one, the lone dot ends the data
+    let mut data = String::with_capacity(message.len() + 8);
+    for line in message.lines() {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".\r\n");
+    try!(conn.write(&data));
+    try!(conn.expect("DATA", &[250]));
+
+    // the mail is taken, whatever the goodbye says
+    let _ = conn.command("QUIT", &[221]);
+    Ok(())
+}
+
+struct Connection<S: Read + Write> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> Connection<S> {
+    fn new(stream: S) -> Connection<S> {
+        Connection { stream: BufReader::new(stream) }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    // the text of the reply's lines after the first one, e.g. the
+    // extensions of an EHLO
+    fn command(&mut self, line: &str, codes: &[u16]) -> Result<Vec<String>, String> {
+        try!(self.write(&format!("{}\r\n", line)));
+        // AUTH must not end up in the log with its credentials
+        let verb = line.split(' ').next().unwrap_or("");
+        self.expect(verb, codes)
+    }
+
+    fn write(&mut self, data: &str) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        stream.write_all(data.as_bytes())
+            .and_then(|_| stream.flush())
+            .map_err(|e| e.to_string())
+    }
+
+    fn expect(&mut self, what: &str, codes: &[u16]) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if try!(self.stream.read_line(&mut line).map_err(|e| format!("{}: {}", what, e))) == 0 {
+                return Err(format!("{}: connection closed", what));
+            }
+            let line = line.trim_right();
+            let code = if line.len() >= 3 && line.is_char_boundary(3) {
+                line[..3].parse::<u16>().ok()
+            } else {
+                None
+            };
+            let code = match code {
+                Some(code) => code,
+                None => return Err(format!("{}: bad reply: {}", what, line)),
+            };
+            // "250-..." is followed by more lines, "250 ..." is the last one
+            let separator = line.as_bytes().get(3).cloned();
+            let text = match separator {
+                Some(b' ') | Some(b'-') => &line[4..],
+                _ => "",
+            };
+            if separator == Some(b'-') {
+                lines.push(text.to_owned());
+                continue;
+            }
+            if !codes.contains(&code) {
+                return Err(format!("{}: {}", what, line));
+            }
+            lines.push(text.to_owned());
+            lines.remove(0);
+            return Ok(lines);
+        }
+    }
+}
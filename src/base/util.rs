@@ -1,12 +1,20 @@
-use pulldown_cmark::Parser;
+use pulldown_cmark::{Event, Parser, Tag};
 use pulldown_cmark::html;
 use crypto::md5;
+use crypto::sha2::Sha256;
 use crypto::digest::Digest;
 use std::collections::HashSet;
 use ammonia::Ammonia;
 use rustc_serialize::json::{Object, Json, Array, ToJson};
 use base::constant;
 use regex::Regex;
+use rand::{OsRng, Rng};
+use iron::Request;
+use hyper::header::UserAgent;
+use persistent::Read;
+use std::net::IpAddr;
+use std::str;
+use base::config::Config;
 
 pub fn render_html(text: &str) -> String {
     let mut s = String::with_capacity(text.len() * 3 / 2);
@@ -19,12 +27,68 @@ pub fn render_html(text: &str) -> String {
     cleaner.clean(&*s).to_owned()
 }
 
+// plain text of markdown for mails: links and images keep only their text,
+// so mentions read `@user` again, and html is dropped
+pub fn render_text(markdown: &str) -> String {
+    let mut s = String::with_capacity(markdown.len());
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(text) => s.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => s.push('\n'),
+            Event::Start(Tag::Item) => s.push_str("- "),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Header(_)) |
+            Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Item) |
+            Event::End(Tag::TableRow) => {
+                if !s.ends_with('\n') {
+                    s.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+    s.trim().to_owned()
+}
+
 pub fn gen_gravatar_url(email: &str) -> String {
     let mut sh = md5::Md5::new();
     sh.input_str(&email.trim().to_lowercase());
     "https://cdn.v2ex.com/gravatar/".to_owned() + &sh.result_str()
 }
 
+pub fn gen_random_token(len: usize) -> String {
+    OsRng::new().unwrap().gen_ascii_chars().take(len).collect()
+}
+
+pub fn sha256_hex(s: &str) -> String {
+    let mut sh = Sha256::new();
+    sh.input_str(s);
+    sh.result_str()
+}
+
+pub fn client_ip(req: &Request) -> String {
+    let remote_ip = req.remote_addr.ip();
+
+    // we are usually deployed behind nginx, see Config::trusted_proxies
+    let trusted = req.extensions.get::<Read<Config>>().map_or(false, |config| {
+        config.trusted_proxies.iter().any(|proxy| proxy.parse::<IpAddr>() == Ok(remote_ip))
+    });
+    if trusted {
+        if let Some(raw) = req.headers.get_raw("X-Real-IP") {
+            if let Ok(ip) = str::from_utf8(&raw[0]) {
+                return ip.trim().to_owned();
+            }
+        }
+    }
+    remote_ip.to_string()
+}
+
+pub fn user_agent(req: &Request) -> String {
+    match req.headers.get::<UserAgent>() {
+        Some(ua) => ua.0.chars().take(255).collect(),
+        None => String::new(),
+    }
+}
+
 pub fn gen_categories_json(raw_active_value: Option<i8>) -> Json {
     let mut categories = Array::new();
 
@@ -44,6 +108,21 @@ pub fn gen_categories_json(raw_active_value: Option<i8>) -> Json {
     categories.to_json()
 }
 
+pub fn gen_pages_json(page_count: usize, current_page: usize) -> Json {
+    let mut pages = Array::new();
+
+    for page in 1..page_count + 1 {
+        let mut object = Object::new();
+        object.insert("page".to_owned(), page.to_json());
+        if page == current_page {
+            object.insert("active".to_owned(), 1.to_json());
+        }
+        pages.push(object.to_json());
+    }
+
+    pages.to_json()
+}
+
 pub fn safe_xml(s: &str) -> String {
     // http://www.w3.org/TR/REC-xml/#charsets
 
@@ -0,0 +1,322 @@
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use chrono::*;
+use libc;
+use regex::Regex;
+use rustc_serialize::json::{Json, Object, ToJson, as_pretty_json};
+use base::constant;
+use base::db::Database;
+use base::migration;
+use base::password;
+use base::session;
+use base::access_token;
+use repo::{Change, Repos, Moderation};
+
+// Site administration from the shell, so that operators never have to write
+// sql against production. `main` runs the server when no command is given.
+
+pub const USAGE: &'static str = "\
+usage: rust_lang_cn [--config <path>] [command]
+
+commands:
+    serve [--dev]                           run the web server (the default),
+                                            --dev reloads templates on change
+    migrate                                 apply pending schema migrations
+    user create <username> <email>          asks for the password, or reads
+                                            the first line of stdin
+    user ban <username>                     ban and log out everywhere
+    user unban <username>
+    user set-role <username> <member|moderator|admin>
+    article pin <article_id>                put on top of the home page
+    article unpin <article_id>
+    article essence <article_id>
+    article unessence <article_id>
+    article delete <article_id>
+    article restore <article_id>
+    reindex-search                          rebuild the fulltext indexes (mysql)
+    export [file]                           dump users, articles and comments as json";
+
+// full text indexes as in tables.sql: (table, index name, columns)
+const FULLTEXT_INDEXES: [(&'static str, &'static str, &'static str); 3] = [
+    ("article", "ft_title", "`title`"),
+    ("article", "ft_title_content", "`title`, `content`"),
+    ("comment", "ft_content", "`content`"),
+];
+
+pub fn run(db: &Database, args: &[String]) -> Result<(), String> {
+    let stdin = io::stdin();
+    let tty = unsafe { libc::isatty(libc::STDIN_FILENO) == 1 };
+    run_with(db, args, &mut stdin.lock(), tty)
+}
+
+// `input` is where passwords come from; on a `tty` they are asked for
+// without echo
+pub fn run_with(db: &Database, args: &[String], input: &mut BufRead, tty: bool)
+                -> Result<(), String> {
+    let arg = |i: usize| args.get(i).map(|s| &**s).unwrap_or("");
+    let repos = &Repos::new(db);
+
+    // like `serve`, never work on a schema this binary does not know
+    if arg(0) != "migrate" {
+        try!(migration::check(db));
+    }
+
+    match (arg(0), arg(1), args.len()) {
+        ("migrate", _, 1) => migrate(db),
+        ("user", "create", 4) => {
+            let password = try!(read_password(input, tty));
+            create_user(repos, arg(2), arg(3), &password)
+        }
+        ("user", "ban", 3) => set_user_status(repos, arg(2), constant::USER::STATUS::BANNED),
+        ("user", "unban", 3) => set_user_status(repos, arg(2), constant::USER::STATUS::NORMAL),
+        ("user", "set-role", 4) => set_role(repos, arg(2), arg(3)),
+        ("article", "pin", 3) => set_flag(repos, arg(2), constant::ARTICLE::FLAG::TOP, true),
+        ("article", "unpin", 3) => set_flag(repos, arg(2), constant::ARTICLE::FLAG::TOP, false),
+        ("article", "essence", 3) => set_flag(repos, arg(2), constant::ARTICLE::FLAG::ESSENCE, true),
+        ("article", "unessence", 3) => set_flag(repos, arg(2), constant::ARTICLE::FLAG::ESSENCE, false),
+        ("article", "delete", 3) => set_article_status(
+            repos, arg(2), constant::ARTICLE::STATUS::NORMAL, constant::ARTICLE::STATUS::DELETED),
+        ("article", "restore", 3) => set_article_status(
+            repos, arg(2), constant::ARTICLE::STATUS::DELETED, constant::ARTICLE::STATUS::NORMAL),
+        ("reindex-search", _, 1) => reindex_search(db),
+        ("export", _, 1) => export(repos, None),
+        ("export", path, 2) => export(repos, Some(path)),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+fn migrate(db: &Database) -> Result<(), String> {
+    let applied = try!(migration::run(db));
+    println!("applied {} migration(s), schema is at version {}",
+             applied.len(), migration::latest_version());
+    Ok(())
+}
+
+// not an argument, so it stays out of the shell history and `ps`
+fn read_password(input: &mut BufRead, tty: bool) -> Result<String, String> {
+    let mut line = String::new();
+    if tty {
+        print!("password: ");
+        try!(io::stdout().flush().map_err(|e| e.to_string()));
+        let _echo = NoEcho::new();
+        try!(input.read_line(&mut line).map_err(|e| e.to_string()));
+        println!("");
+    } else {
+        try!(input.read_line(&mut line).map_err(|e| e.to_string()));
+    }
+    Ok(line.trim_right_matches(|c| c == '\r' || c == '\n').to_owned())
+}
+
+// turns off the echo of the terminal on stdin until dropped
+struct NoEcho(Option<libc::termios>);
+
+impl NoEcho {
+    fn new() -> NoEcho {
+        unsafe {
+            let mut saved: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return NoEcho(None);
+            }
+            let mut quiet = saved;
+            quiet.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &quiet);
+            NoEcho(Some(saved))
+        }
+    }
+}
+
+impl Drop for NoEcho {
+    fn drop(&mut self) {
+        if let Some(ref saved) = self.0 {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+// same rules as the register form
+fn create_user(repos: &Repos, username: &str, email: &str, password: &str) -> Result<(), String> {
+    let re = Regex::new(r"^[a-zA-Z_][\da-zA-Z_]{2,}$").unwrap();
+    if username.len() > 32 || !re.is_match(username) {
+        return Err(format!("invalid username: {}", username));
+    }
+    if email.len() < 5 || email.len() > 64 || !email.contains('@') {
+        return Err(format!("invalid email: {}", email));
+    }
+    if password.len() < 8 || password.len() > 32 {
+        return Err("password must be 8 to 32 characters".to_owned());
+    }
+
+    // mails stay off until the user turns them on
+    match try!(repos.users.create(username, email, &password::hash(password),
+                                  constant::USER::NOTIFY::OFF)
+               .map_err(|e| e.to_string())) {
+        Some(user_id) => {
+            println!("created user {} with id {}", username, user_id);
+            Ok(())
+        }
+        None => Err(format!("username or email already taken: {} {}", username, email)),
+    }
+}
+
+fn set_user_status(repos: &Repos, username: &str, status: i8) -> Result<(), String> {
+    let user_id = try!(find_user(repos, username));
+    try!(repos.users.set_status(user_id, status).map_err(|e| e.to_string()));
+
+    if status == constant::USER::STATUS::BANNED {
+        try!(session::delete_all(repos, user_id).map_err(|e| e.to_string()));
+        try!(access_token::delete_all(repos, user_id).map_err(|e| e.to_string()));
+        println!("banned {}, all sessions and access tokens are revoked", username);
+    } else {
+        println!("unbanned {}", username);
+    }
+    Ok(())
+}
+
+fn set_role(repos: &Repos, username: &str, role: &str) -> Result<(), String> {
+    let role = match role {
+        "member" => constant::USER::ROLE::MEMBER,
+        "moderator" => constant::USER::ROLE::MODERATOR,
+        "admin" => constant::USER::ROLE::ADMIN,
+        _ => return Err(format!("unknown role: {}", role)),
+    };
+    let user_id = try!(find_user(repos, username));
+    try!(repos.users.set_role(user_id, role).map_err(|e| e.to_string()));
+    println!("role of {} is set", username);
+    Ok(())
+}
+
+// like the admin pages, this does not touch update_time
+fn set_flag(repos: &Repos, id: &str, flag: u8, on: bool) -> Result<(), String> {
+    let article_id = try!(find_article(repos, id, constant::ARTICLE::STATUS::NORMAL));
+    try!(repos.articles.moderate(article_id, &Moderation::Flag(flag, on))
+         .map_err(|e| e.to_string()));
+    println!("article {} updated", article_id);
+    Ok(())
+}
+
+fn set_article_status(repos: &Repos, id: &str, from_status: i8, to_status: i8)
+                      -> Result<(), String> {
+    let article_id = try!(id.parse::<u64>().map_err(|_| format!("invalid article id: {}", id)));
+    match try!(repos.articles.set_status(article_id, None, from_status, to_status)
+               .map_err(|e| e.to_string())) {
+        Change::Done => {
+            println!("article {} updated", article_id);
+            Ok(())
+        }
+        _ => Err(format!("no such article: {}", article_id)),
+    }
+}
+
+// Drops and recreates the ngram indexes, e.g. after changing
+// ngram_token_size. Searching is slow until it finishes. SQLite searches
+// with like and has no index to rebuild.
+fn reindex_search(db: &Database) -> Result<(), String> {
+    let pool = match *db {
+        Database::Mysql(ref pool) => pool,
+        Database::Sqlite(_) => {
+            println!("sqlite has no fulltext indexes, nothing to rebuild");
+            return Ok(());
+        }
+    };
+
+    for &(table, index, columns) in FULLTEXT_INDEXES.iter() {
+        try!(pool.prep_exec(format!("ALTER TABLE `{}` DROP INDEX `{}`, \
+                                     ADD FULLTEXT KEY `{}` ({}) WITH PARSER ngram",
+                                    table, index, index, columns), ())
+             .map_err(|e| format!("reindex {}.{} failed: {}", table, index, e)));
+        println!("rebuilt {}.{}", table, index);
+    }
+    Ok(())
+}
+
+// Everything but passwords and sessions, one json document.
+fn export(repos: &Repos, path: Option<&str>) -> Result<(), String> {
+    let users: Vec<Json> = try!(repos.users.all().map_err(|e| e.to_string()))
+        .into_iter()
+        .map(|user| {
+            let mut object = Object::new();
+            object.insert("id".to_owned(), user.id.to_json());
+            object.insert("username".to_owned(), user.username.to_json());
+            object.insert("email".to_owned(), user.email.to_json());
+            object.insert("role".to_owned(), user.role.to_json());
+            object.insert("status".to_owned(), user.status.to_json());
+            object.insert("create_time".to_owned(), format_time(user.create_time));
+            object.to_json()
+        }).collect();
+
+    let articles: Vec<Json> = try!(repos.articles.all().map_err(|e| e.to_string()))
+        .into_iter()
+        .map(|article| {
+            let mut tags = article.tags.clone();
+            tags.sort();
+            let mut object = Object::new();
+            object.insert("id".to_owned(), article.id.to_json());
+            object.insert("category".to_owned(), article.category.value.to_json());
+            object.insert("title".to_owned(), article.title.to_json());
+            object.insert("content".to_owned(), article.content.to_json());
+            object.insert("user_id".to_owned(), article.user.id.to_json());
+            object.insert("status".to_owned(), article.status.to_json());
+            object.insert("flag".to_owned(), article.flag.to_json());
+            object.insert("tags".to_owned(), tags.to_json());
+            object.insert("create_time".to_owned(), format_time(article.create_time));
+            object.insert("update_time".to_owned(), format_time(article.update_time));
+            object.to_json()
+        }).collect();
+
+    let comments: Vec<Json> = try!(repos.comments.all().map_err(|e| e.to_string()))
+        .into_iter()
+        .map(|comment| {
+            let mut object = Object::new();
+            object.insert("id".to_owned(), comment.id.to_json());
+            object.insert("article_id".to_owned(),
+                          comment.article.as_ref().map(|a| a.id).to_json());
+            object.insert("parent_id".to_owned(),
+                          comment.parent.as_ref().map(|p| p.id).to_json());
+            object.insert("user_id".to_owned(), comment.user.id.to_json());
+            object.insert("content".to_owned(), comment.content.to_json());
+            object.insert("status".to_owned(), comment.status.to_json());
+            object.insert("create_time".to_owned(), format_time(comment.create_time));
+            object.insert("update_time".to_owned(),
+                          comment.update_time.map(format_time).to_json());
+            object.to_json()
+        }).collect();
+
+    let mut object = Object::new();
+    object.insert("users".to_owned(), users.to_json());
+    object.insert("articles".to_owned(), articles.to_json());
+    object.insert("comments".to_owned(), comments.to_json());
+    let body = format!("{}\n", as_pretty_json(&object.to_json()));
+
+    match path {
+        Some(path) => {
+            let mut file = try!(File::create(path).map_err(|e| format!("{}: {}", path, e)));
+            try!(file.write_all(body.as_bytes()).map_err(|e| format!("{}: {}", path, e)));
+            println!("exported {} users, {} articles and {} comments to {}",
+                     users.len(), articles.len(), comments.len(), path);
+        }
+        None => try!(io::stdout().write_all(body.as_bytes()).map_err(|e| e.to_string())),
+    }
+    Ok(())
+}
+
+fn find_user(repos: &Repos, username: &str) -> Result<u64, String> {
+    match try!(repos.users.find_by_username(username).map_err(|e| e.to_string())) {
+        Some(user) => Ok(user.id),
+        None => Err(format!("no such user: {}", username)),
+    }
+}
+
+fn find_article(repos: &Repos, id: &str, status: i8) -> Result<u64, String> {
+    let article_id = try!(id.parse::<u64>().map_err(|_| format!("invalid article id: {}", id)));
+    match try!(repos.articles.find(article_id).map_err(|e| e.to_string())) {
+        Some(ref article) if article.status == status => Ok(article_id),
+        _ => Err(format!("no such article: {}", article_id)),
+    }
+}
+
+fn format_time(t: NaiveDateTime) -> Json {
+    t.format("%Y-%m-%d %H:%M:%S").to_string().to_json()
+}
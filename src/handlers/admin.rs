@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use iron::prelude::*;
+use base::framework::{ResponseData, temp_response,
+                      json_error_response, json_ok_response,
+                      not_found_response};
+use urlencoded::{UrlEncodedBody, UrlEncodedQuery};
+use form_checker::{Validator, Checker, Rule, I64, CheckerOption};
+use persistent::Read;
+use router::Router;
+use rustc_serialize::json::{Object, Json, ToJson};
+use base::util;
+use base::constant;
+use repo::{Repos, ArticleFilter, Moderation};
+
+pub fn index(req: &mut Request) -> IronResult<Response> {
+    let mut validator = Validator::new();
+    validator.check(Checker::new("page", "页码", I64)
+                    .meet(Rule::Min(1))
+                    .set(CheckerOption::Optional(true)));
+    validator.validate(&req.get::<UrlEncodedQuery>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return not_found_response();
+    }
+
+    let page = match validator.get_optional("page") {
+        Some(p) => p.as_i64().unwrap(),
+        None => 1,
+    } as usize;
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let count = app_try!(repos.articles.count(&ArticleFilter::All));
+    let page_count = (count + constant::PAGE_SIZE - 1) / constant::PAGE_SIZE;
+
+    // same order as the home page, so moderators see what members see
+    let articles = app_try!(repos.articles.list(&ArticleFilter::All, Some(page)));
+
+    let articles: Vec<Json> = articles.into_iter().map(|article| {
+        let mut object = Object::new();
+        object.insert("id".to_owned(), article.id.to_json());
+        object.insert("category".to_owned(), article.category.to_json());
+        object.insert("categories".to_owned(),
+                      util::gen_categories_json(Some(article.category.value)));
+        object.insert("title".to_owned(), article.title.to_json());
+        object.insert("is_top".to_owned(),
+                      (article.flag & constant::ARTICLE::FLAG::TOP > 0).to_json());
+        object.insert("is_essence".to_owned(),
+                      (article.flag & constant::ARTICLE::FLAG::ESSENCE > 0).to_json());
+        object.insert("priority".to_owned(), article.priority.to_json());
+        object.insert("update_time".to_owned(), article.update_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("user_id".to_owned(), article.user.id.to_json());
+        object.insert("username".to_owned(), article.user.username.to_json());
+        object.to_json()
+    }).collect();
+
+    let mut data = ResponseData::new(req);
+    data.insert("articles", articles.to_json());
+    data.insert("show_pagination", (page_count > 1).to_json());
+    data.insert("pages", util::gen_pages_json(page_count, page));
+    temp_response("admin/index", &data)
+}
+
+pub fn set_top(req: &mut Request) -> IronResult<Response> {
+    set_flag(req, constant::ARTICLE::FLAG::TOP)
+}
+
+pub fn set_essence(req: &mut Request) -> IronResult<Response> {
+    set_flag(req, constant::ARTICLE::FLAG::ESSENCE)
+}
+
+pub fn set_priority(req: &mut Request) -> IronResult<Response> {
+    let article_id = try!(get_article_id(req));
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("priority", "优先级", I64)
+                    .meet(Rule::Min(-128))
+                    .meet(Rule::Max(127)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let priority = validator.get_required("priority").as_i64().unwrap();
+    update_article(req, Moderation::Priority(priority as i8), article_id)
+}
+
+pub fn set_category(req: &mut Request) -> IronResult<Response> {
+    let article_id = try!(get_article_id(req));
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("category", "类别", I64)
+                    .meet(Rule::Lambda(Box::new(|v| {
+                        constant::CATEGORY::ALL.iter().any(
+                            |c|*c as i64 == v.as_i64().unwrap())
+                    }), None)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let category = validator.get_required("category").as_i64().unwrap();
+    update_article(req, Moderation::Category(category as i8), article_id)
+}
+
+fn set_flag(req: &mut Request, flag: u8) -> IronResult<Response> {
+    let article_id = try!(get_article_id(req));
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("on", "开关", I64)
+                    .meet(Rule::Min(0))
+                    .meet(Rule::Max(1)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let on = validator.get_required("on").as_i64().unwrap() == 1;
+    update_article(req, Moderation::Flag(flag, on), article_id)
+}
+
+fn get_article_id(req: &mut Request) -> IronResult<u64> {
+    req.extensions.get::<Router>().unwrap()
+        .find("article_id").unwrap()
+        .parse::<u64>().map_err(|_| not_found_response().unwrap_err())
+}
+
+// moderating an article does not bump its update_time, so it keeps its
+// place on the home page
+fn update_article(req: &mut Request, moderation: Moderation,
+                  article_id: u64) -> IronResult<Response> {
+    let repos = req.get::<Read<Repos>>().unwrap();
+    if !app_try!(repos.articles.moderate(article_id, &moderation)) {
+        return json_error_response("话题不存在");
+    }
+
+    json_ok_response()
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::error::Error;
+use iron::prelude::*;
+use iron::AfterMiddleware;
+use iron::status;
+use rustc_serialize::json::{Object, Json, ToJson, encode};
+use urlencoded::{UrlEncodedBody, UrlEncodedQuery};
+use base::config::Config;
+use form_checker::{Validator, Checker, Rule, Str, I64, CheckerOption};
+use base::framework::{LoginUser, ActionError, current_user, not_found_response};
+use persistent::Read;
+use router::Router;
+use base::model::{Article, Comment};
+use base::util::render_html;
+use base::constant;
+use base::error::{self, AppError};
+use handlers::{article, comment};
+use repo::{Repos, ArticleFilter, MessageFilter};
+
+// Every api response is a json object, `{"data": ...}` on success and
+// `{"error": "..."}` otherwise, with a matching http status code. See API.md.
+pub struct JsonErrors;
+
+impl AfterMiddleware for JsonErrors {
+    // errors raised before the handler (404, csrf, bad tokens) come without
+    // a json body
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        if err.response.status == Some(status::InternalServerError) {
+            error::log_error(req, &err);
+        }
+        let mut resp = err.response;
+        let message = match resp.status {
+            Some(status::NotFound) => "not found".to_owned(),
+            Some(status::Forbidden) => "forbidden".to_owned(),
+            _ => err.error.description().to_owned(),
+        };
+        let mut object = Object::new();
+        object.insert("error".to_owned(), message.to_json());
+        resp.set_mut(mime!(Application/Json)).set_mut(encode(&object).unwrap());
+        Ok(resp)
+    }
+}
+
+pub fn list_articles(req: &mut Request) -> IronResult<Response> {
+    let mut validator = Validator::new();
+    validator
+        .check(Checker::new("page", "页码", I64)
+               .meet(Rule::Min(1))
+               .set(CheckerOption::Optional(true)))
+        .check(Checker::new("category", "类别", I64)
+               .meet(Rule::Lambda(Box::new(|v| {
+                   constant::CATEGORY::ALL.iter().any(
+                       |c|*c as i64 == v.as_i64().unwrap())
+               }), None))
+               .set(CheckerOption::Optional(true)));
+    validator.validate(&req.get::<UrlEncodedQuery>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return api_error(status::BadRequest, &validator.get_some_error());
+    }
+
+    let page = match validator.get_optional("page") {
+        Some(p) => p.as_i64().unwrap(),
+        None => 1,
+    } as usize;
+    let category = validator.get_optional("category").map(|c| c.as_i64().unwrap() as i8);
+
+    let filter = match category {
+        Some(category) => ArticleFilter::Category(category),
+        None => ArticleFilter::All,
+    };
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let count = app_try!(repos.articles.count(&filter));
+    let page_count = (count + constant::PAGE_SIZE - 1) / constant::PAGE_SIZE;
+    let articles = app_try!(repos.articles.list(&filter, Some(page)));
+
+    let mut object = Object::new();
+    object.insert("articles".to_owned(),
+                  Json::Array(articles.iter().map(article_json).collect()));
+    object.insert("page".to_owned(), page.to_json());
+    object.insert("page_count".to_owned(), page_count.to_json());
+    api_response(status::Ok, object.to_json())
+}
+
+pub fn create_article(req: &mut Request) -> IronResult<Response> {
+    let user = match api_user(req, constant::ACCESS_TOKEN::SCOPE::WRITE_ARTICLES) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+
+    let form = match article::check_form(req) {
+        Ok(form) => form,
+        Err(message) => return api_error(status::BadRequest, &message),
+    };
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let article_id = app_try!(repos.articles.create(user.id, &form));
+
+    let mut object = Object::new();
+    object.insert("id".to_owned(), article_id.to_json());
+    api_response(status::Created, object.to_json())
+}
+
+pub fn show_article(req: &mut Request) -> IronResult<Response> {
+    let article_id = try!(get_id(req, "article_id"));
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let article = match app_try!(repos.articles.find(article_id)) {
+        Some(article) => article,
+        None => return api_error(status::NotFound, "话题不存在"),
+    };
+    if article.status != constant::ARTICLE::STATUS::NORMAL {
+        return api_error(status::NotFound, "话题不存在");
+    }
+
+    api_response(status::Ok, article_json(&article))
+}
+
+pub fn edit_article(req: &mut Request) -> IronResult<Response> {
+    let user = match api_user(req, constant::ACCESS_TOKEN::SCOPE::WRITE_ARTICLES) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+
+    let article_id = try!(get_id(req, "article_id"));
+
+    let form = match article::check_form(req) {
+        Ok(form) => form,
+        Err(message) => return api_error(status::BadRequest, &message),
+    };
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    match article::update(&repos, &user, article_id, &form) {
+        Ok(()) => api_response(status::Ok, Object::new().to_json()),
+        Err(err) => action_error(err),
+    }
+}
+
+pub fn list_comments(req: &mut Request) -> IronResult<Response> {
+    let article_id = try!(get_id(req, "article_id"));
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    match app_try!(repos.articles.find(article_id)) {
+        Some(ref article) if article.status == constant::ARTICLE::STATUS::NORMAL => {},
+        _ => return api_error(status::NotFound, "话题不存在"),
+    }
+
+    // deleted comments are kept so that replies can still point at them
+    let comments = app_try!(repos.comments.list_by_article(article_id));
+    let comments: Vec<Json> = comments.into_iter().map(|mut comment| {
+        if comment.status == constant::COMMENT::STATUS::DELETED {
+            comment.content = String::new();
+        }
+        let parent_id = comment.parent.take().map(|parent| parent.id);
+        comment_json(&comment, parent_id)
+    }).collect();
+
+    let mut object = Object::new();
+    object.insert("comments".to_owned(), comments.to_json());
+    api_response(status::Ok, object.to_json())
+}
+
+pub fn create_comment(req: &mut Request) -> IronResult<Response> {
+    let user = match api_user(req, constant::ACCESS_TOKEN::SCOPE::WRITE_COMMENTS) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+
+    let article_id = try!(get_id(req, "article_id"));
+
+    let mut validator = Validator::new();
+    validator
+        .check(Checker::new("content", "内容", Str).meet(Rule::Min(7)))
+        .check(Checker::new("parent_id", "回复的评论", I64)
+               .meet(Rule::Min(1))
+               .set(CheckerOption::Optional(true)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return api_error(status::BadRequest, &validator.get_some_error());
+    }
+
+    let content = validator.get_required("content").as_str().unwrap();
+    let raw_parent_id = validator.get_optional("parent_id")
+        .map(|v| v.as_i64().unwrap() as u64);
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let config = req.get::<Read<Config>>().unwrap();
+    let app_path = &config.app_path;
+
+    match comment::create(&repos, &user, article_id, raw_parent_id, &content, app_path) {
+        Ok(comment_id) => {
+            let mut object = Object::new();
+            object.insert("id".to_owned(), comment_id.to_json());
+            api_response(status::Created, object.to_json())
+        }
+        Err(err) => action_error(err),
+    }
+}
+
+pub fn show_user(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(get_id(req, "user_id"));
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let user = match app_try!(repos.users.find(user_id)) {
+        Some(user) => user,
+        None => return api_error(status::NotFound, "用户不存在"),
+    };
+
+    let articles_count = app_try!(repos.articles.count(&ArticleFilter::User(user_id)));
+    let comments_count = app_try!(repos.comments.count_by_user(user_id));
+
+    let mut object = Object::new();
+    object.insert("user".to_owned(), public_json(user.to_json()));
+    object.insert("articles_count".to_owned(), articles_count.to_json());
+    object.insert("comments_count".to_owned(), comments_count.to_json());
+    api_response(status::Ok, object.to_json())
+}
+
+// unread messages of the current user, oldest first; they stay unread
+// until the client calls `read_messages`
+pub fn list_messages(req: &mut Request) -> IronResult<Response> {
+    let user = match api_user(req, constant::ACCESS_TOKEN::SCOPE::READ) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let filter = MessageFilter {
+        to_user_id: user.id,
+        mode: None,
+        unread_only: true,
+    };
+    let mut messages = app_try!(repos.messages.list(&filter, None));
+    messages.reverse();
+
+    let messages: Vec<Json> = messages.into_iter().map(|message| {
+        let mut object = Object::new();
+        object.insert("id".to_owned(), message.id.to_json());
+        object.insert("mode".to_owned(), (match message.mode {
+            constant::MESSAGE::MODE::MENTION => "mention",
+            constant::MESSAGE::MODE::REPLY_COMMENT => "reply_comment",
+            _ => "reply_article",
+        }).to_json());
+        object.insert("create_time".to_owned(), message.create_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("comment_id".to_owned(), message.comment.id.to_json());
+        object.insert("content_html".to_owned(),
+                      render_html(&message.comment.content).to_json());
+        object.insert("from_user".to_owned(), public_json(message.from_user.to_json()));
+        object.insert("article_id".to_owned(), message.article.id.to_json());
+        object.insert("article_title".to_owned(), message.article.title.to_json());
+        object.to_json()
+    }).collect();
+
+    let mut object = Object::new();
+    object.insert("messages".to_owned(), messages.to_json());
+    api_response(status::Ok, object.to_json())
+}
+
+// feeds the unread badge of the site's pages
+pub fn unread_messages_count(req: &mut Request) -> IronResult<Response> {
+    let user = match api_user(req, constant::ACCESS_TOKEN::SCOPE::READ) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let count = app_try!(repos.messages.count_unread(user.id));
+
+    let mut object = Object::new();
+    object.insert("count".to_owned(), count.to_json());
+    api_response(status::Ok, object.to_json())
+}
+
+// marks unread messages up to `last_id` as read, so messages arriving
+// after the client listed them are not lost
+pub fn read_messages(req: &mut Request) -> IronResult<Response> {
+    let user = match api_user(req, constant::ACCESS_TOKEN::SCOPE::READ) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("last_id", "消息ID", I64).meet(Rule::Min(1)));
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return api_error(status::BadRequest, &validator.get_some_error());
+    }
+
+    let last_id = validator.get_required("last_id").as_i64().unwrap();
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(repos.messages.mark_read(user.id, Some(last_id as u64)));
+
+    api_response(status::Ok, Object::new().to_json())
+}
+
+fn api_response(code: status::Status, data: Json) -> IronResult<Response> {
+    let mut object = Object::new();
+    object.insert("data".to_owned(), data);
+    let mut resp = Response::new();
+    resp.set_mut(mime!(Application/Json)).set_mut(encode(&object).unwrap()).set_mut(code);
+    Ok(resp)
+}
+
+fn api_error(code: status::Status, message: &str) -> IronResult<Response> {
+    let mut object = Object::new();
+    object.insert("error".to_owned(), message.to_json());
+    let mut resp = Response::new();
+    resp.set_mut(mime!(Application/Json)).set_mut(encode(&object).unwrap()).set_mut(code);
+    Ok(resp)
+}
+
+// the current user, provided their token, if any, grants `scope`
+fn api_user(req: &mut Request, scope: u8) -> Result<LoginUser, IronResult<Response>> {
+    match current_user(req) {
+        Some(ref user) if !user.has_scope(scope) => {
+            Err(api_error(status::Forbidden, "令牌没有此权限"))
+        }
+        Some(user) => Ok(user),
+        None => Err(api_error(status::Unauthorized, "请先登录")),
+    }
+}
+
+fn action_error(err: ActionError) -> IronResult<Response> {
+    let code = match err {
+        ActionError::NotFound => status::NotFound,
+        ActionError::Forbidden => status::Forbidden,
+        ActionError::Invalid(_) => status::BadRequest,
+        // logged by `JsonErrors`
+        ActionError::Db(e) => return Err(AppError::from(e).into()),
+    };
+    api_error(code, &err.message())
+}
+
+fn get_id(req: &mut Request, name: &str) -> IronResult<u64> {
+    req.extensions.get::<Router>().unwrap()
+        .find(name).unwrap()
+        .parse::<u64>().map_err(|_| not_found_response().unwrap_err())
+}
+
+// the markdown source is `content`, and `content_html` is what the site shows
+fn article_json(article: &Article) -> Json {
+    let mut json = public_json(article.to_json());
+    if let Json::Object(ref mut object) = json {
+        object.remove("comments");
+        object.insert("content_html".to_owned(), render_html(&article.content).to_json());
+    }
+    json
+}
+
+fn comment_json(comment: &Comment, parent_id: Option<u64>) -> Json {
+    let mut json = public_json(comment.to_json());
+    if let Json::Object(ref mut object) = json {
+        // these describe the web page's buttons, not the comment
+        for key in &["source", "can_edit", "can_delete", "article", "parent"] {
+            object.remove(*key);
+        }
+        object.insert("parent_id".to_owned(), parent_id.to_json());
+        object.insert("content_html".to_owned(), render_html(&comment.content).to_json());
+    }
+    json
+}
+
+// email addresses are never exposed through the api
+fn public_json(json: Json) -> Json {
+    match json {
+        Json::Object(object) => Json::Object(object.into_iter()
+            .filter(|&(ref key, _)| key != "email")
+            .map(|(key, value)| (key, public_json(value)))
+            .collect()),
+        Json::Array(array) => Json::Array(array.into_iter().map(public_json).collect()),
+        other => other,
+    }
+}
@@ -2,22 +2,24 @@ use std::collections::HashMap;
 use iron::prelude::*;
 use base::framework::{ResponseData, temp_response,
                       json_error_response, json_ok_response,
-                      not_found_response};
+                      not_found_response, ActionError};
 use urlencoded::UrlEncodedBody;
-use base::db::MyPool;
-use form_checker::{Validator, Checker, Rule, Str, I64};
+use form_checker::{Validator, Checker, Rule, Str, I64, CheckerOption};
 use base::framework::LoginUser;
 use base::util::render_html;
 use iron_login::User as U;
 use persistent::Read;
 use chrono::*;
 use router::Router;
-use mysql as my;
-use base::model::{Article, User, Category, Comment};
+use base::model::{User, Comment, ArticleForm};
 use rustc_serialize::json::ToJson;
 use base::util;
-use base::util::gen_gravatar_url;
 use base::constant;
+use base::config::Config;
+use base::error::AppError;
+use handlers::comment;
+use handlers::tag;
+use repo::{Change, Repos};
 
 pub fn new_load(req: &mut Request) -> IronResult<Response> {
     let mut data = ResponseData::new(req);
@@ -26,37 +28,16 @@ pub fn new_load(req: &mut Request) -> IronResult<Response> {
 }
 
 pub fn new(req: &mut Request) -> IronResult<Response> {
-    let mut validator = Validator::new();
-    validator
-        .check(Checker::new("category", "类别", I64)
-               .meet(Rule::Lambda(Box::new(|v| {
-                   constant::CATEGORY::ALL.iter().any(
-                       |c|*c as i64 == v.as_i64().unwrap())
-               }), None)))
-        .check(Checker::new("title", "标题", Str)
-               .meet(Rule::Min(3))
-               .meet(Rule::Max(64)))
-        .check(Checker::new("content", "内容", Str)
-               .meet(Rule::Min(7)));
-
-    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
-    if !validator.is_valid() {
-        return json_error_response(&validator.get_some_error());
-    }
+    let form = match check_form(req) {
+        Ok(form) => form,
+        Err(message) => return json_error_response(&message),
+    };
 
-    let category = validator.get_required("category").as_i64().unwrap();
-    let title = validator.get_required("title").as_str().unwrap();
-    let content = validator.get_required("content").as_str().unwrap();
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
+    let repos = req.get::<Read<Repos>>().unwrap();
     let login = LoginUser::get_login(req);
     let user = login.get_user().unwrap();
 
-    let now = Local::now().naive_local();
-    let mut stmt = pool.prepare("INSERT INTO article(category, title, content, \
-                                 user_id, create_time, update_time) \
-                                 VALUES (?, ?, ?, ?, ?, ?)").unwrap();
-    let result = stmt.execute((category, title, content, user.id, now, now));
-    result.unwrap();
+    app_try!(repos.articles.create(user.id, &form));
     json_ok_response()
 }
 
@@ -65,65 +46,31 @@ pub fn show(req: &mut Request) -> IronResult<Response> {
                        .find("article_id").unwrap()
                        .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-    let mut result = pool.prep_exec(
-        "SELECT a.id, a.category, a.title, a.content, a.comments_count, \
-         a.create_time, u.id as user_id, u.username, u.email from article \
-         as a join user as u on a.user_id=u.id where a.id=? and a.status=?",
-        (&article_id, constant::ARTICLE::STATUS::NORMAL)).unwrap();
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let mut article = match app_try!(repos.articles.find(article_id)) {
+        Some(article) => article,
+        None => return not_found_response(),
+    };
 
-    let raw_row = result.next();
-    if raw_row.is_none() {
-        return not_found_response();
+    let raw_login_user = LoginUser::get_login(req).get_user();
+
+    if article.status == constant::ARTICLE::STATUS::DELETED {
+        let mut data = ResponseData::new(req);
+        data.insert("article_id", article.id.to_json());
+        data.insert("can_manage", can_manage_article(&raw_login_user, article.user.id).to_json());
+        return temp_response("article/removed", &data);
     }
-    let row = raw_row.unwrap().unwrap();
-    let (id, category, title, content, comments_count, create_time, user_id, username, email) = my::from_row::<(_,_,_,_,_,_,_,_,String)>(row);
-    let mut article = Article {
-        id: id,
-        category: Category::from_value(category),
-        title: title,
-        content: content,
-        comments_count: comments_count,
-        user: User{
-            id: user_id,
-            avatar: gen_gravatar_url(&email),
-            username: username,
-            email: email,
-            create_time: *constant::DEFAULT_DATETIME,
-        },
-        create_time: create_time,
-        update_time: *constant::DEFAULT_DATETIME,
-        flag: 0,
-        comments: Vec::new(),
-    };
+
     article.content = render_html(&article.content);
 
-    let result = pool.prep_exec(
-        "SELECT c.id, c.content, c.create_time, u.id as user_id, \
-         u.username, u.email from comment \
-         as c join user as u on c.user_id=u.id where c.article_id=? \
-         order by c.create_time", (&article_id, )).unwrap();
-
-    article.comments = result.map(|x| x.unwrap()).map(|row|{
-        let (id, content, create_time, user_id, username, email) = my::from_row::<(_,String,_,_,_,String)>(row);
-        Comment {
-            id: id,
-            content: render_html(&content),
-            user: User {
-                id: user_id,
-                avatar: gen_gravatar_url(&email),
-                username: username,
-                email: email,
-                create_time: *constant::DEFAULT_DATETIME,
-            },
-            create_time: create_time,
-            article: None,
-        }
-    }).collect();
+    let edit_minutes = req.get::<Read<Config>>().unwrap().comment_edit_minutes;
+    let comments = app_try!(repos.comments.list_by_article(article_id));
+    article.comments = prepare_comments(comments, &raw_login_user, edit_minutes,
+                                        Local::now().naive_local());
 
     // judge whether is my article
     let mut is_my_own = false;
-    let raw_login_user = LoginUser::get_login(req).get_user();
+    let can_manage = can_manage_article(&raw_login_user, article.user.id);
     if let Some(login_user) = raw_login_user {
         if login_user.id == article.user.id {
             is_my_own = true;
@@ -132,13 +79,66 @@ pub fn show(req: &mut Request) -> IronResult<Response> {
 
     let mut data = ResponseData::new(req);
     data.insert("article", article.to_json());
-    data.insert("comments_count", article.comments.len().to_json());
+    data.insert("comments_count", article.comments.iter().filter(
+        |c| c.status == constant::COMMENT::STATUS::NORMAL).count().to_json());
     let mentions: Vec<String> = article.comments.into_iter().map(|c|c.user.username).collect();
     data.insert("mentions", mentions.to_json());
     data.insert("is_my_own", is_my_own.to_json());
+    data.insert("can_manage", can_manage.to_json());
     temp_response("article/show", &data)
 }
 
+// Turns an article's comments into what its page shows. Replies are shown
+// flat in time order, quoting the comment they reply to, which always comes
+// earlier.
+pub fn prepare_comments(comments: Vec<Comment>, raw_login_user: &Option<LoginUser>,
+                        edit_minutes: i64, now: NaiveDateTime) -> Vec<Comment> {
+    let mut quotes: HashMap<u64, (u64, String, i8, String)> = HashMap::new();
+    let mut prepared = Vec::new();
+
+    for comment in comments {
+        let is_deleted = comment.status == constant::COMMENT::STATUS::DELETED;
+        let html = if is_deleted { String::new() } else { render_html(&comment.content) };
+
+        let parent = comment.parent.and_then(|parent| quotes.get(&parent.id).map(
+            |&(user_id, ref username, status, ref html)| {
+                let mut user = User::default();
+                user.id = user_id;
+                user.username = username.clone();
+                Box::new(Comment {
+                    id: parent.id,
+                    user: user,
+                    content: html.clone(),
+                    status: status,
+                    ..Default::default()
+                })
+            }));
+
+        let (is_author, is_moderator) = match *raw_login_user {
+            Some(ref login_user) => (login_user.id == comment.user.id, login_user.is_moderator()),
+            None => (false, false),
+        };
+        let in_edit_window = now - comment.create_time <= Duration::minutes(edit_minutes);
+
+        quotes.insert(comment.id, (comment.user.id, comment.user.username.clone(),
+                                   comment.status, html.clone()));
+
+        prepared.push(Comment {
+            content: html,
+            parent: parent,
+            source: if !is_deleted && is_author && in_edit_window {
+                Some(comment::unlink_mentions(&comment.content))
+            } else {
+                None
+            },
+            can_delete: !is_deleted && (is_moderator || (is_author && in_edit_window)),
+            ..comment
+        });
+    }
+
+    prepared
+}
+
 pub fn edit_load(req: &mut Request) -> IronResult<Response> {
     let login = LoginUser::get_login(req);
     let user = login.get_user().unwrap();
@@ -147,48 +147,19 @@ pub fn edit_load(req: &mut Request) -> IronResult<Response> {
                        .find("article_id").unwrap()
                        .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-    let mut result = pool.prep_exec(
-        "SELECT a.id, a.category, a.title, a.content, a.comments_count, \
-         a.create_time, u.id as user_id, u.username, u.email from article \
-         as a join user as u on a.user_id=u.id where a.id=? and a.status=?",
-        (&article_id, constant::ARTICLE::STATUS::NORMAL)).unwrap();
-
-    let raw_row = result.next();
-    if raw_row.is_none() {
-        return not_found_response();
-    }
-    let row = raw_row.unwrap().unwrap();
-    let (id, category, title, content, comments_count,
-         create_time, user_id, username, email) = my::from_row::<
-            (_,_,_,_,_,_,_,_,String)>(row);
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let article = match app_try!(repos.articles.find(article_id)) {
+        Some(article) => article,
+        None => return not_found_response(),
+    };
 
-    if user_id != user.id {
+    if article.status != constant::ARTICLE::STATUS::NORMAL || article.user.id != user.id {
         return not_found_response();
     }
 
-    let article = Article {
-        id: id,
-        category: Category::from_value(category),
-        title: title,
-        content: content,
-        comments_count: comments_count,
-        user: User{
-            id: user_id,
-            avatar: gen_gravatar_url(&email),
-            username: username,
-            email: email,
-            create_time: *constant::DEFAULT_DATETIME,
-        },
-        create_time: create_time,
-        update_time: *constant::DEFAULT_DATETIME,
-        flag: 0,
-        comments: Vec::new(),
-    };
-
     let mut data = ResponseData::new(req);
 
-    data.insert("categories", util::gen_categories_json(Some(category)));
+    data.insert("categories", util::gen_categories_json(Some(article.category.value)));
     data.insert("article", article.to_json());
     temp_response("article/edit_load", &data)
 }
@@ -201,6 +172,21 @@ pub fn edit(req: &mut Request) -> IronResult<Response> {
                        .find("article_id").unwrap()
                        .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
 
+    let form = match check_form(req) {
+        Ok(form) => form,
+        Err(message) => return json_error_response(&message),
+    };
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    match update(&repos, &user, article_id, &form) {
+        Ok(()) => json_ok_response(),
+        Err(ActionError::NotFound) => not_found_response(),
+        Err(ActionError::Db(e)) => Err(AppError::from(e).into()),
+        Err(err) => json_error_response(&err.message()),
+    }
+}
+
+pub fn check_form(req: &mut Request) -> Result<ArticleForm, String> {
     let mut validator = Validator::new();
     validator
         .check(Checker::new("category", "类别", I64)
@@ -212,47 +198,75 @@ pub fn edit(req: &mut Request) -> IronResult<Response> {
                .meet(Rule::Min(3))
                .meet(Rule::Max(64)))
         .check(Checker::new("content", "内容", Str)
-               .meet(Rule::Min(7)));
+               .meet(Rule::Min(7)))
+        .check(Checker::new("tags", "标签", Str)
+               .set(CheckerOption::Optional(true)));
 
-    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    let body = req.get::<UrlEncodedBody>().unwrap_or(HashMap::new());
+    validator.validate(&body);
     if !validator.is_valid() {
-        return json_error_response(&validator.get_some_error());
+        return Err(validator.get_some_error());
     }
 
-    let category = validator.get_required("category").as_i64().unwrap();
-    let title = validator.get_required("title").as_str().unwrap();
-    let content = validator.get_required("content").as_str().unwrap();
-    let now = Local::now().naive_local();
+    // an empty field clears the tags, a missing one keeps them
+    let tags = match body.get("tags").and_then(|values| values.first()) {
+        Some(raw) => Some(try!(tag::parse_tags(raw))),
+        None => None,
+    };
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-    let mut trans = pool.start_transaction(false, None, None).unwrap();
+    Ok(ArticleForm {
+        category: validator.get_required("category").as_i64().unwrap(),
+        title: validator.get_required("title").as_str().unwrap().to_owned(),
+        content: validator.get_required("content").as_str().unwrap().to_owned(),
+        tags: tags,
+    })
+}
 
-    {
-        let mut result = trans.prep_exec(
-            "SELECT u.id as user_id from article \
-             as a join user as u on a.user_id=u.id \
-             where a.id=? and a.status=? for update",
-            (article_id, constant::ARTICLE::STATUS::NORMAL)).unwrap();
+// only the author may edit an article
+pub fn update(repos: &Repos, user: &LoginUser, article_id: u64,
+              form: &ArticleForm) -> Result<(), ActionError> {
+    match try!(repos.articles.update(article_id, user.id, form)) {
+        Change::Done => Ok(()),
+        Change::NotFound => Err(ActionError::NotFound),
+        Change::Forbidden => Err(ActionError::Forbidden),
+    }
+}
 
-        let raw_row = result.next();
-        if raw_row.is_none() {
-            return not_found_response();
-        }
+pub fn delete(req: &mut Request) -> IronResult<Response> {
+    set_status(req, constant::ARTICLE::STATUS::NORMAL, constant::ARTICLE::STATUS::DELETED)
+}
 
-        let row = raw_row.unwrap().unwrap();
-        let user_id = my::from_row::<u64>(row);
+pub fn restore(req: &mut Request) -> IronResult<Response> {
+    set_status(req, constant::ARTICLE::STATUS::DELETED, constant::ARTICLE::STATUS::NORMAL)
+}
 
-        if user_id != user.id {
-            return json_error_response("非法请求");
-        }
-    }
+fn set_status(req: &mut Request, from_status: i8, to_status: i8) -> IronResult<Response> {
+    let login = LoginUser::get_login(req);
+    let user = login.get_user();
 
-    trans.prep_exec("UPDATE article set category=?, title=?, content=?, \
-                     update_time=? where id=?",
-                    (category, title, content, now, article_id)).unwrap();
+    let article_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("article_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
 
+    // moderators may change anybody's
+    let author_id = match user {
+        Some(ref user) if user.is_moderator() => None,
+        Some(ref user) => Some(user.id),
+        None => return json_error_response("非法请求"),
+    };
 
-    trans.commit().unwrap();
+    let repos = req.get::<Read<Repos>>().unwrap();
+    match app_try!(repos.articles.set_status(article_id, author_id, from_status, to_status)) {
+        Change::Done => json_ok_response(),
+        Change::NotFound => not_found_response(),
+        Change::Forbidden => json_error_response("非法请求"),
+    }
+}
 
-    json_ok_response()
+// authors and moderators may delete or restore an article
+fn can_manage_article(raw_login_user: &Option<LoginUser>, author_id: u64) -> bool {
+    match *raw_login_user {
+        Some(ref login_user) => login_user.id == author_id || login_user.is_moderator(),
+        None => false,
+    }
 }
@@ -1,25 +1,31 @@
 use std::collections::HashMap;
 use iron::prelude::*;
 use base::framework::{json_error_response, json_ok_response,
-                      not_found_response};
+                      not_found_response, ActionError};
 use urlencoded::UrlEncodedBody;
-use base::db::MyPool;
-use form_checker::{Validator, Checker, Rule, Str, I64};
+use base::db::DbError;
+use form_checker::{Validator, Checker, Rule, Str, I64, CheckerOption};
 use base::framework::LoginUser;
 use iron_login::User as U;
 use persistent::Read;
 use chrono::*;
-use mysql as my;
 use regex::Regex;
 use regex::Captures;
+use router::Router;
 use base::config::Config;
 use base::constant;
+use base::error::AppError;
+use base::model::Comment;
+use repo::{Repos, NewComment};
 
 pub fn new(req: &mut Request) -> IronResult<Response> {
     let mut validator = Validator::new();
     validator
         .check(Checker::new("article_id", "文章ID", I64).meet(Rule::Min(1)))
-        .check(Checker::new("content", "内容", Str).meet(Rule::Min(7)));
+        .check(Checker::new("content", "内容", Str).meet(Rule::Min(7)))
+        .check(Checker::new("parent_id", "回复的评论", I64)
+               .meet(Rule::Min(1))
+               .set(CheckerOption::Optional(true)));
 
     validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
     if !validator.is_valid() {
@@ -27,82 +33,207 @@ pub fn new(req: &mut Request) -> IronResult<Response> {
     }
 
     let article_id = validator.get_required("article_id").as_i64().unwrap() as u64;
+    let raw_parent_id = validator.get_optional("parent_id")
+        .map(|v| v.as_i64().unwrap() as u64);
+    let content = validator.get_required("content").as_str().unwrap();
+
+    let login = LoginUser::get_login(req);
+    let user = login.get_user().unwrap();
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let config = req.get::<Read<Config>>().unwrap();
+    let app_path = &config.app_path;
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-    let mut trans = pool.start_transaction(false, None, None).unwrap();
+    match create(&repos, &user, article_id, raw_parent_id, &content, app_path) {
+        Ok(_) => json_ok_response(),
+        Err(ActionError::NotFound) => not_found_response(),
+        Err(ActionError::Db(e)) => Err(AppError::from(e).into()),
+        Err(err) => json_error_response(&err.message()),
+    }
+}
 
+// saves a new comment and notifies the people it concerns, returns its id
+pub fn create(repos: &Repos, user: &LoginUser, article_id: u64,
+              raw_parent_id: Option<u64>, content: &str,
+              app_path: &str) -> Result<u64, ActionError> {
     // check whether article exists
-    let raw_row = trans.prep_exec("SELECT user_id from article where id=? for update",
-                                  (&article_id,)).unwrap().next();
-    if raw_row.is_none() {
-            return not_found_response();
+    let article_user_id = match try!(repos.articles.find(article_id)) {
+        Some(ref article) if article.status == constant::ARTICLE::STATUS::NORMAL => {
+            article.user.id
+        },
+        _ => return Err(ActionError::NotFound),
     };
 
-    let article_user_id: u64 = my::from_row(raw_row.unwrap().unwrap());
+    // the replied comment must belong to the same article
+    let mut raw_parent_user_id: Option<u64> = None;
+    if let Some(parent_id) = raw_parent_id {
+        let parent = try!(repos.comments.find(parent_id));
+        match parent {
+            Some(ref parent) if parent.status == constant::COMMENT::STATUS::NORMAL &&
+                parent.article.as_ref().map(|a| a.id) == Some(article_id) => {
+                raw_parent_user_id = Some(parent.user.id);
+            },
+            _ => return Err(ActionError::Invalid("回复的评论不存在".to_owned())),
+        }
+    }
+
+    let (new_content, mut mentions) = try!(link_mentions(repos, content, app_path));
+    let mut notify = Vec::new();
+
+    // send message to the replied comment's author
+    if let Some(parent_user_id) = raw_parent_user_id {
+        if parent_user_id != user.id {
+            notify.push((parent_user_id, constant::MESSAGE::MODE::REPLY_COMMENT));
+        }
+    }
+
+    // send message to article's author, unless already told above
+    if article_user_id != user.id && raw_parent_user_id != Some(article_user_id) {
+        notify.push((article_user_id, constant::MESSAGE::MODE::REPLY_ARTICLE));
+    }
+
+    // send message to mentions
+    mentions.sort();
+    mentions.dedup();
+    for &mention in mentions.iter().filter(|&x| *x != article_user_id && *x != user.id &&
+                                           Some(*x) != raw_parent_user_id) {
+        notify.push((mention, constant::MESSAGE::MODE::MENTION));
+    }
+
+    Ok(try!(repos.comments.create(&NewComment {
+        article_id: article_id,
+        user_id: user.id,
+        parent_id: raw_parent_id,
+        content: new_content,
+        notify: notify,
+    })))
+}
+
+pub fn edit(req: &mut Request) -> IronResult<Response> {
+    let comment_id = try!(get_comment_id(req));
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("content", "内容", Str).meet(Rule::Min(7)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
 
     let content = validator.get_required("content").as_str().unwrap();
     let login = LoginUser::get_login(req);
     let user = login.get_user().unwrap();
     let now = Local::now().naive_local();
+    let config = req.get::<Read<Config>>().unwrap();
+    let app_path = &config.app_path;
+    let edit_minutes = config.comment_edit_minutes;
 
-    // parse mentions such as @foo @bar
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let comment = match app_try!(find_comment(&repos, comment_id)) {
+        Some(comment) => comment,
+        None => return not_found_response(),
+    };
+
+    if comment.user.id != user.id {
+        return json_error_response("非法请求");
+    }
+
+    if now - comment.create_time > Duration::minutes(edit_minutes) {
+        return json_error_response(&format!("回复发表{}分钟后不能再编辑", edit_minutes));
+    }
+
+    // mentions are linked again, but only a new comment notifies them
+    let (new_content, _) = app_try!(link_mentions(&repos, &content, app_path));
+    app_try!(repos.comments.edit(comment_id, &new_content));
+
+    json_ok_response()
+}
+
+pub fn delete(req: &mut Request) -> IronResult<Response> {
+    let comment_id = try!(get_comment_id(req));
+
+    let login = LoginUser::get_login(req);
+    let user = login.get_user().unwrap();
+    let now = Local::now().naive_local();
+    let edit_minutes = req.get::<Read<Config>>().unwrap().comment_edit_minutes;
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let comment = match app_try!(find_comment(&repos, comment_id)) {
+        Some(comment) => comment,
+        None => return not_found_response(),
+    };
+
+    // moderators may delete any comment at any time
+    if !user.is_moderator() {
+        if comment.user.id != user.id {
+            return json_error_response("非法请求");
+        }
+
+        if now - comment.create_time > Duration::minutes(edit_minutes) {
+            return json_error_response(&format!("回复发表{}分钟后不能再删除", edit_minutes));
+        }
+    }
+
+    // nobody should be notified of a comment that is gone
+    app_try!(repos.comments.delete(comment_id));
+
+    json_ok_response()
+}
+
+fn get_comment_id(req: &mut Request) -> IronResult<u64> {
+    req.extensions.get::<Router>().unwrap()
+        .find("comment_id").unwrap()
+        .parse::<u64>().map_err(|_| not_found_response().unwrap_err())
+}
+
+// a normal comment, see `CommentRepo::find`
+fn find_comment(repos: &Repos, comment_id: u64) -> Result<Option<Comment>, DbError> {
+    match try!(repos.comments.find(comment_id)) {
+        Some(comment) => if comment.status == constant::COMMENT::STATUS::NORMAL {
+            Ok(Some(comment))
+        } else {
+            Ok(None)
+        },
+        None => Ok(None),
+    }
+}
+
+// parse mentions such as @foo @bar, and link them to the users' pages
+fn link_mentions(repos: &Repos, content: &str, app_path: &str)
+                 -> Result<(String, Vec<u64>), DbError> {
     let re = Regex::new(r"\B@([\da-zA-Z_]+)").unwrap();
-    let config = req.get::<Read<Config>>().unwrap();
-    let app_path = config.get("app_path").as_str().unwrap();
 
     let mut mentions: Vec<u64> = Vec::new();
-    let new_content = re.replace_all(&content, |cap: &Captures| {
-        match handle_mention(&mut trans, cap.at(1).unwrap()) {
-            Some(user_id) => {
-                mentions.push(user_id);
+    // the replacer can't return early, so the first error is kept here
+    let mut error = None;
+    let new_content = re.replace_all(content, |cap: &Captures| {
+        match repos.users.find_by_username(cap.at(1).unwrap()) {
+            Ok(Some(user)) => {
+                mentions.push(user.id);
                 format!("[@{}]({}{}{})",
                         cap.at(1).unwrap(),
                         app_path,
                         "/user/",
-                        user_id)
+                        user.id)
             },
-            None => format!("@{}", cap.at(1).unwrap()),
+            Ok(None) => format!("@{}", cap.at(1).unwrap()),
+            Err(e) => {
+                error = Some(e);
+                format!("@{}", cap.at(1).unwrap())
+            }
         }
     });
 
-    let comment_id = trans.prep_exec(
-        "INSERT INTO comment(article_id, user_id, content, create_time) \
-         VALUES (?, ?, ?, ?)",
-        (article_id, user.id, new_content, now)).unwrap().last_insert_id();
-
-    trans.prep_exec("UPDATE article set comments_count=comments_count+1, \
-                     update_time=? where id=?",
-                    (now, article_id)).unwrap();
-
-    // send message to article's author
-    if article_user_id != user.id {
-        trans.prep_exec("INSERT INTO message(article_id, comment_id, \
-                         from_user_id, to_user_id, mode, \
-                         status, create_time) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                        (article_id, comment_id, user.id, article_user_id,
-                         constant::MESSAGE::MODE::REPLY_ARTICLE,
-                         constant::MESSAGE::STATUS::INIT, now)).unwrap();
+    match error {
+        Some(e) => Err(e),
+        None => Ok((new_content, mentions)),
     }
-
-    // send message to mentions
-    mentions.sort();
-    mentions.dedup();
-    for mention in mentions.iter().filter(|&x| *x != article_user_id && *x != user.id) {
-        trans.prep_exec("INSERT INTO message(article_id, comment_id, \
-                         from_user_id, to_user_id, mode, \
-                         status, create_time) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                        (article_id, comment_id, user.id, mention,
-                         constant::MESSAGE::MODE::MENTION,
-                         constant::MESSAGE::STATUS::INIT, now)).unwrap();
-    }
-
-    trans.commit().unwrap();
-
-    json_ok_response()
 }
 
-fn handle_mention(trans: &mut my::Transaction, username: &str) -> Option<u64> {
+// the reverse of `link_mentions`, to get back what the user typed
+pub fn unlink_mentions(content: &str) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\[@([\da-zA-Z_]+)\]\([^)]*\)").unwrap();
+    }
 
-    trans.prep_exec("SELECT id from user where username=?", (username,)).unwrap()
-        .next().map(|row| my::from_row(row.unwrap()))
+    RE.replace_all(content, "@$1")
 }
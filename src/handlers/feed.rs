@@ -0,0 +1,145 @@
+use iron::prelude::*;
+use base::framework::not_found_response;
+use base::config::Config;
+use base::feed::{Feed, Entry, Format, feed_response};
+use persistent::Read;
+use router::Router;
+use base::constant;
+use base::model::{Article, Category};
+use repo::{Repos, ArticleFilter};
+
+pub fn rss(req: &mut Request) -> IronResult<Response> {
+    let feed = try!(site_feed(req, "/rss"));
+    feed_response(req, &feed, Format::Rss)
+}
+
+pub fn atom(req: &mut Request) -> IronResult<Response> {
+    let feed = try!(site_feed(req, "/feed.atom"));
+    feed_response(req, &feed, Format::Atom)
+}
+
+pub fn json(req: &mut Request) -> IronResult<Response> {
+    let feed = try!(site_feed(req, "/feed.json"));
+    feed_response(req, &feed, Format::Json)
+}
+
+pub fn category_rss(req: &mut Request) -> IronResult<Response> {
+    let category_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("category_id").unwrap()
+                       .parse::<i8>().map_err(|_| not_found_response().unwrap_err()));
+
+    if constant::CATEGORY::ALL.iter().find(|c|**c == category_id).is_none() {
+        return not_found_response();
+    }
+
+    let app_path = get_app_path(req);
+    let repos = req.get::<Read<Repos>>().unwrap();
+
+    let articles = app_try!(repos.articles.newest(&ArticleFilter::Category(category_id),
+                                                  constant::PAGE_SIZE));
+
+    let category = Category::from_value(category_id);
+    let feed = Feed {
+        title: format!("Rust China社区 - {}", category.title),
+        description: format!("Rust China社区{}版块最新发帖", category.title),
+        link: format!("{}/category/{}", app_path, category_id),
+        feed_url: format!("{}/category/{}/rss", app_path, category_id),
+        entries: article_entries(articles, &app_path),
+    };
+    feed_response(req, &feed, Format::Rss)
+}
+
+pub fn user_rss(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let app_path = get_app_path(req);
+    let repos = req.get::<Read<Repos>>().unwrap();
+
+    let username = match app_try!(repos.users.find(user_id)) {
+        Some(user) => user.username,
+        None => return not_found_response(),
+    };
+
+    let articles = app_try!(repos.articles.newest(&ArticleFilter::User(user_id),
+                                                  constant::PAGE_SIZE));
+
+    let feed = Feed {
+        title: format!("Rust China社区 - {}", username),
+        description: format!("{}在Rust China社区的最新发帖", username),
+        link: format!("{}/user/{}", app_path, user_id),
+        feed_url: format!("{}/user/{}/rss", app_path, user_id),
+        entries: article_entries(articles, &app_path),
+    };
+    feed_response(req, &feed, Format::Rss)
+}
+
+// the newest replies to one article
+pub fn article_rss(req: &mut Request) -> IronResult<Response> {
+    let article_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("article_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let app_path = get_app_path(req);
+    let repos = req.get::<Read<Repos>>().unwrap();
+
+    let title = match app_try!(repos.articles.find(article_id)) {
+        Some(ref article) if article.status == constant::ARTICLE::STATUS::NORMAL => {
+            article.title.clone()
+        },
+        _ => return not_found_response(),
+    };
+
+    let article_path = format!("{}/article/{}", app_path, article_id);
+    let comments = app_try!(repos.comments.list_by_article(article_id));
+    let entries = comments.iter()
+        .rev()
+        .filter(|comment| comment.status == constant::COMMENT::STATUS::NORMAL)
+        .take(constant::PAGE_SIZE)
+        .map(|comment| {
+            Entry::new(&format!("{} 回复了 {}", comment.user.username, title),
+                       &format!("{}#comment-{}", article_path, comment.id),
+                       &comment.user.username, &comment.content,
+                       comment.create_time,
+                       comment.update_time.unwrap_or(comment.create_time))
+        }).collect();
+
+    let feed = Feed {
+        title: format!("{} 的回复", title),
+        description: format!("Rust China社区话题“{}”的最新回复", title),
+        link: article_path.clone(),
+        feed_url: format!("{}/rss", article_path),
+        entries: entries,
+    };
+    feed_response(req, &feed, Format::Rss)
+}
+
+// the newest articles of the whole site, `path` is where the feed is served
+fn site_feed(req: &mut Request, path: &str) -> IronResult<Feed> {
+    let app_path = get_app_path(req);
+    let repos = req.get::<Read<Repos>>().unwrap();
+
+    let articles = app_try!(repos.articles.newest(&ArticleFilter::All, constant::PAGE_SIZE));
+
+    Ok(Feed {
+        title: String::from("Rust China社区"),
+        description: String::from("Rust China社区最新发帖"),
+        link: app_path.clone(),
+        feed_url: format!("{}{}", app_path, path),
+        entries: article_entries(articles, &app_path),
+    })
+}
+
+fn article_entries(articles: Vec<Article>, app_path: &str) -> Vec<Entry> {
+    articles.iter().map(|article| {
+        Entry::new(&article.title, &format!("{}/article/{}", app_path, article.id),
+                   &article.user.username, &article.content,
+                   article.create_time, article.update_time)
+    }).collect()
+}
+
+fn get_app_path(req: &mut Request) -> String {
+    let config = req.get::<Read<Config>>().unwrap();
+    config.app_path.clone()
+}
@@ -2,3 +2,7 @@ pub mod home;
 pub mod user;
 pub mod article;
 pub mod comment;
+pub mod admin;
+pub mod tag;
+pub mod api;
+pub mod feed;
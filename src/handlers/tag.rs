@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use iron::prelude::*;
+use base::framework::{temp_response, json_response, JsonStatus, not_found_response};
+use persistent::Read;
+use rustc_serialize::json::{Object, ToJson};
+use router::Router;
+use base::constant;
+use urlencoded::UrlEncodedQuery;
+use form_checker::{Validator, Checker, Rule, Str, I64, CheckerOption};
+use url::percent_encoding::{percent_decode, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use handlers::home::{index_data, Listing};
+use repo::{Repos, ArticleFilter};
+
+pub fn show(req: &mut Request) -> IronResult<Response> {
+    let name = {
+        let raw_name = req.extensions.get::<Router>().unwrap().find("name").unwrap();
+        try!(percent_decode(raw_name.as_bytes()).decode_utf8()
+             .map_err(|_| not_found_response().unwrap_err())).into_owned()
+    };
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("page", "页码", I64)
+                    .meet(Rule::Min(1))
+                    .set(CheckerOption::Optional(true)));
+    validator.validate(&req.get::<UrlEncodedQuery>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return not_found_response();
+    }
+
+    let page = match validator.get_optional("page") {
+        Some(p) => p.as_i64().unwrap(),
+        None => 1,
+    } as usize;
+
+    // an unknown tag is a 404, a tag whose articles are all gone is not
+    let repos = req.get::<Read<Repos>>().unwrap();
+    if !app_try!(repos.tags.exists(&name)) {
+        return not_found_response();
+    }
+
+    let filter = ArticleFilter::Tag(name.clone());
+    let count = app_try!(repos.articles.count(&filter));
+    let page_count = (count + constant::PAGE_SIZE - 1) / constant::PAGE_SIZE;
+    let articles = app_try!(repos.articles.list(&filter, Some(page)));
+
+    let page_url = format!("/tag/{}?", utf8_percent_encode(&name, PATH_SEGMENT_ENCODE_SET));
+    let mut data = try!(index_data(req, page, page_count, articles, Listing::Tag, &page_url));
+    data.insert("tag", name.to_json());
+    temp_response("index", &data)
+}
+
+// tag names starting with `q`, for autocompletion in the article form
+pub fn complete(req: &mut Request) -> IronResult<Response> {
+    let mut validator = Validator::new();
+    validator.check(Checker::new("q", "标签", Str)
+                    .meet(Rule::Min(1))
+                    .meet(Rule::Max(32)));
+    validator.validate(&req.get::<UrlEncodedQuery>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return not_found_response();
+    }
+
+    let q = validator.get_required("q").as_str().unwrap().to_lowercase();
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let tags = app_try!(repos.tags.complete(&q));
+
+    let mut data = Object::new();
+    data.insert("tags".to_owned(), tags.to_json());
+    json_response(JsonStatus::Ok, "", data, "")
+}
+
+// Splits what the user typed into at most MAX_COUNT lowercased tags.
+pub fn parse_tags(raw: &str) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in raw.split(|c: char| c == ',' || c == '，' || c.is_whitespace()) {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || tags.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > constant::TAG::MAX_LENGTH {
+            return Err(format!("标签不能超过{}个字", constant::TAG::MAX_LENGTH));
+        }
+        if tag.contains('/') {
+            return Err("标签不能包含/".to_owned());
+        }
+        tags.push(tag);
+    }
+
+    if tags.len() > constant::TAG::MAX_COUNT {
+        return Err(format!("最多只能添加{}个标签", constant::TAG::MAX_COUNT));
+    }
+
+    Ok(tags)
+}
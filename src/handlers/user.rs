@@ -1,27 +1,27 @@
 use std::collections::HashMap;
 use iron::prelude::*;
-use base::framework::{ResponseData, temp_response,
+use base::framework::{ResponseData, temp_response, json_response, JsonStatus,
                       json_error_response, json_ok_response,
-                      json_redirect_response, not_found_response};
+                      json_redirect_response, not_found_response, site_config};
 use urlencoded::UrlEncodedBody;
 use urlencoded::UrlEncodedQuery;
-use form_checker::{Validator, Checker, Rule, Str, Email};
-use mysql as my;
-use crypto::md5;
-use crypto::digest::Digest;
-use rand;
-use rand::Rng;
+use form_checker::{Validator, Checker, Rule, Str, I64, Email, CheckerOption};
 use chrono::*;
-use base::db::MyPool;
+use base::db::DbError;
 use persistent::Read;
 use base::framework::LoginUser;
 use iron_login::User as U;
-use base::model::{User, Article, Category, Comment};
 use router::Router;
 use rustc_serialize::json::{Object, Json, ToJson};
-use base::util::gen_gravatar_url;
 use base::util::render_html;
 use base::constant;
+use base::password;
+use base::util;
+use base::mail::{MyMailer, Mail};
+use base::notify;
+use base::session;
+use base::access_token;
+use base::csrf;
 use oven::prelude::*;
 use cookie::Cookie;
 use time;
@@ -29,20 +29,28 @@ use std::io::Read as io_read;
 use url::{form_urlencoded, Url};
 use iron::Url as iron_url;
 use base::config::Config;
+use base::error::AppError;
+use base::model::User;
+use repo::{Repos, ArticleFilter, MessageFilter};
 use iron::status;
 use iron::modifiers::Redirect;
 use hyper::header::Referer;
 
 pub fn register_load(req: &mut Request) -> IronResult<Response> {
+    if !site_config(req).features.register {
+        return not_found_response();
+    }
     let mut data = ResponseData::new(req);
-    let conf_t = req.get::<Read<Config>>().unwrap().value();
-    let github_config = conf_t.get("github").unwrap().as_table().unwrap();
-    let client_id = github_config.get("client_id").unwrap().as_str().unwrap();
-    data.insert("github_client_id", client_id.to_json());
+    let config = req.get::<Read<Config>>().unwrap();
+    data.insert("github_client_id", config.github.client_id.to_json());
+    data.insert("notify_modes", notify_modes(constant::USER::NOTIFY::OFF).to_json());
     temp_response("user/register_load", &data)
 }
 
 pub fn register(req: &mut Request) -> IronResult<Response> {
+    if !site_config(req).features.register {
+        return json_error_response("对不起，暂不开放注册");
+    }
     let mut validator = Validator::new();
 
     validator
@@ -58,7 +66,10 @@ pub fn register(req: &mut Request) -> IronResult<Response> {
         .check(
             Checker::new("password", "密码", Str)
                 .meet(Rule::Min(8))
-                .meet(Rule::Max(32)));
+                .meet(Rule::Max(32)))
+        .check(
+            Checker::new("notify", "邮件提醒", I64)
+                .set(CheckerOption::Optional(true)));
 
     validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
     if !validator.is_valid() {
@@ -68,38 +79,31 @@ pub fn register(req: &mut Request) -> IronResult<Response> {
     let username = validator.get_required("username").as_str().unwrap();
     let email = validator.get_required("email").as_str().unwrap();
     let password = validator.get_required("password").as_str().unwrap();
+    // nobody gets mails they didn't ask for
+    let notify = validator.get_optional("notify").map(|n| n.as_i64().unwrap())
+        .unwrap_or(constant::USER::NOTIFY::OFF as i64);
+    if !is_notify_mode(notify) {
+        return json_error_response("非法请求");
+    }
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-
-    let salt = rand::thread_rng()
-        .gen_ascii_chars()
-        .take(32)
-        .collect::<String>();
+    let repos = req.get::<Read<Repos>>().unwrap();
 
-    let now = Local::now().naive_local();
-    let password_with_salt = password + &salt;
-    let mut sh = md5::Md5::new();
-    sh.input_str(&password_with_salt);
-    let hash = sh.result_str();
-    let mut stmt = pool.prepare(
-        "INSERT INTO user(username, email, \
-         password, salt, create_time) VALUES (?, ?, ?, ?, ?)")
-        .unwrap();
-    let result = stmt.execute((username, email, hash, salt, now));
-    if let Err(my::error::Error::MySqlError(ref e)) = result {
-        if e.code == 1062 {
-            return json_error_response("对不起，该用户已经被注册了");
-        }
+    let hash = password::hash(password);
+    match app_try!(repos.users.create(&username, &email, &hash, notify as i8)) {
+        Some(_) => json_ok_response(),
+        None => json_error_response("对不起，该用户已经被注册了"),
     }
-    result.unwrap();
-    json_ok_response()
 }
 
 pub fn github_register(req: &mut Request) -> IronResult<Response> {
-    let mut github_user_id_str: String = req.get_cookie("logged_in_user")
-        .unwrap().value.clone();
-    github_user_id_str = github_user_id_str.trim_left_matches("github:").to_string();
-    let github_user_id = github_user_id_str.parse::<u64>().unwrap();
+    let features = site_config(req).features;
+    if !features.register || !features.github_login {
+        return json_error_response("对不起，暂不开放注册");
+    }
+    let github_user_id = match github_user_id(req) {
+        Some(id) => id,
+        None => return json_error_response("请先通过GitHub登录"),
+    };
 
     let mut validator = Validator::new();
 
@@ -121,43 +125,28 @@ pub fn github_register(req: &mut Request) -> IronResult<Response> {
 
     let username = validator.get_required("username").as_str().unwrap();
     let email = validator.get_required("email").as_str().unwrap();
-    let now = Local::now().naive_local();
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-    let mut trans = pool.start_transaction(false, None, None).unwrap();
-
-    let user_id: u64;
-
-    {
-        let result = trans.prep_exec(
-            "INSERT INTO user(username, email, \
-             password, salt, create_time) VALUES (?, ?, ?, ?, ?)",
-            (username, email, "", "", now));
-
-        if let Err(my::error::Error::MySqlError(ref e)) = result {
-            if e.code == 1062 {
-                return json_error_response("对不起，该用户已经被注册了");
-            }
-        }
+    let repos = req.get::<Read<Repos>>().unwrap();
 
-        user_id = result.unwrap().last_insert_id();
-    }
-
-    trans.prep_exec("UPDATE github_user set user_id=?, bind_time=? where id=?",
-                    (user_id, now, github_user_id)).unwrap();
-
-    trans.commit().unwrap();
+    let user_id = match app_try!(repos.users.create_from_github(github_user_id,
+                                                                 &username, &email)) {
+        Some(user_id) => user_id,
+        None => return json_error_response("对不起，该用户已经被注册了"),
+    };
 
-    let mut resp = json_ok_response().unwrap();
+    let mut resp = try!(json_ok_response());
     check_redirect_after_login(req, &mut resp);
-    set_login(&mut resp, &(user_id.to_string()), true);
+    try!(set_login(req, &mut resp, user_id));
     Ok(resp)
 }
 
 pub fn github_login(req: &mut Request) -> IronResult<Response> {
-    let mut github_user_id_str: String = req.get_cookie("logged_in_user")
-        .unwrap().value.clone();
-    github_user_id_str = github_user_id_str.trim_left_matches("github:").to_string();
-    let github_user_id = github_user_id_str.parse::<u64>().unwrap();
+    if !site_config(req).features.github_login {
+        return json_error_response("对不起，暂不支持GitHub登录");
+    }
+    let github_user_id = match github_user_id(req) {
+        Some(id) => id,
+        None => return json_error_response("请先通过GitHub登录"),
+    };
 
     let mut validator = Validator::new();
     validator
@@ -171,35 +160,34 @@ pub fn github_login(req: &mut Request) -> IronResult<Response> {
 
     let username = validator.get_required("username").as_str().unwrap();
     let password = validator.get_required("password").as_str().unwrap();
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
+    let repos = req.get::<Read<Repos>>().unwrap();
 
-    let raw_user_id = check_login(&pool, &username, &password);
-    if raw_user_id.is_none() {
-        return json_error_response("对不起，用户名或密码不对");
+    let user = match app_try!(check_login(&repos, &username, &password)) {
+        Some(user) => user,
+        None => return json_error_response("对不起，用户名或密码不对"),
+    };
+    if user.status == constant::USER::STATUS::BANNED {
+        return json_error_response("对不起，该账号已被封禁");
     }
-    let user_id = raw_user_id.unwrap();
+    let user_id = user.id;
 
-    let now = Local::now().naive_local();
-    pool.prep_exec("UPDATE github_user set user_id=?, bind_time=? where id=?",
-                    (user_id, now, github_user_id)).unwrap();
+    app_try!(repos.users.bind_github_user(github_user_id, user_id));
 
     // set session
-    let mut resp = json_ok_response().unwrap();
+    let mut resp = try!(json_ok_response());
     check_redirect_after_login(req, &mut resp);
-    set_login(&mut resp, &(user_id.to_string()), true);
+    try!(set_login(req, &mut resp, user_id));
     Ok(resp)
 }
 
 pub fn login_load(req: &mut Request) -> IronResult<Response> {
     let mut data = ResponseData::new(req);
-    let conf_t = req.get::<Read<Config>>().unwrap().value();
-    let github_config = conf_t.get("github").unwrap().as_table().unwrap();
-    let client_id = github_config.get("client_id").unwrap().as_str().unwrap();
-    data.insert("github_client_id", client_id.to_json());
-    let mut resp = temp_response("user/login_load", &data).unwrap();
+    let config = req.get::<Read<Config>>().unwrap();
+    data.insert("github_client_id", config.github.client_id.to_json());
+    let mut resp = try!(temp_response("user/login_load", &data));
     if let Some(refer) = req.headers.get::<Referer>() {
         let refer_url = refer.0.clone();
-        let app_path = conf_t.get("app_path").unwrap().as_str().unwrap().to_owned();
+        let app_path = config.app_path.clone();
         if refer_url.starts_with(&app_path) &&
             refer_url != app_path.clone() + "/user/login" &&
             refer_url != app_path + "/user/login/" {
@@ -215,6 +203,9 @@ pub fn login_load(req: &mut Request) -> IronResult<Response> {
 }
 
 pub fn github_callback(req: &mut Request) -> IronResult<Response> {
+    if !site_config(req).features.github_login {
+        return not_found_response();
+    }
     let mut validator = Validator::new();
     validator.check(Checker::new("code", "code", Str));
     validator.validate(&req.get::<UrlEncodedQuery>().unwrap_or(HashMap::new()));
@@ -228,19 +219,19 @@ pub fn github_callback(req: &mut Request) -> IronResult<Response> {
     let client = ::hyper::Client::new();
 
     let mut url = Url::parse("https://github.com/login/oauth/access_token").unwrap();
-    let conf_t = req.get::<Read<Config>>().unwrap().value();
-    let github_config = conf_t.get("github").unwrap().as_table().unwrap();
-    let client_id = github_config.get("client_id").unwrap().as_str().unwrap();
-    let client_secret = github_config.get("client_secret").unwrap().as_str().unwrap();
+    let config = req.get::<Read<Config>>().unwrap();
+    let client_id = &config.github.client_id;
+    let client_secret = &config.github.client_secret;
 
     url.query_pairs_mut()
         .append_pair("client_id", &client_id)
         .append_pair("client_secret", &client_secret)
         .append_pair("code", &code);
 
-    let mut res = client.post(url.as_str()).send().unwrap();
+    let mut res = app_try!(client.post(url.as_str()).send()
+                           .map_err(|e| github_error("access token", e)));
     let mut body = String::new();
-    res.read_to_string(&mut body).unwrap();
+    app_try!(res.read_to_string(&mut body).map_err(|e| github_error("access token", e)));
 
     // get access_token
     let mut access_token = "".to_string();
@@ -253,51 +244,32 @@ pub fn github_callback(req: &mut Request) -> IronResult<Response> {
     url = Url::parse("https://api.github.com/user").unwrap();
     url.query_pairs_mut().append_pair("access_token", &access_token);
 
-    res = client.get(url.as_str())
-        .header(::hyper::header::UserAgent("rust-lang-cn".to_string()))
-        .send()
-        .unwrap();
+    res = app_try!(client.get(url.as_str())
+                   .header(::hyper::header::UserAgent("rust-lang-cn".to_string()))
+                   .send()
+                   .map_err(|e| github_error("user", e)));
 
     body.clear();
-    res.read_to_string(&mut body).unwrap();
-
-    let data = Json::from_str(&body).unwrap();
-    let github_user = data.as_object().unwrap();
-    let github_user_id = github_user.get("id").unwrap().as_u64().unwrap();
-    let github_user_name = github_user.get("login").unwrap().as_string().unwrap();
-    let github_user_email = github_user.get("email").unwrap().as_string().unwrap();
-    let github_user_avatar = github_user.get("avatar_url")
-        .unwrap().as_string().unwrap();
-
-    let now = Local::now().naive_local();
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
-
-    let mut stmt = pool.prepare(
-        "INSERT INTO github_user(id, username, email, avatar_url, \
-         create_time, update_time) VALUES (?, ?, ?, ?, ?, ?) \
-         ON DUPLICATE KEY UPDATE \
-         username=VALUES(username), \
-         email=VALUES(email), \
-         avatar_url=VALUES(avatar_url), \
-         update_time=VALUES(update_time)")
-        .unwrap();
-
-    stmt.execute((
-        github_user_id,
-        github_user_name,
-        github_user_email,
-        github_user_avatar,
-        now,
-        now,
-    )).unwrap();
-
-    // query whether is this github user binded to local user.
-    let raw_user_id: Option<u64> = my::from_row(
-        pool.prep_exec("SELECT user_id from github_user where id=?",
-                             (&github_user_id,))
-        .unwrap().next().unwrap().unwrap());
-
-    let app_path = conf_t.get("app_path").unwrap().as_str().unwrap().to_owned();
+    app_try!(res.read_to_string(&mut body).map_err(|e| github_error("user", e)));
+
+    // e.g. an expired code gets an error object instead of the user
+    let data = app_try!(Json::from_str(&body).map_err(|e| github_error("user", e)));
+    let field = |name: &str| data.find(name).ok_or_else(
+        || github_error("user", format!("no {} in {}", name, body)));
+    let github_user_id = app_try!(field("id")).as_u64().unwrap_or(0);
+    let github_user_name = app_try!(field("login")).as_string().unwrap_or("");
+    // null when the user keeps the email private
+    let github_user_email = app_try!(field("email")).as_string().unwrap_or("");
+    let github_user_avatar = app_try!(field("avatar_url")).as_string().unwrap_or("");
+
+    // whether this github user is bound to a local user
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let raw_user_id = app_try!(repos.users.save_github_user(github_user_id,
+                                                            github_user_name,
+                                                            github_user_email,
+                                                            github_user_avatar));
+
+    let app_path = config.app_path.clone();
     if let Some(user_id) = raw_user_id {
         // binded, set session and redirect to home page.
         let mut url_str = app_path.clone() + "/";
@@ -312,15 +284,15 @@ pub fn github_callback(req: &mut Request) -> IronResult<Response> {
         let url = iron_url::parse(&url_str).unwrap();
         let mut resp = Response::with((status::Found, Redirect(url.clone())));
 
-        set_login(&mut resp, &(user_id.to_string()), true);
+        try!(set_login(req, &mut resp, user_id));
         Ok(resp)
     } else {
         // not binded, let user bind.
         let mut data = ResponseData::new(req);
         data.insert("github_user_name", github_user_name.to_string().to_json());
         data.insert("github_user_email", github_user_email.to_string().to_json());
-        let mut resp = temp_response("user/bind_load", &data).unwrap();
-        set_login(&mut resp, &format!("github:{}", github_user_id), false);
+        let mut resp = try!(temp_response("user/bind_load", &data));
+        set_login_cookie(&mut resp, &format!("github:{}", github_user_id), false);
         Ok(resp)
     }
 }
@@ -338,26 +310,34 @@ pub fn login(req: &mut Request) -> IronResult<Response> {
 
     let username = validator.get_required("username").as_str().unwrap();
     let password = validator.get_required("password").as_str().unwrap();
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
+    let repos = req.get::<Read<Repos>>().unwrap();
 
-    let raw_user_id = check_login(&pool, &username, &password);
-    if raw_user_id.is_none() {
-        return json_error_response("对不起，用户名或密码不对");
+    let user = match app_try!(check_login(&repos, &username, &password)) {
+        Some(user) => user,
+        None => return json_error_response("对不起，用户名或密码不对"),
+    };
+    if user.status == constant::USER::STATUS::BANNED {
+        return json_error_response("对不起，该账号已被封禁");
     }
-    let user_id = raw_user_id.unwrap();
+    let user_id = user.id;
 
     // set session
-    let mut resp = json_ok_response().unwrap();
+    let mut resp = try!(json_ok_response());
     check_redirect_after_login(req, &mut resp);
 
-    set_login(&mut resp, &(user_id.to_string()), true);
+    try!(set_login(req, &mut resp, user_id));
     Ok(resp)
 }
 
 pub fn logout(req: &mut Request) -> IronResult<Response> {
     let login = LoginUser::get_login(req);
-    let mut resp = json_ok_response().unwrap();
+    if let Some(login_user) = login.get_user() {
+        let repos = req.get::<Read<Repos>>().unwrap();
+        app_try!(session::delete_by_token(&repos, &login_user.session_token));
+    }
+    let mut resp = try!(json_ok_response());
     resp.set_mut(login.log_out());
+    csrf::rotate_token(req);
     Ok(resp)
 }
 
@@ -368,40 +348,14 @@ pub fn show(req: &mut Request) -> IronResult<Response> {
 
     let login_user = LoginUser::get_login(req).get_user();
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
+    let repos = req.get::<Read<Repos>>().unwrap();
     let mut data = ResponseData::new(req);
 
-    if get_general_info(&mut data, &pool, user_id, login_user.clone()).is_err() {
+    if !app_try!(get_general_info(&mut data, &repos, user_id, login_user)) {
         return not_found_response();
     }
 
-    get_unread_messages_count(&mut data, &pool, user_id, login_user);
-
-    // get articles
-    let articles: Vec<Article> = pool.prep_exec(
-        "SELECT id, category, title, content, comments_count, \
-         create_time from article where status=? and user_id=? order by \
-         create_time desc",
-        (constant::ARTICLE::STATUS::NORMAL, user_id))
-        .unwrap()
-        .map(|x| x.unwrap())
-        .map(|row| {
-            let (id, category, title, content,
-                 comments_count, create_time) = my::from_row(row);
-
-            Article {
-                id: id,
-                category: Category::from_value(category),
-                title: title,
-                content: content,
-                comments_count: comments_count,
-                user: User::default(),
-                create_time: create_time,
-                update_time: *constant::DEFAULT_DATETIME,
-                flag: 0,
-                comments: Vec::new(),
-            }
-        }).collect();
+    let articles = app_try!(repos.articles.list(&ArticleFilter::User(user_id), None));
 
     data.insert("articles", articles.to_json());
     data.insert("articles_active", true.to_json());
@@ -415,147 +369,549 @@ pub fn show_comments(req: &mut Request) -> IronResult<Response> {
 
     let login_user = LoginUser::get_login(req).get_user();
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
+    let repos = req.get::<Read<Repos>>().unwrap();
     let mut data = ResponseData::new(req);
 
-    if get_general_info(&mut data, &pool, user_id, login_user.clone()).is_err() {
+    if !app_try!(get_general_info(&mut data, &repos, user_id, login_user)) {
         return not_found_response();
     }
 
-    get_unread_messages_count(&mut data, &pool, user_id, login_user);
-
-    // get comments
-    let comments: Vec<Comment> = pool.prep_exec(
-        "SELECT c.id, c.content, c.create_time, \
-         a.id as article_id, a.title as article_title from comment as c \
-         join article as a on c.article_id=a.id where c.user_id=? \
-         order by c.create_time desc",
-        (user_id,))
-        .unwrap()
-        .map(|x| x.unwrap())
-        .map(|row| {
-            let (id, content, create_time,
-                 article_id, article_title) = my::from_row(row);
-
-            let mut article = Article::default();
-            article.id = article_id;
-            article.title = article_title;
-
-            let mut comment = Comment {
-                id: id,
-                content: content,
-                user: User:: default(),
-                create_time: create_time,
-                article: Some(article),
-            };
-
-            comment.content = render_html(&comment.content);
-            comment
-        }).collect();
+    let mut comments = app_try!(repos.comments.list_by_user(user_id));
+    for comment in &mut comments {
+        comment.content = render_html(&comment.content);
+    }
 
     data.insert("comments", comments.to_json());
     data.insert("comments_active", true.to_json());
     temp_response("user/show", &data)
 }
 
+// The notification center. Viewing it reads nothing; messages are marked
+// read one by one or all at once.
 pub fn show_messages(req: &mut Request) -> IronResult<Response> {
     let user_id = try!(req.extensions.get::<Router>().unwrap()
                        .find("user_id").unwrap()
                        .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
 
+    let mut validator = Validator::new();
+    validator
+        .check(Checker::new("mode", "类型", I64)
+               .set(CheckerOption::Optional(true)))
+        .check(Checker::new("page", "页码", I64)
+               .meet(Rule::Min(1))
+               .set(CheckerOption::Optional(true)));
+    validator.validate(&req.get::<UrlEncodedQuery>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return not_found_response();
+    }
+
+    let raw_mode = validator.get_optional("mode").map(|m| m.as_i64().unwrap());
+    if let Some(mode) = raw_mode {
+        if constant::MESSAGE::MODE::ALL.iter().find(|&&(m, _)| m as i64 == mode).is_none() {
+            return not_found_response();
+        }
+    }
+    let raw_mode = raw_mode.map(|m| m as i8);
+    let page = match validator.get_optional("page") {
+        Some(p) => p.as_i64().unwrap(),
+        None => 1,
+    } as usize;
+
     let login_user = LoginUser::get_login(req).get_user();
 
     if login_user.clone().unwrap().id != user_id {
         return not_found_response();
     }
 
-    let pool = req.get::<Read<MyPool>>().unwrap().value();
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let mut data = ResponseData::new(req);
+
+    if !app_try!(get_general_info(&mut data, &repos, user_id, login_user)) {
+        return not_found_response();
+    }
+
+    let filter = MessageFilter {
+        to_user_id: user_id,
+        mode: raw_mode,
+        unread_only: false,
+    };
+    let count = app_try!(repos.messages.count(&filter));
+    let page_count = (count + constant::PAGE_SIZE - 1) / constant::PAGE_SIZE;
+
+    let messages = app_try!(repos.messages.list(&filter, Some(page)));
+    let messages: Vec<Json> = messages.into_iter().map(|message| {
+        let mut object = Object::new();
+        object.insert("id".to_owned(), message.id.to_json());
+        object.insert("is_new".to_owned(),
+                      (if message.status == constant::MESSAGE::STATUS::INIT {true}
+                       else {false}).to_json());
+        object.insert("action".to_owned(), message.action().to_json());
+        object.insert("create_time".to_owned(), message.create_time.format(
+            "%Y-%m-%d %H:%M:%S").to_string().to_json());
+        object.insert("content".to_owned(), render_html(&message.comment.content).to_json());
+        object.insert("user_id".to_owned(), message.from_user.id.to_json());
+        object.insert("username".to_owned(), message.from_user.username.to_json());
+        object.insert("avatar".to_owned(), message.from_user.avatar.to_json());
+        object.insert("article_id".to_owned(), message.article.id.to_json());
+        object.insert("article_title".to_owned(), message.article.title.to_json());
+        object.insert("comment_id".to_owned(), message.comment.id.to_json());
+        object.to_json()
+    }).collect();
+
+    let modes: Vec<Json> = constant::MESSAGE::MODE::ALL.iter().map(|&(mode, title)| {
+        let mut object = Object::new();
+        object.insert("mode".to_owned(), mode.to_json());
+        object.insert("title".to_owned(), title.to_json());
+        object.insert("active".to_owned(), (raw_mode == Some(mode)).to_json());
+        object.to_json()
+    }).collect();
+
+    let notify = app_try!(repos.users.find(user_id))
+        .map(|user| user.notify).unwrap_or(constant::USER::NOTIFY::OFF);
+
+    let page_url = match raw_mode {
+        Some(mode) => format!("/user/{}/messages?mode={}&", user_id, mode),
+        None => format!("/user/{}/messages?", user_id),
+    };
+
+    data.insert("messages", messages.to_json());
+    data.insert("message_modes", modes.to_json());
+    data.insert("all_modes_active", raw_mode.is_none().to_json());
+    data.insert("notify_modes", notify_modes(notify).to_json());
+    data.insert("page_url", page_url.to_json());
+    data.insert("show_pagination", (page_count > 1).to_json());
+    data.insert("pages", util::gen_pages_json(page_count, page));
+    data.insert("previous_page",
+                (if page - 1 < 1 {1} else {page - 1}).to_json());
+    data.insert("next_page",
+                (if page + 1 > page_count {page_count} else {page + 1}).to_json());
+    data.insert("messages_active", true.to_json());
+    temp_response("user/show", &data)
+}
+
+pub fn read_message(req: &mut Request) -> IronResult<Response> {
+    set_message_status(req, constant::MESSAGE::STATUS::READ)
+}
+
+pub fn unread_message(req: &mut Request) -> IronResult<Response> {
+    set_message_status(req, constant::MESSAGE::STATUS::INIT)
+}
+
+fn set_message_status(req: &mut Request, status: i8) -> IronResult<Response> {
+    let (user_id, message_id) = try!(get_message_ids(req));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(repos.messages.set_status(user_id, message_id, status));
+    json_ok_response()
+}
+
+pub fn delete_message(req: &mut Request) -> IronResult<Response> {
+    let (user_id, message_id) = try!(get_message_ids(req));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(repos.messages.delete(user_id, message_id));
+    json_ok_response()
+}
+
+pub fn read_all_messages(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(repos.messages.mark_read(user_id, None));
+    json_ok_response()
+}
+
+// how new messages are mailed, one of constant::USER::NOTIFY
+pub fn set_notify(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("notify", "邮件提醒", I64));
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let notify = validator.get_required("notify").as_i64().unwrap();
+    if !is_notify_mode(notify) {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(repos.users.set_notify(user_id, notify as i8));
+    json_ok_response()
+}
+
+// The link at the bottom of every message mail, works without login. Mail
+// scanners open links too, so only the POST of its confirmation page, or
+// the one click of the List-Unsubscribe-Post header, turns mails off.
+pub fn unsubscribe_load(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(unsubscribe_user_id(req));
+
+    let mut data = ResponseData::new(req);
+    data.insert("user_id", user_id.to_json());
+    temp_response("user/unsubscribe_load", &data)
+}
+
+pub fn unsubscribe(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(unsubscribe_user_id(req));
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(repos.users.set_notify(user_id, constant::USER::NOTIFY::OFF));
+
+    let mut data = ResponseData::new(req);
+    data.insert("user_id", user_id.to_json());
+    temp_response("user/unsubscribe", &data)
+}
+
+// the user of an unsubscribe link, 404 when its token is wrong
+fn unsubscribe_user_id(req: &mut Request) -> IronResult<u64> {
+    let (user_id, token) = {
+        let router = req.extensions.get::<Router>().unwrap();
+        let user_id = try!(router.find("user_id").unwrap()
+                           .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+        (user_id, router.find("token").unwrap().to_owned())
+    };
+
+    let config = req.get::<Read<Config>>().unwrap();
+    if !notify::check_unsubscribe_token(&config, user_id, &token) {
+        return Err(not_found_response().unwrap_err());
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    if app_try!(repos.users.find(user_id)).is_none() {
+        return Err(not_found_response().unwrap_err());
+    }
+    Ok(user_id)
+}
+
+// the choices of constant::USER::NOTIFY for a form, `notify` checked
+fn notify_modes(notify: i8) -> Vec<Json> {
+    constant::USER::NOTIFY::ALL.iter().map(|&(mode, title)| {
+        let mut object = Object::new();
+        object.insert("notify".to_owned(), mode.to_json());
+        object.insert("title".to_owned(), title.to_json());
+        object.insert("active".to_owned(), (notify == mode).to_json());
+        object.to_json()
+    }).collect()
+}
+
+fn is_notify_mode(notify: i64) -> bool {
+    constant::USER::NOTIFY::ALL.iter().any(|&(n, _)| n as i64 == notify)
+}
+
+fn get_message_ids(req: &mut Request) -> IronResult<(u64, u64)> {
+    let router = req.extensions.get::<Router>().unwrap();
+    let user_id = try!(router.find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+    let message_id = try!(router.find("message_id").unwrap()
+                          .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+    Ok((user_id, message_id))
+}
+
+pub fn show_sessions(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return not_found_response();
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
     let mut data = ResponseData::new(req);
 
-    if get_general_info(&mut data, &pool, user_id, login_user).is_err() {
+    if !app_try!(get_general_info(&mut data, &repos, user_id, Some(login_user.clone()))) {
+        return not_found_response();
+    }
+
+    let sessions = app_try!(session::list(&repos, user_id, login_user.session_id));
+    data.insert("sessions", sessions.to_json());
+    data.insert("sessions_active", true.to_json());
+    temp_response("user/show", &data)
+}
+
+pub fn delete_session(req: &mut Request) -> IronResult<Response> {
+    let (user_id, session_id) = {
+        let router = req.extensions.get::<Router>().unwrap();
+        let user_id = try!(router.find("user_id").unwrap()
+                           .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+        let session_id = try!(router.find("session_id").unwrap()
+                              .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+        (user_id, session_id)
+    };
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(session::delete(&repos, user_id, session_id));
+    json_ok_response()
+}
+
+pub fn logout_other_sessions(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(session::delete_others(&repos, user_id, login_user.session_id));
+    json_ok_response()
+}
+
+pub fn show_tokens(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
         return not_found_response();
     }
 
-    // get messages
-    let messages: Vec<Json> = pool.prep_exec(
-        "SELECT m.status, m.create_time, c.content, u.id as user_id, u.username, \
-         u.email, a.id as article_id, a.title as article_title \
-         from message as m join user as u on m.from_user_id=u.id \
-         join article as a on a.id=m.article_id \
-         join comment as c on c.id=m.comment_id \
-         where to_user_id=? order by create_time desc",
-        (user_id,))
-        .unwrap()
-        .map(|x| x.unwrap())
-        .map(|row| {
-            let (status, create_time, content, user_id, username, email,
-                 article_id, article_title)
-                = my::from_row::<(
-                    i8, NaiveDateTime, String, u64,
-                    String, String, u64, String)>(row);
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let mut data = ResponseData::new(req);
 
+    if !app_try!(get_general_info(&mut data, &repos, user_id, Some(login_user))) {
+        return not_found_response();
+    }
+
+    let scopes: Vec<Json> = constant::ACCESS_TOKEN::SCOPE::ALL.iter()
+        .map(|&(_, field, title)| {
             let mut object = Object::new();
-            object.insert("is_new".to_owned(),
-                          (if status == constant::MESSAGE::STATUS::INIT {true}
-                           else {false}).to_json());
-            object.insert("create_time".to_owned(), create_time.format(
-                "%Y-%m-%d %H:%M:%S").to_string().to_json());
-            object.insert("content".to_owned(), render_html(&content).to_json());
-            object.insert("user_id".to_owned(), user_id.to_json());
-            object.insert("username".to_owned(), username.to_json());
-            object.insert("avatar".to_owned(), gen_gravatar_url(&email).to_json());
-            object.insert("article_id".to_owned(), article_id.to_json());
-            object.insert("article_title".to_owned(), article_title.to_json());
+            object.insert("field".to_owned(), field.to_json());
+            object.insert("title".to_owned(), title.to_json());
             object.to_json()
         }).collect();
 
-    // mark messages as read
-    pool.prep_exec("UPDATE message set status=? where to_user_id=? and status=?",
-                   (constant::MESSAGE::STATUS::READ,
-                    user_id,
-                    constant::MESSAGE::STATUS::INIT)).unwrap();
-
-    data.insert("messages", messages.to_json());
-    data.insert("messages_active", true.to_json());
+    data.insert("tokens", app_try!(access_token::list(&repos, user_id)).to_json());
+    data.insert("scopes", scopes.to_json());
+    data.insert("tokens_active", true.to_json());
     temp_response("user/show", &data)
 }
 
+// the token is only shown in this response, it cannot be seen again
+pub fn new_token(req: &mut Request) -> IronResult<Response> {
+    let user_id = try!(req.extensions.get::<Router>().unwrap()
+                       .find("user_id").unwrap()
+                       .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let body = req.get::<UrlEncodedBody>().unwrap_or(HashMap::new());
+
+    let mut validator = Validator::new();
+    validator.check(Checker::new("name", "名称", Str)
+                    .meet(Rule::Min(1))
+                    .meet(Rule::Max(64)));
+    validator.validate(&body);
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let scopes = constant::ACCESS_TOKEN::SCOPE::ALL.iter()
+        .filter(|&&(_, field, _)| body.contains_key(field))
+        .fold(0, |scopes, &(scope, _, _)| scopes | scope);
+    if scopes == 0 {
+        return json_error_response("请至少选择一项权限");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    if app_try!(access_token::list(&repos, user_id)).len() >= constant::ACCESS_TOKEN::MAX_COUNT {
+        return json_error_response(&format!("最多只能创建{}个令牌",
+                                            constant::ACCESS_TOKEN::MAX_COUNT));
+    }
+
+    let name = validator.get_required("name").as_str().unwrap();
+    let token = app_try!(access_token::create(&repos, user_id, &name, scopes));
+
+    let mut data = Object::new();
+    data.insert("token".to_owned(), token.to_json());
+    json_response(JsonStatus::Ok, "", data, "")
+}
+
+pub fn delete_token(req: &mut Request) -> IronResult<Response> {
+    let (user_id, token_id) = {
+        let router = req.extensions.get::<Router>().unwrap();
+        let user_id = try!(router.find("user_id").unwrap()
+                           .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+        let token_id = try!(router.find("token_id").unwrap()
+                            .parse::<u64>().map_err(|_| not_found_response().unwrap_err()));
+        (user_id, token_id)
+    };
+
+    let login_user = LoginUser::get_login(req).get_user().unwrap();
+
+    if login_user.id != user_id {
+        return json_error_response("非法请求");
+    }
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    app_try!(access_token::delete(&repos, user_id, token_id));
+    json_ok_response()
+}
+
+pub fn forgot_load(req: &mut Request) -> IronResult<Response> {
+    let data = ResponseData::new(req);
+    temp_response("user/forgot_load", &data)
+}
+
+pub fn forgot(req: &mut Request) -> IronResult<Response> {
+    let mut validator = Validator::new();
+    validator.check(Checker::new("email", "邮箱", Email).meet(Rule::Max(64)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let email = validator.get_required("email").as_str().unwrap();
+    let repos = req.get::<Read<Repos>>().unwrap();
+
+    // do not tell whether the email is registered
+    let user = match app_try!(repos.users.find_by_email(&email)) {
+        Some(user) => user,
+        None => return json_ok_response(),
+    };
+    let username = user.username;
+
+    let token = util::gen_random_token(32);
+    let expire_time = Local::now().naive_local() +
+        Duration::hours(constant::PASSWORD_RESET_EXPIRE_HOURS);
+    app_try!(repos.users.create_password_reset(user.id, &util::sha256_hex(&token),
+                                               expire_time));
+
+    let config = req.get::<Read<Config>>().unwrap();
+    let app_path = &config.app_path;
+    let mail = Mail {
+        to: email.to_owned(),
+        subject: "Rust China 重置密码".to_owned(),
+        body: format!("{}，你好：\n\n请在{}小时内打开以下链接重置密码：\n\n\
+                       {}/user/password/reset/{}\n\n\
+                       如果这不是你本人的操作，请忽略本邮件。\n",
+                      username, constant::PASSWORD_RESET_EXPIRE_HOURS,
+                      app_path, token),
+        headers: Vec::new(),
+    };
+
+    // a failure is only logged, telling about it would tell that the email
+    // is registered
+    let mailer = req.get::<Read<MyMailer>>().unwrap();
+    if let Err(e) = mailer.send(&mail) {
+        error!("send password reset mail to {} failed: {}", email, e);
+    }
+
+    json_ok_response()
+}
+
+pub fn reset_load(req: &mut Request) -> IronResult<Response> {
+    let token = req.extensions.get::<Router>().unwrap()
+        .find("token").unwrap().to_owned();
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let now = Local::now().naive_local();
+    if app_try!(repos.users.find_password_reset(&util::sha256_hex(&token), now)).is_none() {
+        return not_found_response();
+    }
+
+    let mut data = ResponseData::new(req);
+    data.insert("token", token.to_json());
+    temp_response("user/reset_load", &data)
+}
+
+pub fn reset(req: &mut Request) -> IronResult<Response> {
+    let token = req.extensions.get::<Router>().unwrap()
+        .find("token").unwrap().to_owned();
+
+    let mut validator = Validator::new();
+    validator.check(
+        Checker::new("password", "密码", Str)
+            .meet(Rule::Min(8))
+            .meet(Rule::Max(32)));
+
+    validator.validate(&req.get::<UrlEncodedBody>().unwrap_or(HashMap::new()));
+    if !validator.is_valid() {
+        return json_error_response(&validator.get_some_error());
+    }
+
+    let password = validator.get_required("password").as_str().unwrap();
+    let now = Local::now().naive_local();
+
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let (reset_id, user_id) = match app_try!(repos.users.find_password_reset(
+        &util::sha256_hex(&token), now)) {
+        Some(reset) => reset,
+        None => return json_error_response("链接已失效，请重新申请重置密码"),
+    };
+
+    // whoever knew the old password must not stay logged in, so this also
+    // ends the user's sessions and access tokens
+    if !app_try!(repos.users.reset_password(reset_id, user_id, &password::hash(password))) {
+        return json_error_response("链接已失效，请重新申请重置密码");
+    }
+
+    json_ok_response()
+}
+
+// false when there is no such user
 fn get_general_info(data: &mut ResponseData,
-                    pool: &my::Pool,
+                    repos: &Repos,
                     user_id: u64,
-                    raw_login_user: Option<LoginUser>) -> Result<(),()> {
-    let row = try!(pool.prep_exec(
-        "SELECT id, username, email, create_time from user where id=?", (&user_id,))
-                   .unwrap()
-                   .next()
-                   .map(|row|row.unwrap()).ok_or(()));
-
-    let (user_id, username, email, create_time) = my::from_row::<(_,_,String,_)>(row);
-    let user = User{
-        id: user_id,
-        avatar: gen_gravatar_url(&email),
-        username: username,
-        email: email,
-        create_time: create_time,
+                    raw_login_user: Option<LoginUser>) -> Result<bool, DbError> {
+    let user = match try!(repos.users.find(user_id)) {
+        Some(user) => user,
+        None => return Ok(false),
     };
 
-    // get articles count
-    let articles_count = my::from_row::<usize>(
-        pool.prep_exec("SELECT count(id) from article where status=? and user_id=?",
-                       (constant::ARTICLE::STATUS::NORMAL, user_id))
-            .unwrap().next().unwrap().unwrap());
-
-    // get comments count
-    let comments_count = my::from_row::<usize>(
-        pool.prep_exec("SELECT count(id) from comment where user_id=?",
-                       (user_id,))
-        .unwrap().next().unwrap().unwrap());
+    let articles_count = try!(repos.articles.count(&ArticleFilter::User(user_id)));
+    let comments_count = try!(repos.comments.count_by_user(user_id));
 
     // where is me among all members
-    let which_member = my::from_row::<usize>(
-        pool.prep_exec("SELECT count(id) as count from user where id < ?",
-                       (user_id,))
-            .unwrap().next().unwrap().unwrap()) + 1;
+    let which_member = try!(repos.users.rank(user_id));
 
     // judge whether is myself
     let mut is_myself = false;
@@ -573,64 +929,70 @@ fn get_general_info(data: &mut ResponseData,
     data.insert("articles_count", articles_count.to_json());
     data.insert("comments_count", comments_count.to_json());
     data.insert("is_myself", is_myself.to_json());
-    Ok(())
+    Ok(true)
 }
 
-fn get_unread_messages_count(data: &mut ResponseData, pool: &my::Pool,
-                             user_id: u64, raw_login_user: Option<LoginUser>) {
-
-    if raw_login_user.is_none() {
-        return;
-    }
-
-    let login_user = raw_login_user.unwrap();
-
-    if login_user.id != user_id {
-        return;
-    }
-
-    let unread_messages_count = my::from_row::<usize>(pool.prep_exec(
-        "SELECT count(id) as count from message where to_user_id=? and status=?",
-        (login_user.id, constant::MESSAGE::STATUS::INIT)).unwrap()
-                                                    .next().unwrap().unwrap());
-    data.insert("unread_messages_count", unread_messages_count.to_json());
+fn set_login(req: &mut Request, resp: &mut Response, user_id: u64) -> IronResult<()> {
+    let repos = req.get::<Read<Repos>>().unwrap();
+    let token = app_try!(session::create(&repos, user_id, &util::client_ip(req),
+                                         &util::user_agent(req)));
+    set_login_cookie(resp, &token, true);
+    csrf::rotate_token(req);
+    Ok(())
 }
 
-fn set_login(resp: &mut Response, user_id: &str, persist: bool) {
+fn set_login_cookie(resp: &mut Response, value: &str, persist: bool) {
     let mut c = Cookie::new("logged_in_user".to_owned(), "".to_owned());
     c.httponly = true;
     if persist {
-        c.expires = Some(time::now() + time::Duration::days(365));
+        c.expires = Some(time::now() + time::Duration::days(
+            constant::SESSION_EXPIRE_DAYS));
     }
     c.path = Some("/".to_owned());
-    c.value = user_id.to_string();
+    c.value = value.to_string();
     resp.set_cookie(c);
 }
 
-fn check_login(pool: &my::Pool, username: &str, password: &str) -> Option<u64> {
-    let mut result = pool.prep_exec(
-        "SELECT id, email, password, salt from user where username=?",
-        (username,)).unwrap();
-    let raw_row = result.next();
-    if raw_row.is_none() {
-        return None;
-    }
-    let row = raw_row.unwrap().unwrap();
-    let (user_id, _, pass, salt) = my::from_row::<(
-        u64, String, String, String)>(row);
-    let password_with_salt = password.to_string() + &salt;
-    let mut sh = md5::Md5::new();
-    sh.input_str(&password_with_salt);
-    let hash = sh.result_str();
-    if pass != hash {
-        return None;
+// the user, when the password is right
+fn check_login(repos: &Repos, username: &str, password: &str)
+               -> Result<Option<User>, DbError> {
+    let user = match try!(repos.users.find_by_username(username)) {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+    let (pass, salt) = match try!(repos.users.password(user.id)) {
+        Some(password) => password,
+        None => return Ok(None),
+    };
+    match password::verify(password, &salt, &pass) {
+        password::Verified::Ok => {},
+        password::Verified::NeedsUpgrade => {
+            // rehash with the current hasher now that we know the password
+            try!(repos.users.set_password(user.id, &password::hash(password)));
+        },
+        password::Verified::Fail => return Ok(None),
     }
-    Some(user_id)
+    Ok(Some(user))
+}
+
+// the id in the `github:<id>` cookie of a github user not yet bound
+fn github_user_id(req: &mut Request) -> Option<u64> {
+    req.get_cookie("logged_in_user").and_then(|c| {
+        if c.value.starts_with("github:") {
+            c.value.trim_left_matches("github:").parse::<u64>().ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn github_error<E: ::std::fmt::Display>(what: &str, e: E) -> AppError {
+    AppError::Internal(format!("get {} from github failed: {}", what, e))
 }
 
 fn check_redirect_after_login(req: &mut Request, resp: &mut Response) {
     let config = req.get::<Read<Config>>().unwrap();
-    let app_path = config.get("app_path").as_str().unwrap();
+    let app_path = &config.app_path;
 
     if let Some(c) = req.get_cookie("redirect_url") {
         let redirect_url = c.value.clone();
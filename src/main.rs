@@ -9,6 +9,7 @@ extern crate traitobject;
 #[macro_use]
 extern crate mime;
 extern crate mysql;
+extern crate rusqlite;
 extern crate crypto;
 extern crate rand;
 extern crate iron_login;
@@ -23,49 +24,155 @@ extern crate pulldown_cmark;
 extern crate regex;
 extern crate ammonia;
 extern crate rss;
+extern crate xml;
 extern crate cookie;
 extern crate oven;
 extern crate url;
 extern crate mount;
 extern crate staticfile;
 extern crate form_checker;
+extern crate libc;
+#[cfg(test)]
+extern crate iron_test;
 
+#[macro_use]
 mod base;
 mod handlers;
+mod repo;
 mod route;
+mod command;
+#[cfg(test)]
+mod tests;
 
 use iron::Chain;
 use hbsi::{HandlebarsEngine, DirectorySource};
-use persistent::Read;
-use base::config::Config;
-use base::db::MyPool;
+use persistent::{Read, State};
+use base::config::{self, Config, SiteConfig};
+use base::db::Database;
+use repo::Repos;
+use base::news;
+use base::notify;
+use base::migration;
+use base::reload;
+use base::mail::MyMailer;
+use base::feed::FeedVersions;
+use base::csrf::Csrf;
+use base::error::{ErrorPages, CatchPanic};
+use base::access_token::BearerAuth;
+use handlers::api::JsonErrors;
 use mount::Mount;
 use staticfile::Static;
+use std::collections::HashMap;
 use std::path::Path;
+use std::env;
+use std::process;
+use std::sync::{Arc, RwLock};
 
 fn main() {
     // init logging
     log4rs::init_file("log4rs.yaml", Default::default()).unwrap();
 
-    let mut chain = Chain::new(route::gen_router());
-    let config = Config::new();
-    chain.link_before(Read::<Config>::one(config.clone()));
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    // `--config <path>` may come before the command
+    let mut config_path = config::DEFAULT_PATH.to_owned();
+    if args.len() >= 2 && args[0] == "--config" {
+        config_path = args[1].clone();
+        args.drain(..2);
+    }
+
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("bad config: {}", e);
+            process::exit(1);
+        }
+    };
+    let db = match Database::connect(&config) {
+        Ok(db) => db,
+        Err(e) => {
+            println!("cannot open the database: {}", e);
+            process::exit(1);
+        }
+    };
 
-    let my_pool = MyPool::new(&config);
-    chain.link_before(Read::<MyPool>::one(my_pool));
+    if !args.is_empty() && args[0] != "serve" {
+        if let Err(e) = command::run(&db, &args) {
+            println!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
-    let cookie_sign_key = config.get("cookie_sign_key").as_str().unwrap().as_bytes().to_owned();
-    chain.link_around(iron_login::LoginManager::new(cookie_sign_key));
+    if let Err(e) = migration::check(&db) {
+        error!("{}", e);
+        println!("{}", e);
+        process::exit(1);
+    }
+
+    // `serve --dev` reloads templates as soon as they are saved
+    let dev = args.len() == 2 && args[1] == "--dev";
+    serve(config, db, config_path, dev);
+}
+
+fn serve(config: Config, db: Database, config_path: String, dev: bool) {
+    news::spawn_worker(&config, Repos::new(&db));
+
+    // `[site]` is swapped as a whole on SIGHUP
+    let site = Arc::new(RwLock::new(config.site.clone()));
 
     let mut hbse = HandlebarsEngine::new();
     hbse.add(Box::new(DirectorySource::new("templates/", ".hbs")));
     hbse.reload().unwrap();
+    let hbse = Arc::new(hbse);
+    if dev {
+        reload::watch_templates(hbse.clone(), "templates/");
+    }
+    reload::reload_on_hup(hbse.clone(), site.clone(), config_path);
+
+    let mailer = MyMailer::new(&config);
+    notify::spawn_worker(&config, Repos::new(&db), mailer.clone(), hbse.clone());
+
+    let mount = app(&config, &db, site, hbse, mailer);
+    iron::Iron::new(mount).http(&*config.listen).unwrap();
+}
+
+// The whole site as one handler. Background threads are started by the
+// caller, so tests get the same chains without them.
+fn app(config: &Config, db: &Database, site: Arc<RwLock<SiteConfig>>,
+       hbse: Arc<HandlebarsEngine>, mailer: MyMailer) -> Mount {
+    let mut chain = Chain::new(route::gen_router());
+    chain.link_before(Read::<Config>::one(config.clone()));
+    // `State::one` can't take the shared lock that `reload` writes to
+    chain.link_before(State::<SiteConfig>::both(site).0);
+    chain.link_before(Read::<Repos>::one(Repos::new(db)));
+    chain.link_before(Read::<MyMailer>::one(mailer));
+    chain.link_before(State::<FeedVersions>::both(HashMap::new()).0);
+
+    chain.link_before(Csrf);
+    chain.link_after(Csrf);
+
+    // a panicking handler still ends up on the 500 page
+    chain.link_around(CatchPanic);
+    let cookie_sign_key = config.cookie_sign_key.as_bytes().to_owned();
+    chain.link_around(iron_login::LoginManager::new(cookie_sign_key.clone()));
+    chain.link_after(ErrorPages);
     chain.link_after(hbse);
 
+    // the api shares state and logins with the site, but answers in json
+    let mut api_chain = Chain::new(route::gen_api_router());
+    api_chain.link_before(Read::<Config>::one(config.clone()));
+    api_chain.link_before(Read::<Repos>::one(Repos::new(db)));
+    api_chain.link_before(BearerAuth);
+    api_chain.link_before(Csrf);
+    api_chain.link_after(Csrf);
+    api_chain.link_around(CatchPanic);
+    api_chain.link_around(iron_login::LoginManager::new(cookie_sign_key));
+    api_chain.link_after(JsonErrors);
+
     let mut mount = Mount::new();
     mount.mount("/", chain);
+    mount.mount("/api/v1/", api_chain);
     mount.mount("/static/", Static::new(Path::new("static")));
-
-    let listen = config.get("listen").as_str().unwrap();
-    iron::Iron::new(mount).http(listen).unwrap();
+    mount
 }
@@ -0,0 +1,891 @@
+// Keeps everything in Vecs, for unit tests of handler logic that don't
+// need a database. Search is a plain substring match, and ids are the
+// largest one so far plus one, as in SQLite.
+
+use std::sync::{Arc, Mutex};
+use chrono::*;
+use base::constant;
+use base::model::{AccessToken, Article, ArticleForm, Category, Comment, Message, Session, User};
+use base::news::{self, NewsItem};
+use base::util::gen_gravatar_url;
+use repo::{ArticleFilter, Change, MessageFilter, Moderation, NewComment, ArticleRepo,
+           CommentRepo, MessageRepo, UserRepo, TagRepo, SessionRepo, TokenRepo, NewsRepo,
+           Repos, RepoResult};
+
+#[derive(Default)]
+struct Data {
+    // with their password hash
+    users: Vec<(User, String)>,
+    github_users: Vec<GithubUser>,
+    password_resets: Vec<PasswordReset>,
+    // `user` only has its id set
+    articles: Vec<Article>,
+    // with their article id; `user` and `parent` only have their id set
+    comments: Vec<(u64, Comment)>,
+    // old contents, with their comment id
+    revisions: Vec<(u64, String)>,
+    messages: Vec<StoredMessage>,
+    tags: Vec<String>,
+    sessions: Vec<StoredSession>,
+    tokens: Vec<StoredToken>,
+    // with their normalized url
+    news: Vec<(String, NewsItem)>,
+}
+
+struct GithubUser {
+    id: u64,
+    user_id: Option<u64>,
+}
+
+struct PasswordReset {
+    id: u64,
+    user_id: u64,
+    token_hash: String,
+    expire_time: NaiveDateTime,
+    used: bool,
+}
+
+// messages are put together when they are read, like a join would
+struct StoredMessage {
+    id: u64,
+    to_user_id: u64,
+    from_user_id: u64,
+    article_id: u64,
+    comment_id: u64,
+    mode: i8,
+    status: i8,
+    create_time: NaiveDateTime,
+    mailed: bool,
+}
+
+struct StoredSession {
+    session: Session,
+    user_id: u64,
+    token_hash: String,
+    expire_time: NaiveDateTime,
+}
+
+struct StoredToken {
+    token: AccessToken,
+    user_id: u64,
+    token_hash: String,
+}
+
+#[derive(Clone, Default)]
+pub struct Memory(Arc<Mutex<Data>>);
+
+impl Memory {
+    pub fn new() -> Memory {
+        Default::default()
+    }
+
+    // every repo shares this one store
+    pub fn repos(&self) -> Repos {
+        Repos {
+            articles: Box::new(self.clone()),
+            comments: Box::new(self.clone()),
+            messages: Box::new(self.clone()),
+            users: Box::new(self.clone()),
+            tags: Box::new(self.clone()),
+            sessions: Box::new(self.clone()),
+            tokens: Box::new(self.clone()),
+            news: Box::new(self.clone()),
+        }
+    }
+
+    // the filter's normal articles, in the order the site shows them
+    fn filtered(&self, filter: &ArticleFilter) -> Vec<Article> {
+        let data = self.0.lock().unwrap();
+        let mut articles: Vec<Article> = data.articles.iter()
+            .filter(|a| a.status == constant::ARTICLE::STATUS::NORMAL)
+            .filter(|a| match *filter {
+                ArticleFilter::All => true,
+                ArticleFilter::Category(category) => a.category.value == category,
+                ArticleFilter::Tag(ref name) => a.tags.contains(name),
+                ArticleFilter::User(user_id) => a.user.id == user_id,
+                ArticleFilter::Search { ref query, comments } => {
+                    a.title.contains(&**query) || a.content.contains(&**query) ||
+                        (comments && data.comments.iter().any(|&(article_id, ref c)| {
+                            article_id == a.id &&
+                                c.status == constant::COMMENT::STATUS::NORMAL &&
+                                c.content.contains(&**query)
+                        }))
+                },
+            })
+            .map(|a| with_author(&data, a))
+            .collect();
+
+        let top = constant::ARTICLE::FLAG::TOP;
+        match *filter {
+            ArticleFilter::All => articles.sort_by(|a, b| {
+                (b.flag & top, b.priority, b.update_time)
+                    .cmp(&(a.flag & top, a.priority, a.update_time))
+            }),
+            ArticleFilter::Category(_) => articles.sort_by(|a, b| {
+                (b.flag & top, b.priority, b.create_time)
+                    .cmp(&(a.flag & top, a.priority, a.create_time))
+            }),
+            ArticleFilter::Tag(_) => articles.sort_by(|a, b| b.update_time.cmp(&a.update_time)),
+            ArticleFilter::User(_) => articles.sort_by(|a, b| b.create_time.cmp(&a.create_time)),
+            ArticleFilter::Search { ref query, .. } => articles.sort_by(|a, b| {
+                (b.title.contains(&**query), b.update_time)
+                    .cmp(&(a.title.contains(&**query), a.update_time))
+            }),
+        }
+        articles
+    }
+
+    fn messages(&self, filter: &MessageFilter) -> Vec<Message> {
+        let data = self.0.lock().unwrap();
+        data.messages.iter().rev()
+            .filter(|m| {
+                m.to_user_id == filter.to_user_id &&
+                    filter.mode.map_or(true, |mode| m.mode == mode) &&
+                    (!filter.unread_only || m.status == constant::MESSAGE::STATUS::INIT)
+            })
+            .map(|m| message(&data, m))
+            .collect()
+    }
+
+    fn find_user_by<F>(&self, f: F) -> RepoResult<Option<User>> where F: Fn(&User) -> bool {
+        let data = self.0.lock().unwrap();
+        Ok(data.users.iter().map(|&(ref u, _)| u).find(|u| f(*u)).cloned())
+    }
+}
+
+impl ArticleRepo for Memory {
+    fn count(&self, filter: &ArticleFilter) -> RepoResult<usize> {
+        Ok(self.filtered(filter).len())
+    }
+
+    fn list(&self, filter: &ArticleFilter, page: Option<usize>) -> RepoResult<Vec<Article>> {
+        let articles = self.filtered(filter);
+        Ok(match page {
+            Some(page) => articles.into_iter()
+                .skip((page - 1) * constant::PAGE_SIZE)
+                .take(constant::PAGE_SIZE)
+                .collect(),
+            None => articles,
+        })
+    }
+
+    fn newest(&self, filter: &ArticleFilter, limit: usize) -> RepoResult<Vec<Article>> {
+        let mut articles = self.filtered(filter);
+        articles.sort_by(|a, b| b.create_time.cmp(&a.create_time));
+        articles.truncate(limit);
+        Ok(articles)
+    }
+
+    fn find(&self, id: u64) -> RepoResult<Option<Article>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.articles.iter().find(|a| a.id == id).map(|a| with_author(&data, a)))
+    }
+
+    fn all(&self) -> RepoResult<Vec<Article>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.articles.iter().map(|a| with_author(&data, a)).collect())
+    }
+
+    fn create(&self, user_id: u64, form: &ArticleForm) -> RepoResult<u64> {
+        let mut data = self.0.lock().unwrap();
+        let data = &mut *data;
+        let id = next_id(data.articles.iter().map(|a| a.id));
+        let now = Local::now().naive_local();
+        let tags = match form.tags {
+            Some(ref tags) => save_tags(&mut data.tags, tags),
+            None => Vec::new(),
+        };
+        data.articles.push(Article {
+            id: id,
+            category: Category::from_value(form.category as i8),
+            title: form.title.clone(),
+            content: form.content.clone(),
+            user: User { id: user_id, ..Default::default() },
+            create_time: now,
+            update_time: now,
+            tags: tags,
+            ..Default::default()
+        });
+        Ok(id)
+    }
+
+    fn update(&self, id: u64, author_id: u64, form: &ArticleForm) -> RepoResult<Change> {
+        let mut data = self.0.lock().unwrap();
+        let data = &mut *data;
+        let change = check_article(&data.articles, id, Some(author_id),
+                                   constant::ARTICLE::STATUS::NORMAL);
+        if change != Change::Done {
+            return Ok(change);
+        }
+        let tags = form.tags.as_ref().map(|tags| save_tags(&mut data.tags, tags));
+        if let Some(article) = data.articles.iter_mut().find(|a| a.id == id) {
+            article.category = Category::from_value(form.category as i8);
+            article.title = form.title.clone();
+            article.content = form.content.clone();
+            if let Some(tags) = tags {
+                article.tags = tags;
+            }
+            article.update_time = Local::now().naive_local();
+        }
+        Ok(Change::Done)
+    }
+
+    fn set_status(&self, id: u64, author_id: Option<u64>, from_status: i8,
+                  to_status: i8) -> RepoResult<Change> {
+        let mut data = self.0.lock().unwrap();
+        let change = check_article(&data.articles, id, author_id, from_status);
+        if change != Change::Done {
+            return Ok(change);
+        }
+        if let Some(article) = data.articles.iter_mut().find(|a| a.id == id) {
+            article.status = to_status;
+        }
+        Ok(Change::Done)
+    }
+
+    fn moderate(&self, id: u64, moderation: &Moderation) -> RepoResult<bool> {
+        let mut data = self.0.lock().unwrap();
+        let article = match data.articles.iter_mut()
+            .find(|a| a.id == id && a.status == constant::ARTICLE::STATUS::NORMAL) {
+            Some(article) => article,
+            None => return Ok(false),
+        };
+
+        match *moderation {
+            Moderation::Flag(flag, true) => article.flag |= flag,
+            Moderation::Flag(flag, false) => article.flag &= !flag,
+            Moderation::Priority(priority) => article.priority = priority,
+            Moderation::Category(category) => article.category = Category::from_value(category),
+        }
+        Ok(true)
+    }
+}
+
+impl CommentRepo for Memory {
+    fn find(&self, id: u64) -> RepoResult<Option<Comment>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.comments.iter()
+           .find(|&&(_, ref c)| c.id == id)
+           .map(|&(article_id, ref c)| comment_stub(article_id, c)))
+    }
+
+    fn list_by_article(&self, article_id: u64) -> RepoResult<Vec<Comment>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.comments.iter()
+           .filter(|&&(id, _)| id == article_id)
+           .map(|&(_, ref c)| Comment { user: find_user(&data, c.user.id), ..c.clone() })
+           .collect())
+    }
+
+    fn list_by_user(&self, user_id: u64) -> RepoResult<Vec<Comment>> {
+        let data = self.0.lock().unwrap();
+        let mut comments: Vec<Comment> = data.comments.iter()
+            .filter(|&&(_, ref c)| {
+                c.user.id == user_id && c.status == constant::COMMENT::STATUS::NORMAL
+            })
+            .filter_map(|&(article_id, ref c)| {
+                data.articles.iter()
+                    .find(|a| a.id == article_id &&
+                          a.status == constant::ARTICLE::STATUS::NORMAL)
+                    .map(|a| {
+                        let mut article = Article::default();
+                        article.id = a.id;
+                        article.title = a.title.clone();
+                        Comment {
+                            id: c.id,
+                            content: c.content.clone(),
+                            create_time: c.create_time,
+                            article: Some(article),
+                            ..Default::default()
+                        }
+                    })
+            })
+            .collect();
+        comments.reverse();
+        Ok(comments)
+    }
+
+    fn count_by_user(&self, user_id: u64) -> RepoResult<usize> {
+        self.list_by_user(user_id).map(|comments| comments.len())
+    }
+
+    fn all(&self) -> RepoResult<Vec<Comment>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.comments.iter()
+           .map(|&(article_id, ref c)| comment_stub(article_id, c))
+           .collect())
+    }
+
+    fn create(&self, comment: &NewComment) -> RepoResult<u64> {
+        let mut data = self.0.lock().unwrap();
+        let data = &mut *data;
+        let now = Local::now().naive_local();
+        let id = next_id(data.comments.iter().map(|&(_, ref c)| c.id));
+
+        data.comments.push((comment.article_id, Comment {
+            id: id,
+            user: User { id: comment.user_id, ..Default::default() },
+            content: comment.content.clone(),
+            create_time: now,
+            parent: comment.parent_id.map(|parent_id| Box::new(Comment {
+                id: parent_id,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }));
+
+        if let Some(article) = data.articles.iter_mut().find(|a| a.id == comment.article_id) {
+            article.comments_count += 1;
+            article.update_time = now;
+        }
+
+        for &(to_user_id, mode) in &comment.notify {
+            let message_id = next_id(data.messages.iter().map(|m| m.id));
+            data.messages.push(StoredMessage {
+                id: message_id,
+                to_user_id: to_user_id,
+                from_user_id: comment.user_id,
+                article_id: comment.article_id,
+                comment_id: id,
+                mode: mode,
+                status: constant::MESSAGE::STATUS::INIT,
+                create_time: now,
+                mailed: false,
+            });
+        }
+        Ok(id)
+    }
+
+    fn edit(&self, id: u64, content: &str) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        let data = &mut *data;
+        if let Some(&mut (_, ref mut c)) = data.comments.iter_mut()
+            .find(|&&mut (_, ref c)| c.id == id) {
+            data.revisions.push((id, c.content.clone()));
+            c.content = content.to_owned();
+            c.update_time = Some(Local::now().naive_local());
+        }
+        Ok(())
+    }
+
+    fn revisions(&self, id: u64) -> RepoResult<Vec<String>> {
+        Ok(self.0.lock().unwrap().revisions.iter()
+           .filter(|&&(comment_id, _)| comment_id == id)
+           .map(|&(_, ref content)| content.clone())
+           .collect())
+    }
+
+    fn delete(&self, id: u64) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        let data = &mut *data;
+        let article_id = match data.comments.iter_mut().find(|&&mut (_, ref c)| {
+            c.id == id && c.status == constant::COMMENT::STATUS::NORMAL
+        }) {
+            Some(&mut (article_id, ref mut c)) => {
+                c.status = constant::COMMENT::STATUS::DELETED;
+                c.update_time = Some(Local::now().naive_local());
+                article_id
+            },
+            None => return Ok(()),
+        };
+
+        if let Some(article) = data.articles.iter_mut().find(|a| a.id == article_id) {
+            article.comments_count -= 1;
+        }
+        data.messages.retain(|m| m.comment_id != id);
+        Ok(())
+    }
+}
+
+impl MessageRepo for Memory {
+    fn count(&self, filter: &MessageFilter) -> RepoResult<usize> {
+        Ok(self.messages(filter).len())
+    }
+
+    fn list(&self, filter: &MessageFilter, page: Option<usize>) -> RepoResult<Vec<Message>> {
+        let messages = self.messages(filter);
+        Ok(match page {
+            Some(page) => messages.into_iter()
+                .skip((page - 1) * constant::PAGE_SIZE)
+                .take(constant::PAGE_SIZE)
+                .collect(),
+            None => messages,
+        })
+    }
+
+    fn count_unread(&self, to_user_id: u64) -> RepoResult<usize> {
+        MessageRepo::count(self, &MessageFilter {
+            to_user_id: to_user_id,
+            mode: None,
+            unread_only: true,
+        })
+    }
+
+    fn mark_read(&self, to_user_id: u64, last_id: Option<u64>) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        for m in data.messages.iter_mut() {
+            if m.to_user_id == to_user_id && m.id <= last_id.unwrap_or(m.id) {
+                m.status = constant::MESSAGE::STATUS::READ;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_status(&self, to_user_id: u64, id: u64, status: i8) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        for m in data.messages.iter_mut() {
+            if m.to_user_id == to_user_id && m.id == id {
+                m.status = status;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&self, to_user_id: u64, id: u64) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        data.messages.retain(|m| !(m.to_user_id == to_user_id && m.id == id));
+        Ok(())
+    }
+
+    fn list_unmailed(&self, notify: i8,
+                     before: NaiveDateTime) -> RepoResult<Vec<(u64, Message)>> {
+        let data = self.0.lock().unwrap();
+        let mut messages: Vec<&StoredMessage> = data.messages.iter()
+            .filter(|m| {
+                !m.mailed && m.create_time < before &&
+                    data.users.iter()
+                    .any(|&(ref u, _)| u.id == m.to_user_id && u.notify == notify)
+            })
+            .collect();
+        messages.sort_by(|a, b| (a.to_user_id, a.id).cmp(&(b.to_user_id, b.id)));
+        Ok(messages.into_iter().map(|m| (m.to_user_id, message(&data, m))).collect())
+    }
+
+    fn set_mailed(&self, ids: &[u64], _: NaiveDateTime) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        for m in data.messages.iter_mut() {
+            if ids.contains(&m.id) {
+                m.mailed = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UserRepo for Memory {
+    fn find(&self, id: u64) -> RepoResult<Option<User>> {
+        self.find_user_by(|u| u.id == id)
+    }
+
+    fn find_by_username(&self, username: &str) -> RepoResult<Option<User>> {
+        self.find_user_by(|u| u.username == username)
+    }
+
+    fn find_by_email(&self, email: &str) -> RepoResult<Option<User>> {
+        self.find_user_by(|u| u.email == email)
+    }
+
+    fn all(&self) -> RepoResult<Vec<User>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.users.iter().map(|&(ref u, _)| u.clone()).collect())
+    }
+
+    fn count(&self) -> RepoResult<usize> {
+        Ok(self.0.lock().unwrap().users.len())
+    }
+
+    fn rank(&self, id: u64) -> RepoResult<usize> {
+        let data = self.0.lock().unwrap();
+        Ok(data.users.iter().filter(|&&(ref u, _)| u.id < id).count() + 1)
+    }
+
+    fn create(&self, username: &str, email: &str, password_hash: &str,
+              notify: i8) -> RepoResult<Option<u64>> {
+        let mut data = self.0.lock().unwrap();
+        Ok(add_user(&mut data, username, email, password_hash, notify))
+    }
+
+    fn password(&self, id: u64) -> RepoResult<Option<(String, String)>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.users.iter()
+           .find(|&&(ref u, _)| u.id == id)
+           .map(|&(_, ref password)| (password.clone(), String::new())))
+    }
+
+    fn set_password(&self, id: u64, password_hash: &str) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        if let Some(&mut (_, ref mut password)) = data.users.iter_mut()
+            .find(|&&mut (ref u, _)| u.id == id) {
+            *password = password_hash.to_owned();
+        }
+        Ok(())
+    }
+
+    fn set_status(&self, id: u64, status: i8) -> RepoResult<()> {
+        update_user(&self.0, id, |u| u.status = status)
+    }
+
+    fn set_role(&self, id: u64, role: i8) -> RepoResult<()> {
+        update_user(&self.0, id, |u| u.role = role)
+    }
+
+    fn set_notify(&self, id: u64, notify: i8) -> RepoResult<()> {
+        update_user(&self.0, id, |u| u.notify = notify)
+    }
+
+    fn save_github_user(&self, github_id: u64, _: &str, _: &str,
+                        _: &str) -> RepoResult<Option<u64>> {
+        let mut data = self.0.lock().unwrap();
+        if let Some(github_user) = data.github_users.iter().find(|g| g.id == github_id) {
+            return Ok(github_user.user_id);
+        }
+        data.github_users.push(GithubUser { id: github_id, user_id: None });
+        Ok(None)
+    }
+
+    fn bind_github_user(&self, github_id: u64, user_id: u64) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        if let Some(github_user) = data.github_users.iter_mut().find(|g| g.id == github_id) {
+            github_user.user_id = Some(user_id);
+        }
+        Ok(())
+    }
+
+    fn create_from_github(&self, github_id: u64, username: &str,
+                          email: &str) -> RepoResult<Option<u64>> {
+        let mut data = self.0.lock().unwrap();
+        let notify = constant::USER::NOTIFY::OFF;
+        let user_id = match add_user(&mut data, username, email, "", notify) {
+            Some(user_id) => user_id,
+            None => return Ok(None),
+        };
+        if let Some(github_user) = data.github_users.iter_mut().find(|g| g.id == github_id) {
+            github_user.user_id = Some(user_id);
+        }
+        Ok(Some(user_id))
+    }
+
+    fn create_password_reset(&self, user_id: u64, token_hash: &str,
+                             expire_time: NaiveDateTime) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        let id = next_id(data.password_resets.iter().map(|r| r.id));
+        data.password_resets.push(PasswordReset {
+            id: id,
+            user_id: user_id,
+            token_hash: token_hash.to_owned(),
+            expire_time: expire_time,
+            used: false,
+        });
+        Ok(())
+    }
+
+    fn find_password_reset(&self, token_hash: &str,
+                           now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.password_resets.iter()
+           .find(|r| r.token_hash == token_hash && !r.used && r.expire_time > now)
+           .map(|r| (r.id, r.user_id)))
+    }
+
+    fn reset_password(&self, reset_id: u64, user_id: u64,
+                      password_hash: &str) -> RepoResult<bool> {
+        {
+            let mut data = self.0.lock().unwrap();
+            match data.password_resets.iter_mut().find(|r| r.id == reset_id && !r.used) {
+                Some(reset) => reset.used = true,
+                None => return Ok(false),
+            }
+            for reset in data.password_resets.iter_mut().filter(|r| r.user_id == user_id) {
+                reset.used = true;
+            }
+            data.sessions.retain(|s| s.user_id != user_id);
+            data.tokens.retain(|t| t.user_id != user_id);
+        }
+        try!(self.set_password(user_id, password_hash));
+        Ok(true)
+    }
+}
+
+impl TagRepo for Memory {
+    fn exists(&self, name: &str) -> RepoResult<bool> {
+        Ok(self.0.lock().unwrap().tags.iter().any(|tag| tag == name))
+    }
+
+    fn complete(&self, prefix: &str) -> RepoResult<Vec<String>> {
+        let data = self.0.lock().unwrap();
+        let mut counts: Vec<(usize, &String)> = data.tags.iter()
+            .filter(|tag| tag.starts_with(prefix))
+            .map(|tag| (data.articles.iter().filter(|a| a.tags.contains(tag)).count(), tag))
+            .filter(|&(count, _)| count > 0)
+            .collect();
+        counts.sort_by(|a, b| b.0.cmp(&a.0));
+        Ok(counts.into_iter().take(10).map(|(_, tag)| tag.clone()).collect())
+    }
+}
+
+impl SessionRepo for Memory {
+    fn create(&self, user_id: u64, token_hash: &str, ip: &str, user_agent: &str,
+              now: NaiveDateTime, expire_time: NaiveDateTime) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        let id = next_id(data.sessions.iter().map(|s| s.session.id));
+        data.sessions.push(StoredSession {
+            session: Session {
+                id: id,
+                last_seen_ip: ip.to_owned(),
+                user_agent: user_agent.to_owned(),
+                create_time: now,
+                last_seen_time: now,
+                is_current: false,
+            },
+            user_id: user_id,
+            token_hash: token_hash.to_owned(),
+            expire_time: expire_time,
+        });
+        Ok(())
+    }
+
+    fn find(&self, token_hash: &str, now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.sessions.iter()
+           .find(|s| s.token_hash == token_hash && s.expire_time > now)
+           .map(|s| (s.session.id, s.user_id)))
+    }
+
+    fn touch(&self, id: u64, ip: &str, user_agent: &str, now: NaiveDateTime,
+             before: NaiveDateTime) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        if let Some(s) = data.sessions.iter_mut()
+            .find(|s| s.session.id == id && s.session.last_seen_time < before) {
+            s.session.last_seen_ip = ip.to_owned();
+            s.session.user_agent = user_agent.to_owned();
+            s.session.last_seen_time = now;
+        }
+        Ok(())
+    }
+
+    fn list(&self, user_id: u64, now: NaiveDateTime) -> RepoResult<Vec<Session>> {
+        let data = self.0.lock().unwrap();
+        let mut sessions: Vec<Session> = data.sessions.iter()
+            .filter(|s| s.user_id == user_id && s.expire_time > now)
+            .map(|s| s.session.clone())
+            .collect();
+        sessions.sort_by(|a, b| b.last_seen_time.cmp(&a.last_seen_time));
+        Ok(sessions)
+    }
+
+    fn delete_by_token(&self, token_hash: &str) -> RepoResult<()> {
+        self.0.lock().unwrap().sessions.retain(|s| s.token_hash != token_hash);
+        Ok(())
+    }
+
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()> {
+        self.0.lock().unwrap().sessions
+            .retain(|s| !(s.user_id == user_id && s.session.id == id));
+        Ok(())
+    }
+
+    fn delete_all(&self, user_id: u64) -> RepoResult<()> {
+        self.0.lock().unwrap().sessions.retain(|s| s.user_id != user_id);
+        Ok(())
+    }
+
+    fn delete_others(&self, user_id: u64, keep_id: u64) -> RepoResult<()> {
+        self.0.lock().unwrap().sessions
+            .retain(|s| s.user_id != user_id || s.session.id == keep_id);
+        Ok(())
+    }
+}
+
+impl TokenRepo for Memory {
+    fn create(&self, user_id: u64, name: &str, token_hash: &str, scopes: u8,
+              now: NaiveDateTime) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        let id = next_id(data.tokens.iter().map(|t| t.token.id));
+        data.tokens.push(StoredToken {
+            token: AccessToken {
+                id: id,
+                name: name.to_owned(),
+                scopes: scopes,
+                create_time: now,
+                last_used_time: None,
+            },
+            user_id: user_id,
+            token_hash: token_hash.to_owned(),
+        });
+        Ok(())
+    }
+
+    fn find(&self, token_hash: &str) -> RepoResult<Option<(u64, u8, u64)>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.tokens.iter()
+           .find(|t| t.token_hash == token_hash)
+           .map(|t| (t.token.id, t.token.scopes, t.user_id)))
+    }
+
+    fn touch(&self, id: u64, now: NaiveDateTime, before: NaiveDateTime) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        if let Some(t) = data.tokens.iter_mut().find(|t| t.token.id == id) {
+            if t.token.last_used_time.map_or(true, |time| time < before) {
+                t.token.last_used_time = Some(now);
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self, user_id: u64) -> RepoResult<Vec<AccessToken>> {
+        let data = self.0.lock().unwrap();
+        Ok(data.tokens.iter().rev()
+           .filter(|t| t.user_id == user_id)
+           .map(|t| t.token.clone())
+           .collect())
+    }
+
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()> {
+        self.0.lock().unwrap().tokens.retain(|t| !(t.user_id == user_id && t.token.id == id));
+        Ok(())
+    }
+
+    fn delete_all(&self, user_id: u64) -> RepoResult<()> {
+        self.0.lock().unwrap().tokens.retain(|t| t.user_id != user_id);
+        Ok(())
+    }
+}
+
+impl NewsRepo for Memory {
+    fn save(&self, items: &[NewsItem]) -> RepoResult<()> {
+        let mut data = self.0.lock().unwrap();
+        for item in items {
+            let url = news::normalize_url(&item.url);
+            let is_new = match data.news.iter_mut().find(|&&mut (ref u, _)| *u == url) {
+                Some(&mut (_, ref mut saved)) => {
+                    if saved.source == item.source {
+                        saved.score = item.score;
+                        saved.comments_count = item.comments_count;
+                    }
+                    false
+                },
+                None => true,
+            };
+            if is_new {
+                data.news.push((url, item.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn count(&self) -> RepoResult<usize> {
+        Ok(self.0.lock().unwrap().news.len())
+    }
+
+    fn list(&self, page: usize) -> RepoResult<Vec<NewsItem>> {
+        let data = self.0.lock().unwrap();
+        let mut items: Vec<(usize, &NewsItem)> = data.news.iter()
+            .enumerate()
+            .map(|(i, &(_, ref item))| (i, item))
+            .collect();
+        items.sort_by(|a, b| (b.1.publish_time, b.0).cmp(&(a.1.publish_time, a.0)));
+        Ok(items.into_iter()
+           .skip((page - 1) * constant::PAGE_SIZE)
+           .take(constant::PAGE_SIZE)
+           .map(|(_, item)| item.clone())
+           .collect())
+    }
+}
+
+fn next_id<I: Iterator<Item = u64>>(ids: I) -> u64 {
+    ids.max().unwrap_or(0) + 1
+}
+
+fn find_user(data: &Data, user_id: u64) -> User {
+    data.users.iter()
+        .find(|&&(ref u, _)| u.id == user_id)
+        .map(|&(ref u, _)| u.clone())
+        .unwrap_or(User::default())
+}
+
+// None when the username or email is taken
+fn add_user(data: &mut Data, username: &str, email: &str, password_hash: &str,
+            notify: i8) -> Option<u64> {
+    if data.users.iter().any(|&(ref u, _)| u.username == username || u.email == email) {
+        return None;
+    }
+
+    let id = next_id(data.users.iter().map(|&(ref u, _)| u.id));
+    data.users.push((User {
+        id: id,
+        username: username.to_owned(),
+        email: email.to_owned(),
+        avatar: gen_gravatar_url(email),
+        notify: notify,
+        create_time: Local::now().naive_local(),
+        ..Default::default()
+    }, password_hash.to_owned()));
+    Some(id)
+}
+
+fn update_user<F>(data: &Mutex<Data>, id: u64, f: F) -> RepoResult<()>
+    where F: FnOnce(&mut User) {
+    let mut data = data.lock().unwrap();
+    if let Some(&mut (ref mut u, _)) = data.users.iter_mut()
+        .find(|&&mut (ref u, _)| u.id == id) {
+        f(u);
+    }
+    Ok(())
+}
+
+fn with_author(data: &Data, article: &Article) -> Article {
+    Article { user: find_user(data, article.user.id), ..article.clone() }
+}
+
+// as CommentRepo::find has it, `article` only has its id set
+fn comment_stub(article_id: u64, comment: &Comment) -> Comment {
+    let mut article = Article::default();
+    article.id = article_id;
+    Comment { article: Some(article), ..comment.clone() }
+}
+
+fn message(data: &Data, m: &StoredMessage) -> Message {
+    let mut article = Article::default();
+    article.id = m.article_id;
+    article.title = data.articles.iter().find(|a| a.id == m.article_id)
+        .map(|a| a.title.clone()).unwrap_or(String::new());
+    let content = data.comments.iter().find(|&&(_, ref c)| c.id == m.comment_id)
+        .map(|&(_, ref c)| c.content.clone()).unwrap_or(String::new());
+
+    Message {
+        id: m.id,
+        mode: m.mode,
+        status: m.status,
+        create_time: m.create_time,
+        from_user: find_user(data, m.from_user_id),
+        article: article,
+        comment: Comment {
+            id: m.comment_id,
+            content: content,
+            ..Default::default()
+        },
+    }
+}
+
+fn check_article(articles: &[Article], id: u64, author_id: Option<u64>, status: i8) -> Change {
+    match articles.iter().find(|a| a.id == id && a.status == status) {
+        None => Change::NotFound,
+        Some(article) => match author_id {
+            Some(author_id) if author_id != article.user.id => Change::Forbidden,
+            _ => Change::Done,
+        },
+    }
+}
+
+// the tag names of an article, each saved as a tag, sorted as they are read
+fn save_tags(saved: &mut Vec<String>, tags: &[String]) -> Vec<String> {
+    let mut tags = tags.to_vec();
+    tags.sort();
+    tags.dedup();
+    for tag in &tags {
+        if !saved.contains(tag) {
+            saved.push(tag.clone());
+        }
+    }
+    tags
+}
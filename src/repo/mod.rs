@@ -0,0 +1,266 @@
+// Everything the site reads from and writes to the database. Handlers get a
+// `Repos` from the request instead of writing SQL, so that the same site
+// runs on MySQL or SQLite, see `base::db::Database`. Unit tests of handler
+// logic can use the in-memory versions instead.
+
+pub mod mysql;
+pub mod sqlite;
+#[cfg(test)]
+pub mod memory;
+
+use chrono::NaiveDateTime;
+use iron::typemap::Key;
+use base::db::{Database, DbError};
+use base::model::{AccessToken, Article, ArticleForm, Comment, Message, Session, User};
+use base::news::NewsItem;
+
+pub type RepoResult<T> = Result<T, DbError>;
+
+// which normal articles a listing shows, each in the order the site shows them
+pub enum ArticleFilter {
+    // pinned first, then by priority and last activity
+    All,
+    // pinned first, then by priority, newest first
+    Category(i8),
+    // by last activity
+    Tag(String),
+    // newest first
+    User(u64),
+    // best match first, `comments` also matches the replies
+    Search { query: String, comments: bool },
+}
+
+// what a moderator changes of an article
+pub enum Moderation {
+    // a constant::ARTICLE::FLAG, switched on or off
+    Flag(u8, bool),
+    Priority(i8),
+    Category(i8),
+}
+
+// how a change that only some users may make went, checked in the same
+// transaction as the write
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Done,
+    // no such article, or not in the status the change starts from
+    NotFound,
+    // somebody else's
+    Forbidden,
+}
+
+// which messages of one user a listing shows
+pub struct MessageFilter {
+    pub to_user_id: u64,
+    // a constant::MESSAGE::MODE, or every mode for `None`
+    pub mode: Option<i8>,
+    pub unread_only: bool,
+}
+
+pub struct NewComment {
+    pub article_id: u64,
+    pub user_id: u64,
+    pub parent_id: Option<u64>,
+    pub content: String,
+    // (user id, constant::MESSAGE::MODE) of everyone to tell about it
+    pub notify: Vec<(u64, i8)>,
+}
+
+pub trait ArticleRepo: Send + Sync {
+    fn count(&self, filter: &ArticleFilter) -> RepoResult<usize>;
+    // one page of PAGE_SIZE articles, or all of them for `None`
+    fn list(&self, filter: &ArticleFilter, page: Option<usize>) -> RepoResult<Vec<Article>>;
+    // at most `limit` of them, newest first whatever the filter's own order
+    fn newest(&self, filter: &ArticleFilter, limit: usize) -> RepoResult<Vec<Article>>;
+    // whatever its status, with its author and tags but no comments
+    fn find(&self, id: u64) -> RepoResult<Option<Article>>;
+    // every article whatever its status, by id
+    fn all(&self) -> RepoResult<Vec<Article>>;
+    fn create(&self, user_id: u64, form: &ArticleForm) -> RepoResult<u64>;
+    // only a normal article of `author_id`
+    fn update(&self, id: u64, author_id: u64, form: &ArticleForm) -> RepoResult<Change>;
+    // only an article in `from_status`, of `author_id` unless that is `None`;
+    // the update time is kept, deleting is no activity
+    fn set_status(&self, id: u64, author_id: Option<u64>, from_status: i8,
+                  to_status: i8) -> RepoResult<Change>;
+    // false when there is no such normal article; the update time is kept,
+    // so the article keeps its place on the home page
+    fn moderate(&self, id: u64, moderation: &Moderation) -> RepoResult<bool>;
+}
+
+pub trait CommentRepo: Send + Sync {
+    // whatever its status, `article` only has its id set
+    fn find(&self, id: u64) -> RepoResult<Option<Comment>>;
+    // deleted ones too, oldest first, `parent` only has its id set
+    fn list_by_article(&self, article_id: u64) -> RepoResult<Vec<Comment>>;
+    // normal comments on normal articles, newest first, `article` only has
+    // its id and title set
+    fn list_by_user(&self, user_id: u64) -> RepoResult<Vec<Comment>>;
+    fn count_by_user(&self, user_id: u64) -> RepoResult<usize>;
+    // every comment whatever its status, by id, `article` and `parent` only
+    // have their id set
+    fn all(&self) -> RepoResult<Vec<Comment>>;
+    // also bumps the article and sends the messages, returns the new id
+    fn create(&self, comment: &NewComment) -> RepoResult<u64>;
+    // the old content is kept as a revision
+    fn edit(&self, id: u64, content: &str) -> RepoResult<()>;
+    // the contents a comment had before each edit, oldest first
+    fn revisions(&self, id: u64) -> RepoResult<Vec<String>>;
+    // nobody is told any more about a deleted comment
+    fn delete(&self, id: u64) -> RepoResult<()>;
+}
+
+pub trait MessageRepo: Send + Sync {
+    fn count(&self, filter: &MessageFilter) -> RepoResult<usize>;
+    // one page of PAGE_SIZE messages, or all of them for `None`, newest first
+    fn list(&self, filter: &MessageFilter, page: Option<usize>) -> RepoResult<Vec<Message>>;
+    fn count_unread(&self, to_user_id: u64) -> RepoResult<usize>;
+    // up to and including message `last_id`, or all of them for `None`
+    fn mark_read(&self, to_user_id: u64, last_id: Option<u64>) -> RepoResult<()>;
+    // a constant::MESSAGE::STATUS; messages of other users are left alone
+    fn set_status(&self, to_user_id: u64, id: u64, status: i8) -> RepoResult<()>;
+    fn delete(&self, to_user_id: u64, id: u64) -> RepoResult<()>;
+    // (recipient id, message) of the messages created before `before` that
+    // are not mailed yet, to users whose constant::USER::NOTIFY is `notify`;
+    // by recipient, oldest first
+    fn list_unmailed(&self, notify: i8,
+                     before: NaiveDateTime) -> RepoResult<Vec<(u64, Message)>>;
+    // whether a mail went out for them or they were skipped
+    fn set_mailed(&self, ids: &[u64], now: NaiveDateTime) -> RepoResult<()>;
+}
+
+pub trait UserRepo: Send + Sync {
+    fn find(&self, id: u64) -> RepoResult<Option<User>>;
+    fn find_by_username(&self, username: &str) -> RepoResult<Option<User>>;
+    fn find_by_email(&self, email: &str) -> RepoResult<Option<User>>;
+    // by id
+    fn all(&self) -> RepoResult<Vec<User>>;
+    fn count(&self) -> RepoResult<usize>;
+    // the how manieth member the user is
+    fn rank(&self, id: u64) -> RepoResult<usize>;
+    // None when the username or email is taken; `notify` is a
+    // constant::USER::NOTIFY
+    fn create(&self, username: &str, email: &str, password_hash: &str,
+              notify: i8) -> RepoResult<Option<u64>>;
+    // (password, salt) to check a login against
+    fn password(&self, id: u64) -> RepoResult<Option<(String, String)>>;
+    // a hash of password::hash, which needs no salt
+    fn set_password(&self, id: u64, password_hash: &str) -> RepoResult<()>;
+    fn set_status(&self, id: u64, status: i8) -> RepoResult<()>;
+    fn set_role(&self, id: u64, role: i8) -> RepoResult<()>;
+    fn set_notify(&self, id: u64, notify: i8) -> RepoResult<()>;
+
+    // keeps what github told about one of its users, returns the local
+    // user it is bound to
+    fn save_github_user(&self, github_id: u64, username: &str, email: &str,
+                        avatar_url: &str) -> RepoResult<Option<u64>>;
+    fn bind_github_user(&self, github_id: u64, user_id: u64) -> RepoResult<()>;
+    // a local user without password, bound to the github user; None when
+    // the username or email is taken
+    fn create_from_github(&self, github_id: u64, username: &str,
+                          email: &str) -> RepoResult<Option<u64>>;
+
+    fn create_password_reset(&self, user_id: u64, token_hash: &str,
+                             expire_time: NaiveDateTime) -> RepoResult<()>;
+    // (reset id, user id) of a reset not used and not expired at `now`
+    fn find_password_reset(&self, token_hash: &str,
+                           now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>>;
+    // Sets the password, uses up the reset along with every other one of the
+    // user, and ends the user's sessions and access tokens. False when
+    // somebody else used the reset first.
+    fn reset_password(&self, reset_id: u64, user_id: u64,
+                      password_hash: &str) -> RepoResult<bool>;
+}
+
+pub trait TagRepo: Send + Sync {
+    fn exists(&self, name: &str) -> RepoResult<bool>;
+    // at most 10 tag names starting with `prefix`, the most used first
+    fn complete(&self, prefix: &str) -> RepoResult<Vec<String>>;
+}
+
+// sessions and access tokens are looked up by the sha256 of their token
+pub trait SessionRepo: Send + Sync {
+    fn create(&self, user_id: u64, token_hash: &str, ip: &str, user_agent: &str,
+              now: NaiveDateTime, expire_time: NaiveDateTime) -> RepoResult<()>;
+    // (session id, user id) of a session not expired at `now`
+    fn find(&self, token_hash: &str, now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>>;
+    // only when it was last seen before `before`, to save writes
+    fn touch(&self, id: u64, ip: &str, user_agent: &str, now: NaiveDateTime,
+             before: NaiveDateTime) -> RepoResult<()>;
+    // the live ones, last seen first, none of them `is_current`
+    fn list(&self, user_id: u64, now: NaiveDateTime) -> RepoResult<Vec<Session>>;
+    fn delete_by_token(&self, token_hash: &str) -> RepoResult<()>;
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()>;
+    fn delete_all(&self, user_id: u64) -> RepoResult<()>;
+    fn delete_others(&self, user_id: u64, keep_id: u64) -> RepoResult<()>;
+}
+
+pub trait TokenRepo: Send + Sync {
+    fn create(&self, user_id: u64, name: &str, token_hash: &str, scopes: u8,
+              now: NaiveDateTime) -> RepoResult<()>;
+    // (token id, scopes, user id)
+    fn find(&self, token_hash: &str) -> RepoResult<Option<(u64, u8, u64)>>;
+    // only when it was last used before `before`, to save writes
+    fn touch(&self, id: u64, now: NaiveDateTime, before: NaiveDateTime) -> RepoResult<()>;
+    // newest first
+    fn list(&self, user_id: u64) -> RepoResult<Vec<AccessToken>>;
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()>;
+    fn delete_all(&self, user_id: u64) -> RepoResult<()>;
+}
+
+pub trait NewsRepo: Send + Sync {
+    // An item whose url is already stored keeps its original source; only
+    // the numbers are refreshed.
+    fn save(&self, items: &[NewsItem]) -> RepoResult<()>;
+    fn count(&self) -> RepoResult<usize>;
+    // newest first, `page` starts from 1
+    fn list(&self, page: usize) -> RepoResult<Vec<NewsItem>>;
+}
+
+pub struct Repos {
+    pub articles: Box<ArticleRepo>,
+    pub comments: Box<CommentRepo>,
+    pub messages: Box<MessageRepo>,
+    pub users: Box<UserRepo>,
+    pub tags: Box<TagRepo>,
+    pub sessions: Box<SessionRepo>,
+    pub tokens: Box<TokenRepo>,
+    pub news: Box<NewsRepo>,
+}
+
+impl Repos {
+    pub fn new(db: &Database) -> Repos {
+        match *db {
+            Database::Mysql(ref pool) => Repos {
+                articles: Box::new(self::mysql::MyArticleRepo(pool.clone())),
+                comments: Box::new(self::mysql::MyCommentRepo(pool.clone())),
+                messages: Box::new(self::mysql::MyMessageRepo(pool.clone())),
+                users: Box::new(self::mysql::MyUserRepo(pool.clone())),
+                tags: Box::new(self::mysql::MyTagRepo(pool.clone())),
+                sessions: Box::new(self::mysql::MySessionRepo(pool.clone())),
+                tokens: Box::new(self::mysql::MyTokenRepo(pool.clone())),
+                news: Box::new(self::mysql::MyNewsRepo(pool.clone())),
+            },
+            Database::Sqlite(ref conn) => Repos {
+                articles: Box::new(self::sqlite::LiteArticleRepo(conn.clone())),
+                comments: Box::new(self::sqlite::LiteCommentRepo(conn.clone())),
+                messages: Box::new(self::sqlite::LiteMessageRepo(conn.clone())),
+                users: Box::new(self::sqlite::LiteUserRepo(conn.clone())),
+                tags: Box::new(self::sqlite::LiteTagRepo(conn.clone())),
+                sessions: Box::new(self::sqlite::LiteSessionRepo(conn.clone())),
+                tokens: Box::new(self::sqlite::LiteTokenRepo(conn.clone())),
+                news: Box::new(self::sqlite::LiteNewsRepo(conn.clone())),
+            },
+        }
+    }
+}
+
+impl Key for Repos {
+    type Value = Repos;
+}
+
+// `s` as the start of a `like` pattern that matches it literally, with `\`
+// as the escape character
+fn escape_like(s: &str) -> String {
+    s.replace("\\", "\\\\").replace("%", "\\%").replace("_", "\\_")
+}
@@ -0,0 +1,1025 @@
+use std::collections::HashMap;
+use chrono::*;
+use mysql as my;
+use base::db;
+use base::constant;
+use base::model::{AccessToken, Article, ArticleForm, Category, Comment, Message, Session, User};
+use base::news::{self, NewsItem};
+use base::util::{self, gen_gravatar_url};
+use repo::{ArticleFilter, Change, MessageFilter, Moderation, NewComment, ArticleRepo,
+           CommentRepo, MessageRepo, UserRepo, TagRepo, SessionRepo, TokenRepo, NewsRepo,
+           RepoResult, escape_like};
+
+pub struct MyArticleRepo(pub my::Pool);
+pub struct MyCommentRepo(pub my::Pool);
+pub struct MyMessageRepo(pub my::Pool);
+pub struct MyUserRepo(pub my::Pool);
+pub struct MyTagRepo(pub my::Pool);
+pub struct MySessionRepo(pub my::Pool);
+pub struct MyTokenRepo(pub my::Pool);
+pub struct MyNewsRepo(pub my::Pool);
+
+// the error code of a taken unique key
+const DUPLICATE_ENTRY: u16 = 1062;
+
+// articles are `a` and their authors `u`, see `article_from_row`
+const ARTICLE_COLUMNS: &'static str =
+    "a.id, a.category, a.title, a.content, a.comments_count, a.create_time, \
+     a.update_time, a.flag, a.priority, a.status, u.id as user_id, u.username, u.email";
+
+// see `user_from_row`
+const USER_COLUMNS: &'static str = "id, username, email, role, status, notify, create_time";
+
+// how a filter narrows and orders `article as a`
+struct FilterSql {
+    join: &'static str,
+    condition: String,
+    params: Vec<my::Value>,
+    order: String,
+    order_params: Vec<my::Value>,
+}
+
+impl FilterSql {
+    fn new(filter: &ArticleFilter) -> FilterSql {
+        let mut sql = FilterSql {
+            join: "",
+            condition: "1=1".to_owned(),
+            params: Vec::new(),
+            order: "a.flag & 1 desc, a.priority desc, a.update_time desc".to_owned(),
+            order_params: Vec::new(),
+        };
+
+        match *filter {
+            ArticleFilter::All => {},
+            ArticleFilter::Category(category) => {
+                sql.condition = "a.category=?".to_owned();
+                sql.params.push(category.into());
+                sql.order = "a.flag & 1 desc, a.priority desc, a.create_time desc".to_owned();
+            },
+            ArticleFilter::Tag(ref name) => {
+                sql.join = "join article_tag as at on at.article_id=a.id \
+                            join tag as t on t.id=at.tag_id";
+                sql.condition = "t.name=?".to_owned();
+                sql.params.push(name.clone().into());
+                sql.order = "a.update_time desc".to_owned();
+            },
+            ArticleFilter::User(user_id) => {
+                sql.condition = "a.user_id=?".to_owned();
+                sql.params.push(user_id.into());
+                sql.order = "a.create_time desc".to_owned();
+            },
+            ArticleFilter::Search { ref query, comments } => {
+                // MySQL FULLTEXT indexes with the ngram parser, see
+                // tables.sql. Title matches weigh double.
+                sql.condition = "MATCH(a.title, a.content) AGAINST(?)".to_owned();
+                sql.params.push(query.clone().into());
+                let mut score = "MATCH(a.title) AGAINST(?) * 2 + \
+                                 MATCH(a.title, a.content) AGAINST(?)".to_owned();
+                sql.order_params.push(query.clone().into());
+                sql.order_params.push(query.clone().into());
+
+                if comments {
+                    sql.condition.push_str(" or a.id in (SELECT c.article_id from comment as c \
+                                            where c.status=? and MATCH(c.content) AGAINST(?))");
+                    sql.params.push(constant::COMMENT::STATUS::NORMAL.into());
+                    sql.params.push(query.clone().into());
+                    score.push_str(" + ifnull((SELECT max(MATCH(c.content) AGAINST(?)) \
+                                    from comment as c where c.article_id=a.id and c.status=?), 0)");
+                    sql.order_params.push(query.clone().into());
+                    sql.order_params.push(constant::COMMENT::STATUS::NORMAL.into());
+                }
+                sql.order = format!("{} desc, a.update_time desc", score);
+            },
+        }
+
+        sql
+    }
+
+    // the query of the filter's normal articles, `limit` being sql that
+    // takes `limit_params`
+    fn select(self, limit: &str, limit_params: Vec<my::Value>) -> (String, Vec<my::Value>) {
+        let mut params: Vec<my::Value> = vec![constant::ARTICLE::STATUS::NORMAL.into()];
+        params.extend(self.params);
+        params.extend(self.order_params);
+        params.extend(limit_params);
+
+        (format!("SELECT {} from article as a join user as u on a.user_id=u.id {} \
+                  where a.status=? and ({}) order by {}{}",
+                 ARTICLE_COLUMNS, self.join, self.condition, self.order, limit),
+         params)
+    }
+}
+
+impl MyArticleRepo {
+    // the articles a query of ARTICLE_COLUMNS finds, with their tags
+    fn select(&self, query: String, params: Vec<my::Value>) -> RepoResult<Vec<Article>> {
+        let result = try!(self.0.prep_exec(query, params));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+        let mut articles: Vec<Article> = rows.into_iter().map(article_from_row).collect();
+
+        let article_ids: Vec<u64> = articles.iter().map(|a| a.id).collect();
+        let mut tags = try!(get_tags(&self.0, &article_ids));
+        for article in &mut articles {
+            article.tags = tags.remove(&article.id).unwrap_or(Vec::new());
+        }
+        Ok(articles)
+    }
+}
+
+impl ArticleRepo for MyArticleRepo {
+    fn count(&self, filter: &ArticleFilter) -> RepoResult<usize> {
+        let sql = FilterSql::new(filter);
+        let mut params: Vec<my::Value> = vec![constant::ARTICLE::STATUS::NORMAL.into()];
+        params.extend(sql.params);
+        Ok(try!(db::count(&self.0,
+                          format!("SELECT count(a.id) from article as a {} \
+                                   where a.status=? and ({})", sql.join, sql.condition),
+                          params)))
+    }
+
+    fn list(&self, filter: &ArticleFilter, page: Option<usize>) -> RepoResult<Vec<Article>> {
+        let sql = FilterSql::new(filter);
+        let (query, params) = match page {
+            Some(page) => sql.select(" limit ?,?",
+                                     vec![((page - 1) * constant::PAGE_SIZE).into(),
+                                          constant::PAGE_SIZE.into()]),
+            None => sql.select("", Vec::new()),
+        };
+        self.select(query, params)
+    }
+
+    fn newest(&self, filter: &ArticleFilter, limit: usize) -> RepoResult<Vec<Article>> {
+        let mut sql = FilterSql::new(filter);
+        sql.order = "a.create_time desc".to_owned();
+        sql.order_params.clear();
+        let (query, params) = sql.select(" limit ?", vec![limit.into()]);
+        self.select(query, params)
+    }
+
+    fn find(&self, id: u64) -> RepoResult<Option<Article>> {
+        let articles = try!(self.select(
+            format!("SELECT {} from article as a join user as u on a.user_id=u.id \
+                     where a.id=?", ARTICLE_COLUMNS),
+            vec![id.into()]));
+        Ok(articles.into_iter().next())
+    }
+
+    fn all(&self) -> RepoResult<Vec<Article>> {
+        self.select(format!("SELECT {} from article as a join user as u on a.user_id=u.id \
+                             order by a.id", ARTICLE_COLUMNS),
+                    Vec::new())
+    }
+
+    fn create(&self, user_id: u64, form: &ArticleForm) -> RepoResult<u64> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+        let article_id = try!(trans.prep_exec("INSERT INTO article(category, title, content, \
+                                               user_id, create_time, update_time) \
+                                               VALUES (?, ?, ?, ?, ?, ?)",
+                                              (form.category, &form.title, &form.content,
+                                               user_id, now, now)))
+            .last_insert_id();
+        if let Some(ref tags) = form.tags {
+            try!(save_tags(&mut trans, article_id, tags));
+        }
+        try!(trans.commit());
+        Ok(article_id)
+    }
+
+    fn update(&self, id: u64, author_id: u64, form: &ArticleForm) -> RepoResult<Change> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+        let change = try!(check_article(&mut trans, id, Some(author_id),
+                                        constant::ARTICLE::STATUS::NORMAL));
+        if change != Change::Done {
+            return Ok(change);
+        }
+        try!(trans.prep_exec("UPDATE article set category=?, title=?, content=?, \
+                              update_time=? where id=?",
+                             (form.category, &form.title, &form.content, now, id)));
+        if let Some(ref tags) = form.tags {
+            try!(save_tags(&mut trans, id, tags));
+        }
+        try!(trans.commit());
+        Ok(Change::Done)
+    }
+
+    fn set_status(&self, id: u64, author_id: Option<u64>, from_status: i8,
+                  to_status: i8) -> RepoResult<Change> {
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+        let change = try!(check_article(&mut trans, id, author_id, from_status));
+        if change != Change::Done {
+            return Ok(change);
+        }
+        try!(trans.prep_exec("UPDATE article set status=? where id=?", (to_status, id)));
+        try!(trans.commit());
+        Ok(Change::Done)
+    }
+
+    fn moderate(&self, id: u64, moderation: &Moderation) -> RepoResult<bool> {
+        // an update to the same value affects no rows, so look first
+        if try!(self.0.prep_exec("SELECT id from article where id=? and status=?",
+                                 (id, constant::ARTICLE::STATUS::NORMAL))).next().is_none() {
+            return Ok(false);
+        }
+
+        let (assignment, value): (&str, my::Value) = match *moderation {
+            Moderation::Flag(flag, true) => ("flag=flag | ?", flag.into()),
+            Moderation::Flag(flag, false) => ("flag=flag & ~?", flag.into()),
+            Moderation::Priority(priority) => ("priority=?", priority.into()),
+            Moderation::Category(category) => ("category=?", category.into()),
+        };
+        try!(self.0.prep_exec(format!("UPDATE article set {} where id=?", assignment),
+                              (value, id)));
+        Ok(true)
+    }
+}
+
+impl CommentRepo for MyCommentRepo {
+    fn find(&self, id: u64) -> RepoResult<Option<Comment>> {
+        let raw_row = try!(self.0.prep_exec(
+            "SELECT c.article_id, c.parent_id, c.user_id, c.content, c.status, \
+             c.create_time, c.update_time from comment as c where c.id=?", (id,))).next();
+
+        match raw_row {
+            Some(row) => {
+                let (article_id, parent_id, user_id, content, status,
+                     create_time, update_time) = my::from_row(try!(row));
+                Ok(Some(comment_stub(id, article_id, parent_id, user_id, content, status,
+                                     create_time, update_time)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn list_by_article(&self, article_id: u64) -> RepoResult<Vec<Comment>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT c.id, c.parent_id, c.content, c.status, c.create_time, \
+             c.update_time, u.id as user_id, u.username, u.email from comment \
+             as c join user as u on c.user_id=u.id where c.article_id=? \
+             order by c.create_time", (article_id,)));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (id, parent_id, content, status, create_time, update_time,
+                 user_id, username, email) = my::from_row::<(
+                     u64, Option<u64>, String, i8, NaiveDateTime,
+                     Option<NaiveDateTime>, u64, String, String)>(row);
+
+            Comment {
+                id: id,
+                user: user(user_id, username, email, *constant::DEFAULT_DATETIME),
+                content: content,
+                status: status,
+                create_time: create_time,
+                update_time: update_time,
+                parent: parent_id.map(|parent_id| Box::new(Comment {
+                    id: parent_id,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+        }).collect())
+    }
+
+    fn list_by_user(&self, user_id: u64) -> RepoResult<Vec<Comment>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT c.id, c.content, c.create_time, \
+             a.id as article_id, a.title as article_title from comment as c \
+             join article as a on c.article_id=a.id where c.user_id=? \
+             and a.status=? and c.status=? order by c.create_time desc",
+            (user_id, constant::ARTICLE::STATUS::NORMAL, constant::COMMENT::STATUS::NORMAL)));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (id, content, create_time, article_id, article_title) = my::from_row(row);
+
+            let mut article = Article::default();
+            article.id = article_id;
+            article.title = article_title;
+
+            Comment {
+                id: id,
+                content: content,
+                create_time: create_time,
+                article: Some(article),
+                ..Default::default()
+            }
+        }).collect())
+    }
+
+    fn count_by_user(&self, user_id: u64) -> RepoResult<usize> {
+        Ok(try!(db::count(&self.0, "SELECT count(c.id) from comment as c \
+                                    join article as a on c.article_id=a.id \
+                                    where c.user_id=? and a.status=? and c.status=?",
+                          (user_id, constant::ARTICLE::STATUS::NORMAL,
+                           constant::COMMENT::STATUS::NORMAL))))
+    }
+
+    fn all(&self) -> RepoResult<Vec<Comment>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT id, article_id, parent_id, user_id, content, status, \
+             create_time, update_time from comment order by id", ()));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (id, article_id, parent_id, user_id, content, status,
+                 create_time, update_time) = my::from_row(row);
+            comment_stub(id, article_id, parent_id, user_id, content, status,
+                         create_time, update_time)
+        }).collect())
+    }
+
+    fn create(&self, comment: &NewComment) -> RepoResult<u64> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+
+        let comment_id = try!(trans.prep_exec(
+            "INSERT INTO comment(article_id, user_id, parent_id, content, create_time) \
+             VALUES (?, ?, ?, ?, ?)",
+            (comment.article_id, comment.user_id, comment.parent_id,
+             &comment.content, now))).last_insert_id();
+
+        try!(trans.prep_exec("UPDATE article set comments_count=comments_count+1, \
+                              update_time=? where id=?",
+                             (now, comment.article_id)));
+
+        for &(to_user_id, mode) in &comment.notify {
+            try!(trans.prep_exec("INSERT INTO message(article_id, comment_id, \
+                                  from_user_id, to_user_id, mode, \
+                                  status, create_time) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                                 (comment.article_id, comment_id, comment.user_id,
+                                  to_user_id, mode, constant::MESSAGE::STATUS::INIT, now)));
+        }
+
+        try!(trans.commit());
+        Ok(comment_id)
+    }
+
+    fn edit(&self, id: u64, content: &str) -> RepoResult<()> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+
+        try!(trans.prep_exec("INSERT INTO comment_revision(comment_id, content, create_time) \
+                              SELECT id, content, ? from comment where id=?",
+                             (now, id)));
+        try!(trans.prep_exec("UPDATE comment set content=?, update_time=? where id=?",
+                             (content, now, id)));
+
+        try!(trans.commit());
+        Ok(())
+    }
+
+    fn revisions(&self, id: u64) -> RepoResult<Vec<String>> {
+        let result = try!(self.0.prep_exec("SELECT content from comment_revision \
+                                            where comment_id=? order by id", (id,)));
+        let mut revisions = Vec::new();
+        for row in result {
+            revisions.push(my::from_row::<String>(try!(row)));
+        }
+        Ok(revisions)
+    }
+
+    fn delete(&self, id: u64) -> RepoResult<()> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+
+        let raw_row = try!(trans.prep_exec("SELECT article_id from comment \
+                                            where id=? and status=? for update",
+                                           (id, constant::COMMENT::STATUS::NORMAL))).next();
+        let article_id: u64 = match raw_row {
+            Some(row) => my::from_row(try!(row)),
+            None => return Ok(()),
+        };
+
+        try!(trans.prep_exec("UPDATE comment set status=?, update_time=? where id=?",
+                             (constant::COMMENT::STATUS::DELETED, now, id)));
+        try!(trans.prep_exec("UPDATE article set comments_count=comments_count-1 where id=?",
+                             (article_id,)));
+        try!(trans.prep_exec("DELETE FROM message where comment_id=?", (id,)));
+
+        try!(trans.commit());
+        Ok(())
+    }
+}
+
+impl MyMessageRepo {
+    // (recipient id, message) of `message as m` where `condition`, which may
+    // also order and limit them
+    fn select(&self, condition: &str, params: Vec<my::Value>) -> RepoResult<Vec<(u64, Message)>> {
+        let result = try!(self.0.prep_exec(
+            format!("SELECT m.id, m.to_user_id, m.mode, m.status, m.create_time, \
+                     c.id as comment_id, c.content, u.id as user_id, u.username, u.email, \
+                     a.id as article_id, a.title as article_title \
+                     from message as m join user as u on m.from_user_id=u.id \
+                     join article as a on a.id=m.article_id \
+                     join comment as c on c.id=m.comment_id \
+                     where {}", condition),
+            params));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (id, to_user_id, mode, status, create_time, comment_id, content,
+                 user_id, username, email, article_id, article_title) = my::from_row::<(
+                     u64, u64, i8, i8, NaiveDateTime, u64, String,
+                     u64, String, String, u64, String)>(row);
+
+            let mut article = Article::default();
+            article.id = article_id;
+            article.title = article_title;
+
+            (to_user_id, Message {
+                id: id,
+                mode: mode,
+                status: status,
+                create_time: create_time,
+                from_user: user(user_id, username, email, *constant::DEFAULT_DATETIME),
+                article: article,
+                comment: Comment {
+                    id: comment_id,
+                    content: content,
+                    ..Default::default()
+                },
+            })
+        }).collect())
+    }
+}
+
+impl MessageRepo for MyMessageRepo {
+    fn count(&self, filter: &MessageFilter) -> RepoResult<usize> {
+        let (condition, params) = message_condition(filter);
+        Ok(try!(db::count(&self.0,
+                          format!("SELECT count(m.id) from message as m where {}", condition),
+                          params)))
+    }
+
+    fn list(&self, filter: &MessageFilter, page: Option<usize>) -> RepoResult<Vec<Message>> {
+        let (condition, mut params) = message_condition(filter);
+        let limit = match page {
+            Some(page) => {
+                params.push(((page - 1) * constant::PAGE_SIZE).into());
+                params.push(constant::PAGE_SIZE.into());
+                " limit ?,?"
+            },
+            None => "",
+        };
+        let messages = try!(self.select(&format!("{} order by m.id desc{}", condition, limit),
+                                        params));
+        Ok(messages.into_iter().map(|(_, message)| message).collect())
+    }
+
+    fn count_unread(&self, to_user_id: u64) -> RepoResult<usize> {
+        Ok(try!(db::count(&self.0, "SELECT count(id) from message where to_user_id=? and status=?",
+                          (to_user_id, constant::MESSAGE::STATUS::INIT))))
+    }
+
+    fn mark_read(&self, to_user_id: u64, last_id: Option<u64>) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE message set status=? where to_user_id=? and status=? \
+                               and id<=?",
+                              (constant::MESSAGE::STATUS::READ,
+                               to_user_id,
+                               constant::MESSAGE::STATUS::INIT,
+                               last_id.unwrap_or(u64::max_value()))));
+        Ok(())
+    }
+
+    fn set_status(&self, to_user_id: u64, id: u64, status: i8) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE message set status=? where id=? and to_user_id=?",
+                              (status, id, to_user_id)));
+        Ok(())
+    }
+
+    fn delete(&self, to_user_id: u64, id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM message where id=? and to_user_id=?",
+                              (id, to_user_id)));
+        Ok(())
+    }
+
+    fn list_unmailed(&self, notify: i8,
+                     before: NaiveDateTime) -> RepoResult<Vec<(u64, Message)>> {
+        self.select("m.mail_time is null and m.create_time<? and \
+                     m.to_user_id in (SELECT id from user where notify=?) \
+                     order by m.to_user_id, m.id",
+                    vec![before.into(), notify.into()])
+    }
+
+    fn set_mailed(&self, ids: &[u64], now: NaiveDateTime) -> RepoResult<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let placeholders = vec!["?"; ids.len()].join(",");
+        let mut params: Vec<my::Value> = vec![now.into()];
+        params.extend(ids.iter().map(|&id| id.into()));
+        try!(self.0.prep_exec(format!("UPDATE message set mail_time=? where id in ({})",
+                                      placeholders),
+                              params));
+        Ok(())
+    }
+}
+
+impl MyUserRepo {
+    // `condition` has a single `?` for `value`
+    fn find_by(&self, condition: &str, value: my::Value) -> RepoResult<Option<User>> {
+        match try!(self.0.prep_exec(format!("SELECT {} from user where {}",
+                                            USER_COLUMNS, condition),
+                                    (value,))).next() {
+            Some(row) => Ok(Some(user_from_row(try!(row)))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl UserRepo for MyUserRepo {
+    fn find(&self, id: u64) -> RepoResult<Option<User>> {
+        self.find_by("id=?", id.into())
+    }
+
+    fn find_by_username(&self, username: &str) -> RepoResult<Option<User>> {
+        self.find_by("username=?", username.to_owned().into())
+    }
+
+    fn find_by_email(&self, email: &str) -> RepoResult<Option<User>> {
+        self.find_by("email=?", email.to_owned().into())
+    }
+
+    fn all(&self) -> RepoResult<Vec<User>> {
+        let result = try!(self.0.prep_exec(format!("SELECT {} from user order by id",
+                                                   USER_COLUMNS), ()));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+        Ok(rows.into_iter().map(user_from_row).collect())
+    }
+
+    fn count(&self) -> RepoResult<usize> {
+        Ok(try!(db::count(&self.0, "SELECT count(id) from user", ())))
+    }
+
+    fn rank(&self, id: u64) -> RepoResult<usize> {
+        Ok(try!(db::count(&self.0, "SELECT count(id) from user where id < ?", (id,))) + 1)
+    }
+
+    fn create(&self, username: &str, email: &str, password_hash: &str,
+              notify: i8) -> RepoResult<Option<u64>> {
+        let result = self.0.prep_exec("INSERT INTO user(username, email, password, salt, \
+                                       notify, create_time) VALUES (?, ?, ?, ?, ?, ?)",
+                                      (username, email, password_hash, "", notify,
+                                       Local::now().naive_local()));
+        match result {
+            Ok(result) => Ok(Some(result.last_insert_id())),
+            Err(my::error::Error::MySqlError(ref e)) if e.code == DUPLICATE_ENTRY => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn password(&self, id: u64) -> RepoResult<Option<(String, String)>> {
+        match try!(self.0.prep_exec("SELECT password, salt from user where id=?",
+                                    (id,))).next() {
+            Some(row) => Ok(Some(my::from_row(try!(row)))),
+            None => Ok(None),
+        }
+    }
+
+    fn set_password(&self, id: u64, password_hash: &str) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE user set password=?, salt=? where id=?",
+                              (password_hash, "", id)));
+        Ok(())
+    }
+
+    fn set_status(&self, id: u64, status: i8) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE user set status=? where id=?", (status, id)));
+        Ok(())
+    }
+
+    fn set_role(&self, id: u64, role: i8) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE user set role=? where id=?", (role, id)));
+        Ok(())
+    }
+
+    fn set_notify(&self, id: u64, notify: i8) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE user set notify=? where id=?", (notify, id)));
+        Ok(())
+    }
+
+    fn save_github_user(&self, github_id: u64, username: &str, email: &str,
+                        avatar_url: &str) -> RepoResult<Option<u64>> {
+        let now = Local::now().naive_local();
+        try!(self.0.prep_exec("INSERT INTO github_user(id, username, email, avatar_url, \
+                               create_time, update_time) VALUES (?, ?, ?, ?, ?, ?) \
+                               ON DUPLICATE KEY UPDATE \
+                               username=VALUES(username), \
+                               email=VALUES(email), \
+                               avatar_url=VALUES(avatar_url), \
+                               update_time=VALUES(update_time)",
+                              (github_id, username, email, avatar_url, now, now)));
+
+        // the row was written just above
+        let row = try!(self.0.prep_exec("SELECT user_id from github_user where id=?",
+                                        (github_id,))).next().unwrap();
+        Ok(my::from_row(try!(row)))
+    }
+
+    fn bind_github_user(&self, github_id: u64, user_id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE github_user set user_id=?, bind_time=? where id=?",
+                              (user_id, Local::now().naive_local(), github_id)));
+        Ok(())
+    }
+
+    fn create_from_github(&self, github_id: u64, username: &str,
+                          email: &str) -> RepoResult<Option<u64>> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+
+        let user_id = match trans.prep_exec("INSERT INTO user(username, email, \
+                                             password, salt, create_time) \
+                                             VALUES (?, ?, ?, ?, ?)",
+                                            (username, email, "", "", now)) {
+            Ok(result) => result.last_insert_id(),
+            Err(my::error::Error::MySqlError(ref e)) if e.code == DUPLICATE_ENTRY => {
+                return Ok(None);
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        try!(trans.prep_exec("UPDATE github_user set user_id=?, bind_time=? where id=?",
+                             (user_id, now, github_id)));
+
+        try!(trans.commit());
+        Ok(Some(user_id))
+    }
+
+    fn create_password_reset(&self, user_id: u64, token_hash: &str,
+                             expire_time: NaiveDateTime) -> RepoResult<()> {
+        try!(self.0.prep_exec("INSERT INTO password_reset(user_id, token_hash, \
+                               create_time, expire_time) VALUES (?, ?, ?, ?)",
+                              (user_id, token_hash, Local::now().naive_local(), expire_time)));
+        Ok(())
+    }
+
+    fn find_password_reset(&self, token_hash: &str,
+                           now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>> {
+        match try!(self.0.prep_exec("SELECT id, user_id from password_reset where token_hash=? \
+                                     and used_time is null and expire_time>?",
+                                    (token_hash, now))).next() {
+            Some(row) => Ok(Some(my::from_row(try!(row)))),
+            None => Ok(None),
+        }
+    }
+
+    fn reset_password(&self, reset_id: u64, user_id: u64,
+                      password_hash: &str) -> RepoResult<bool> {
+        let now = Local::now().naive_local();
+        let mut trans = try!(self.0.start_transaction(false, None, None));
+
+        if try!(trans.prep_exec("UPDATE password_reset set used_time=? \
+                                 where id=? and used_time is null",
+                                (now, reset_id))).affected_rows() == 0 {
+            return Ok(false);
+        }
+
+        try!(trans.prep_exec("UPDATE password_reset set used_time=? \
+                              where user_id=? and used_time is null",
+                             (now, user_id)));
+
+        try!(trans.prep_exec("UPDATE user set password=?, salt=? where id=?",
+                             (password_hash, "", user_id)));
+        try!(trans.prep_exec("DELETE FROM session where user_id=?", (user_id,)));
+        try!(trans.prep_exec("DELETE FROM access_token where user_id=?", (user_id,)));
+
+        try!(trans.commit());
+        Ok(true)
+    }
+}
+
+impl TagRepo for MyTagRepo {
+    fn exists(&self, name: &str) -> RepoResult<bool> {
+        Ok(try!(self.0.prep_exec("SELECT id from tag where name=?", (name,))).next().is_some())
+    }
+
+    fn complete(&self, prefix: &str) -> RepoResult<Vec<String>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT t.name from tag as t join article_tag as at on at.tag_id=t.id \
+             where t.name like ? group by t.id order by count(at.article_id) desc limit 10",
+            (escape_like(prefix) + "%",)));
+        Ok(try!(result.map(|x| x.map(my::from_row)).collect::<Result<Vec<String>, _>>()))
+    }
+}
+
+impl SessionRepo for MySessionRepo {
+    fn create(&self, user_id: u64, token_hash: &str, ip: &str, user_agent: &str,
+              now: NaiveDateTime, expire_time: NaiveDateTime) -> RepoResult<()> {
+        try!(self.0.prep_exec("INSERT INTO session(user_id, token_hash, last_seen_ip, \
+                               user_agent, create_time, last_seen_time, expire_time) \
+                               VALUES (?, ?, ?, ?, ?, ?, ?)",
+                              (user_id, token_hash, ip, user_agent, now, now, expire_time)));
+        Ok(())
+    }
+
+    fn find(&self, token_hash: &str, now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>> {
+        match try!(self.0.prep_exec("SELECT id, user_id from session where token_hash=? \
+                                     and expire_time>?",
+                                    (token_hash, now))).next() {
+            Some(row) => Ok(Some(my::from_row(try!(row)))),
+            None => Ok(None),
+        }
+    }
+
+    fn touch(&self, id: u64, ip: &str, user_agent: &str, now: NaiveDateTime,
+             before: NaiveDateTime) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE session set last_seen_ip=?, user_agent=?, \
+                               last_seen_time=? where id=? and last_seen_time<?",
+                              (ip, user_agent, now, id, before)));
+        Ok(())
+    }
+
+    fn list(&self, user_id: u64, now: NaiveDateTime) -> RepoResult<Vec<Session>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT id, last_seen_ip, user_agent, create_time, last_seen_time \
+             from session where user_id=? and expire_time>? \
+             order by last_seen_time desc",
+            (user_id, now)));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (id, last_seen_ip, user_agent, create_time,
+                 last_seen_time) = my::from_row(row);
+
+            Session {
+                id: id,
+                last_seen_ip: last_seen_ip,
+                user_agent: user_agent,
+                create_time: create_time,
+                last_seen_time: last_seen_time,
+                is_current: false,
+            }
+        }).collect())
+    }
+
+    fn delete_by_token(&self, token_hash: &str) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM session where token_hash=?", (token_hash,)));
+        Ok(())
+    }
+
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM session where user_id=? and id=?", (user_id, id)));
+        Ok(())
+    }
+
+    fn delete_all(&self, user_id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM session where user_id=?", (user_id,)));
+        Ok(())
+    }
+
+    fn delete_others(&self, user_id: u64, keep_id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM session where user_id=? and id<>?",
+                              (user_id, keep_id)));
+        Ok(())
+    }
+}
+
+impl TokenRepo for MyTokenRepo {
+    fn create(&self, user_id: u64, name: &str, token_hash: &str, scopes: u8,
+              now: NaiveDateTime) -> RepoResult<()> {
+        try!(self.0.prep_exec("INSERT INTO access_token(user_id, name, token_hash, scopes, \
+                               create_time) VALUES (?, ?, ?, ?, ?)",
+                              (user_id, name, token_hash, scopes, now)));
+        Ok(())
+    }
+
+    fn find(&self, token_hash: &str) -> RepoResult<Option<(u64, u8, u64)>> {
+        match try!(self.0.prep_exec("SELECT id, scopes, user_id from access_token \
+                                     where token_hash=?", (token_hash,))).next() {
+            Some(row) => Ok(Some(my::from_row(try!(row)))),
+            None => Ok(None),
+        }
+    }
+
+    fn touch(&self, id: u64, now: NaiveDateTime, before: NaiveDateTime) -> RepoResult<()> {
+        try!(self.0.prep_exec("UPDATE access_token set last_used_time=? where id=? \
+                               and (last_used_time is null or last_used_time<?)",
+                              (now, id, before)));
+        Ok(())
+    }
+
+    fn list(&self, user_id: u64) -> RepoResult<Vec<AccessToken>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT id, name, scopes, create_time, last_used_time \
+             from access_token where user_id=? order by id desc",
+            (user_id,)));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (id, name, scopes, create_time, last_used_time) = my::from_row(row);
+
+            AccessToken {
+                id: id,
+                name: name,
+                scopes: scopes,
+                create_time: create_time,
+                last_used_time: last_used_time,
+            }
+        }).collect())
+    }
+
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM access_token where user_id=? and id=?",
+                              (user_id, id)));
+        Ok(())
+    }
+
+    fn delete_all(&self, user_id: u64) -> RepoResult<()> {
+        try!(self.0.prep_exec("DELETE FROM access_token where user_id=?", (user_id,)));
+        Ok(())
+    }
+}
+
+impl NewsRepo for MyNewsRepo {
+    fn save(&self, items: &[NewsItem]) -> RepoResult<()> {
+        let now = Local::now().naive_local();
+        for item in items {
+            try!(self.0.prep_exec("INSERT INTO news(source, title, url, url_hash, score, \
+                                   author, comments_count, comments_url, publish_time, \
+                                   fetch_time) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
+                                   ON DUPLICATE KEY UPDATE \
+                                   score=IF(source=VALUES(source), VALUES(score), score), \
+                                   comments_count=IF(source=VALUES(source), \
+                                   VALUES(comments_count), comments_count), \
+                                   fetch_time=VALUES(fetch_time)",
+                                  (&item.source, &item.title, &item.url,
+                                   util::sha256_hex(&news::normalize_url(&item.url)),
+                                   item.score, &item.author, item.comments_count,
+                                   &item.comments_url, item.publish_time, now)));
+        }
+        Ok(())
+    }
+
+    fn count(&self) -> RepoResult<usize> {
+        Ok(try!(db::count(&self.0, "SELECT count(id) from news", ())))
+    }
+
+    fn list(&self, page: usize) -> RepoResult<Vec<NewsItem>> {
+        let result = try!(self.0.prep_exec(
+            "SELECT source, title, url, author, score, comments_count, \
+             comments_url, publish_time from news \
+             order by publish_time desc, id desc limit ?,?",
+            ((page - 1) * constant::PAGE_SIZE, constant::PAGE_SIZE)));
+        let rows = try!(result.collect::<Result<Vec<_>, _>>());
+
+        Ok(rows.into_iter().map(|row| {
+            let (source, title, url, author, score, comments_count,
+                 comments_url, publish_time) = my::from_row(row);
+            NewsItem {
+                source: source,
+                title: title,
+                url: url,
+                author: author,
+                score: score,
+                comments_count: comments_count,
+                comments_url: comments_url,
+                publish_time: publish_time,
+            }
+        }).collect())
+    }
+}
+
+// a row of ARTICLE_COLUMNS
+fn article_from_row(row: my::Row) -> Article {
+    let (id, category, title, content, comments_count, create_time, update_time,
+         flag, priority, status, user_id, username, email) = my::from_row::<
+            (_,_,_,_,_,_,_,_,_,_,_,_,String)>(row);
+
+    Article {
+        id: id,
+        category: Category::from_value(category),
+        title: title,
+        content: content,
+        user: user(user_id, username, email, *constant::DEFAULT_DATETIME),
+        comments_count: comments_count,
+        create_time: create_time,
+        update_time: update_time,
+        comments: Vec::new(),
+        flag: flag,
+        priority: priority,
+        status: status,
+        tags: Vec::new(),
+    }
+}
+
+// the `where` of `message as m` for a filter
+fn message_condition(filter: &MessageFilter) -> (String, Vec<my::Value>) {
+    let mut condition = "m.to_user_id=?".to_owned();
+    let mut params: Vec<my::Value> = vec![filter.to_user_id.into()];
+    if let Some(mode) = filter.mode {
+        condition.push_str(" and m.mode=?");
+        params.push(mode.into());
+    }
+    if filter.unread_only {
+        condition.push_str(" and m.status=?");
+        params.push(constant::MESSAGE::STATUS::INIT.into());
+    }
+    (condition, params)
+}
+
+// a row of USER_COLUMNS
+fn user_from_row(row: my::Row) -> User {
+    let (id, username, email, role, status, notify, create_time) = my::from_row::<
+            (u64, String, String, i8, i8, i8, NaiveDateTime)>(row);
+
+    User {
+        role: role,
+        status: status,
+        notify: notify,
+        ..user(id, username, email, create_time)
+    }
+}
+
+fn user(id: u64, username: String, email: String, create_time: NaiveDateTime) -> User {
+    User {
+        id: id,
+        avatar: gen_gravatar_url(&email),
+        username: username,
+        email: email,
+        create_time: create_time,
+        ..Default::default()
+    }
+}
+
+// a comment whose author, article and parent only have their id set
+fn comment_stub(id: u64, article_id: u64, parent_id: Option<u64>, user_id: u64,
+                content: String, status: i8, create_time: NaiveDateTime,
+                update_time: Option<NaiveDateTime>) -> Comment {
+    let mut article = Article::default();
+    article.id = article_id;
+
+    Comment {
+        id: id,
+        user: User { id: user_id, ..Default::default() },
+        content: content,
+        status: status,
+        create_time: create_time,
+        update_time: update_time,
+        article: Some(article),
+        parent: parent_id.map(|parent_id| Box::new(Comment {
+            id: parent_id,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+// locks the article until the transaction ends, so that nobody else
+// changes it between the check and the write
+fn check_article(trans: &mut my::Transaction, id: u64, author_id: Option<u64>,
+                 status: i8) -> RepoResult<Change> {
+    let row = try!(trans.prep_exec("SELECT user_id, status from article where id=? for update",
+                                   (id,))).next();
+    let (user_id, current_status): (u64, i8) = match row {
+        Some(row) => my::from_row(try!(row)),
+        None => return Ok(Change::NotFound),
+    };
+    if current_status != status {
+        return Ok(Change::NotFound);
+    }
+    match author_id {
+        Some(author_id) if author_id != user_id => Ok(Change::Forbidden),
+        _ => Ok(Change::Done),
+    }
+}
+
+fn save_tags(trans: &mut my::Transaction, article_id: u64,
+             tags: &[String]) -> RepoResult<()> {
+    let now = Local::now().naive_local();
+
+    try!(trans.prep_exec("DELETE FROM article_tag where article_id=?", (article_id,)));
+
+    for tag in tags {
+        try!(trans.prep_exec("INSERT IGNORE INTO tag(name, create_time) VALUES (?, ?)",
+                             (tag, now)));
+        // the collation may give two of the names the same tag
+        try!(trans.prep_exec("INSERT IGNORE INTO article_tag(article_id, tag_id) \
+                              SELECT ?, id from tag where name=?",
+                             (article_id, tag)));
+    }
+    Ok(())
+}
+
+// tag names of each article, keyed by article id
+fn get_tags(pool: &my::Pool, article_ids: &[u64]) -> RepoResult<HashMap<u64, Vec<String>>> {
+    let mut tags: HashMap<u64, Vec<String>> = HashMap::new();
+
+    if article_ids.is_empty() {
+        return Ok(tags);
+    }
+
+    let placeholders = vec!["?"; article_ids.len()].join(",");
+    let result = try!(pool.prep_exec(
+        format!("SELECT at.article_id, t.name from article_tag as at \
+                 join tag as t on at.tag_id=t.id where at.article_id in ({}) \
+                 order by t.name", placeholders),
+        article_ids.to_vec()));
+
+    for row in result {
+        let (article_id, name) = my::from_row::<(u64, String)>(try!(row));
+        tags.entry(article_id).or_insert_with(Vec::new).push(name);
+    }
+
+    Ok(tags)
+}
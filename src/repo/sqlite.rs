@@ -0,0 +1,1095 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use chrono::*;
+use rusqlite::{self, Connection, Row};
+use rusqlite::types::ToSql;
+use base::constant;
+use base::db::DbError;
+use base::model::{AccessToken, Article, ArticleForm, Category, Comment, Message, Session, User};
+use base::news::{self, NewsItem};
+use base::util::{self, gen_gravatar_url};
+use repo::{ArticleFilter, Change, MessageFilter, Moderation, NewComment, ArticleRepo,
+           CommentRepo, MessageRepo, UserRepo, TagRepo, SessionRepo, TokenRepo, NewsRepo,
+           RepoResult, escape_like};
+
+pub struct LiteArticleRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteCommentRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteMessageRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteUserRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteTagRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteSessionRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteTokenRepo(pub Arc<Mutex<Connection>>);
+pub struct LiteNewsRepo(pub Arc<Mutex<Connection>>);
+
+// times are stored as TEXT in this format, which sorts like the time itself
+const TIME_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+
+// articles are `a` and their authors `u`, see `article_from_row`
+const ARTICLE_COLUMNS: &'static str =
+    "a.id, a.category, a.title, a.content, a.comments_count, a.create_time, \
+     a.update_time, a.flag, a.priority, a.status, u.id as user_id, u.username, u.email";
+
+// see `user_from_row`
+const USER_COLUMNS: &'static str = "id, username, email, role, status, notify, create_time";
+
+// how a filter narrows and orders `article as a`
+struct FilterSql {
+    join: &'static str,
+    condition: String,
+    params: Vec<Box<ToSql>>,
+    order: String,
+    order_params: Vec<Box<ToSql>>,
+}
+
+impl FilterSql {
+    fn new(filter: &ArticleFilter) -> FilterSql {
+        let mut sql = FilterSql {
+            join: "",
+            condition: "1=1".to_owned(),
+            params: Vec::new(),
+            order: "a.flag & 1 desc, a.priority desc, a.update_time desc".to_owned(),
+            order_params: Vec::new(),
+        };
+
+        match *filter {
+            ArticleFilter::All => {},
+            ArticleFilter::Category(category) => {
+                sql.condition = "a.category=?".to_owned();
+                sql.params.push(Box::new(category as i64));
+                sql.order = "a.flag & 1 desc, a.priority desc, a.create_time desc".to_owned();
+            },
+            ArticleFilter::Tag(ref name) => {
+                sql.join = "join article_tag as at on at.article_id=a.id \
+                            join tag as t on t.id=at.tag_id";
+                sql.condition = "t.name=?".to_owned();
+                sql.params.push(Box::new(name.clone()));
+                sql.order = "a.update_time desc".to_owned();
+            },
+            ArticleFilter::User(user_id) => {
+                sql.condition = "a.user_id=?".to_owned();
+                sql.params.push(Box::new(user_id as i64));
+                sql.order = "a.create_time desc".to_owned();
+            },
+            ArticleFilter::Search { ref query, comments } => {
+                // sqlite has no full text index here, so this is a plain
+                // substring match, title matches first
+                let pattern = format!("%{}%", escape_like(query));
+                sql.condition = "a.title like ? escape '\\' \
+                                 or a.content like ? escape '\\'".to_owned();
+                sql.params.push(Box::new(pattern.clone()));
+                sql.params.push(Box::new(pattern.clone()));
+
+                if comments {
+                    sql.condition.push_str(" or a.id in (SELECT c.article_id from comment as c \
+                                            where c.status=? and c.content like ? escape '\\')");
+                    sql.params.push(Box::new(constant::COMMENT::STATUS::NORMAL as i64));
+                    sql.params.push(Box::new(pattern.clone()));
+                }
+                sql.order = "a.title like ? escape '\\' desc, a.update_time desc".to_owned();
+                sql.order_params.push(Box::new(pattern));
+            },
+        }
+
+        sql
+    }
+
+    // the query of the filter's normal articles, `limit` being sql that
+    // takes `limit_params`
+    fn select(self, limit: &str, limit_params: Vec<Box<ToSql>>) -> (String, Vec<Box<ToSql>>) {
+        let mut params: Vec<Box<ToSql>> =
+            vec![Box::new(constant::ARTICLE::STATUS::NORMAL as i64)];
+        params.extend(self.params);
+        params.extend(self.order_params);
+        params.extend(limit_params);
+
+        (format!("SELECT {} from article as a join user as u on a.user_id=u.id {} \
+                  where a.status=? and ({}) order by {}{}",
+                 ARTICLE_COLUMNS, self.join, self.condition, self.order, limit),
+         params)
+    }
+}
+
+impl LiteArticleRepo {
+    // the articles a query of ARTICLE_COLUMNS finds, with their tags
+    fn select(&self, query: &str, params: Vec<Box<ToSql>>) -> RepoResult<Vec<Article>> {
+        let conn = lock(&self.0);
+        let mut articles = try!(select(&conn, query, &refs(&params), article_from_row));
+
+        let article_ids: Vec<u64> = articles.iter().map(|a| a.id).collect();
+        let mut tags = try!(get_tags(&conn, &article_ids));
+        for article in &mut articles {
+            article.tags = tags.remove(&article.id).unwrap_or(Vec::new());
+        }
+        Ok(articles)
+    }
+}
+
+impl ArticleRepo for LiteArticleRepo {
+    fn count(&self, filter: &ArticleFilter) -> RepoResult<usize> {
+        let sql = FilterSql::new(filter);
+        let mut params: Vec<Box<ToSql>> =
+            vec![Box::new(constant::ARTICLE::STATUS::NORMAL as i64)];
+        params.extend(sql.params);
+        count(&lock(&self.0),
+              &format!("SELECT count(a.id) from article as a {} \
+                        where a.status=? and ({})", sql.join, sql.condition),
+              &refs(&params))
+    }
+
+    fn list(&self, filter: &ArticleFilter, page: Option<usize>) -> RepoResult<Vec<Article>> {
+        let sql = FilterSql::new(filter);
+        let (query, params) = match page {
+            Some(page) => sql.select(" limit ?,?",
+                                     vec![Box::new(((page - 1) * constant::PAGE_SIZE) as i64),
+                                          Box::new(constant::PAGE_SIZE as i64)]),
+            None => sql.select("", Vec::new()),
+        };
+        self.select(&query, params)
+    }
+
+    fn newest(&self, filter: &ArticleFilter, limit: usize) -> RepoResult<Vec<Article>> {
+        let mut sql = FilterSql::new(filter);
+        sql.order = "a.create_time desc".to_owned();
+        sql.order_params.clear();
+        let (query, params) = sql.select(" limit ?", vec![Box::new(limit as i64)]);
+        self.select(&query, params)
+    }
+
+    fn find(&self, id: u64) -> RepoResult<Option<Article>> {
+        let articles = try!(self.select(
+            &format!("SELECT {} from article as a join user as u on a.user_id=u.id \
+                      where a.id=?", ARTICLE_COLUMNS),
+            vec![Box::new(id as i64)]));
+        Ok(articles.into_iter().next())
+    }
+
+    fn all(&self) -> RepoResult<Vec<Article>> {
+        self.select(&format!("SELECT {} from article as a join user as u on a.user_id=u.id \
+                              order by a.id", ARTICLE_COLUMNS),
+                    Vec::new())
+    }
+
+    fn create(&self, user_id: u64, form: &ArticleForm) -> RepoResult<u64> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+        try!(trans.execute("INSERT INTO article(category, title, content, \
+                            user_id, create_time, update_time) \
+                            VALUES (?, ?, ?, ?, ?, ?)",
+                           &[&form.category, &form.title, &form.content,
+                             &(user_id as i64), &now, &now]));
+        let article_id = trans.last_insert_rowid() as u64;
+        if let Some(ref tags) = form.tags {
+            try!(save_tags(&trans, article_id, tags));
+        }
+        try!(trans.commit());
+        Ok(article_id)
+    }
+
+    fn update(&self, id: u64, author_id: u64, form: &ArticleForm) -> RepoResult<Change> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+        let change = try!(check_article(&trans, id, Some(author_id),
+                                        constant::ARTICLE::STATUS::NORMAL));
+        if change != Change::Done {
+            return Ok(change);
+        }
+        try!(trans.execute("UPDATE article set category=?, title=?, content=?, \
+                            update_time=? where id=?",
+                           &[&form.category, &form.title, &form.content, &now, &(id as i64)]));
+        if let Some(ref tags) = form.tags {
+            try!(save_tags(&trans, id, tags));
+        }
+        try!(trans.commit());
+        Ok(Change::Done)
+    }
+
+    fn set_status(&self, id: u64, author_id: Option<u64>, from_status: i8,
+                  to_status: i8) -> RepoResult<Change> {
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+        let change = try!(check_article(&trans, id, author_id, from_status));
+        if change != Change::Done {
+            return Ok(change);
+        }
+        try!(trans.execute("UPDATE article set status=? where id=?",
+                           &[&(to_status as i64), &(id as i64)]));
+        try!(trans.commit());
+        Ok(Change::Done)
+    }
+
+    fn moderate(&self, id: u64, moderation: &Moderation) -> RepoResult<bool> {
+        let (assignment, value) = match *moderation {
+            Moderation::Flag(flag, true) => ("flag=flag | ?", flag as i64),
+            Moderation::Flag(flag, false) => ("flag=flag & ~?", flag as i64),
+            Moderation::Priority(priority) => ("priority=?", priority as i64),
+            Moderation::Category(category) => ("category=?", category as i64),
+        };
+        // unlike mysql, sqlite counts a row set to what it already was
+        let changed = try!(lock(&self.0).execute(
+            &format!("UPDATE article set {} where id=? and status=?", assignment),
+            &[&value, &(id as i64), &(constant::ARTICLE::STATUS::NORMAL as i64)]));
+        Ok(changed > 0)
+    }
+}
+
+impl CommentRepo for LiteCommentRepo {
+    fn find(&self, id: u64) -> RepoResult<Option<Comment>> {
+        let comments = try!(select(
+            &lock(&self.0),
+            "SELECT id, article_id, parent_id, user_id, content, status, \
+             create_time, update_time from comment where id=?",
+            &[&(id as i64)], comment_stub_from_row));
+        Ok(comments.into_iter().next())
+    }
+
+    fn list_by_article(&self, article_id: u64) -> RepoResult<Vec<Comment>> {
+        select(&lock(&self.0),
+               "SELECT c.id, c.parent_id, c.content, c.status, c.create_time, \
+                c.update_time, u.id as user_id, u.username, u.email from comment \
+                as c join user as u on c.user_id=u.id where c.article_id=? \
+                order by c.create_time, c.id",
+               &[&(article_id as i64)],
+               |row| {
+                   let parent_id: Option<i64> = try!(row.get_checked(1));
+                   Ok(Comment {
+                       id: try!(get_u64(row, 0)),
+                       user: user(try!(get_u64(row, 6)), try!(row.get_checked(7)),
+                                  try!(row.get_checked(8)), *constant::DEFAULT_DATETIME),
+                       content: try!(row.get_checked(2)),
+                       status: try!(get_i8(row, 3)),
+                       create_time: try!(get_time(row, 4)),
+                       update_time: try!(get_optional_time(row, 5)),
+                       parent: parent_id.map(|parent_id| Box::new(Comment {
+                           id: parent_id as u64,
+                           ..Default::default()
+                       })),
+                       ..Default::default()
+                   })
+               })
+    }
+
+    fn list_by_user(&self, user_id: u64) -> RepoResult<Vec<Comment>> {
+        select(&lock(&self.0),
+               "SELECT c.id, c.content, c.create_time, \
+                a.id as article_id, a.title as article_title from comment as c \
+                join article as a on c.article_id=a.id where c.user_id=? \
+                and a.status=? and c.status=? order by c.create_time desc, c.id desc",
+               &[&(user_id as i64), &(constant::ARTICLE::STATUS::NORMAL as i64),
+                 &(constant::COMMENT::STATUS::NORMAL as i64)],
+               |row| {
+                   let mut article = Article::default();
+                   article.id = try!(get_u64(row, 3));
+                   article.title = try!(row.get_checked(4));
+
+                   Ok(Comment {
+                       id: try!(get_u64(row, 0)),
+                       content: try!(row.get_checked(1)),
+                       create_time: try!(get_time(row, 2)),
+                       article: Some(article),
+                       ..Default::default()
+                   })
+               })
+    }
+
+    fn count_by_user(&self, user_id: u64) -> RepoResult<usize> {
+        count(&lock(&self.0),
+              "SELECT count(c.id) from comment as c \
+               join article as a on c.article_id=a.id \
+               where c.user_id=? and a.status=? and c.status=?",
+              &[&(user_id as i64), &(constant::ARTICLE::STATUS::NORMAL as i64),
+                &(constant::COMMENT::STATUS::NORMAL as i64)])
+    }
+
+    fn all(&self) -> RepoResult<Vec<Comment>> {
+        select(&lock(&self.0),
+               "SELECT id, article_id, parent_id, user_id, content, status, \
+                create_time, update_time from comment order by id",
+               &[], comment_stub_from_row)
+    }
+
+    fn create(&self, comment: &NewComment) -> RepoResult<u64> {
+        let now = format_time(&Local::now().naive_local());
+        let article_id = comment.article_id as i64;
+        let user_id = comment.user_id as i64;
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+
+        try!(trans.execute("INSERT INTO comment(article_id, user_id, parent_id, content, \
+                            create_time) VALUES (?, ?, ?, ?, ?)",
+                           &[&article_id, &user_id, &comment.parent_id.map(|id| id as i64),
+                             &comment.content, &now]));
+        let comment_id = trans.last_insert_rowid();
+
+        try!(trans.execute("UPDATE article set comments_count=comments_count+1, \
+                            update_time=? where id=?",
+                           &[&now, &article_id]));
+
+        for &(to_user_id, mode) in &comment.notify {
+            try!(trans.execute("INSERT INTO message(article_id, comment_id, \
+                                from_user_id, to_user_id, mode, \
+                                status, create_time) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                               &[&article_id, &comment_id, &user_id, &(to_user_id as i64),
+                                 &(mode as i64), &(constant::MESSAGE::STATUS::INIT as i64),
+                                 &now]));
+        }
+
+        try!(trans.commit());
+        Ok(comment_id as u64)
+    }
+
+    fn edit(&self, id: u64, content: &str) -> RepoResult<()> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+
+        try!(trans.execute("INSERT INTO comment_revision(comment_id, content, create_time) \
+                            SELECT id, content, ? from comment where id=?",
+                           &[&now, &(id as i64)]));
+        try!(trans.execute("UPDATE comment set content=?, update_time=? where id=?",
+                           &[&content, &now, &(id as i64)]));
+
+        try!(trans.commit());
+        Ok(())
+    }
+
+    fn revisions(&self, id: u64) -> RepoResult<Vec<String>> {
+        select(&lock(&self.0),
+               "SELECT content from comment_revision where comment_id=? order by id",
+               &[&(id as i64)], |row| Ok(try!(row.get_checked(0))))
+    }
+
+    fn delete(&self, id: u64) -> RepoResult<()> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+
+        let article_ids = try!(select(&trans, "SELECT article_id from comment \
+                                               where id=? and status=?",
+                                      &[&(id as i64), &(constant::COMMENT::STATUS::NORMAL as i64)],
+                                      |row| get_u64(row, 0)));
+        let article_id = match article_ids.first() {
+            Some(&article_id) => article_id as i64,
+            None => return Ok(()),
+        };
+
+        try!(trans.execute("UPDATE comment set status=?, update_time=? where id=?",
+                           &[&(constant::COMMENT::STATUS::DELETED as i64), &now, &(id as i64)]));
+        try!(trans.execute("UPDATE article set comments_count=comments_count-1 where id=?",
+                           &[&article_id]));
+        try!(trans.execute("DELETE FROM message where comment_id=?", &[&(id as i64)]));
+
+        try!(trans.commit());
+        Ok(())
+    }
+}
+
+impl LiteMessageRepo {
+    // (recipient id, message) of `message as m` where `condition`, which may
+    // also order and limit them
+    fn select(&self, condition: &str, params: &[&ToSql]) -> RepoResult<Vec<(u64, Message)>> {
+        select(&lock(&self.0),
+               &format!("SELECT m.id, m.to_user_id, m.mode, m.status, m.create_time, \
+                         c.id as comment_id, c.content, u.id as user_id, u.username, u.email, \
+                         a.id as article_id, a.title as article_title \
+                         from message as m join user as u on m.from_user_id=u.id \
+                         join article as a on a.id=m.article_id \
+                         join comment as c on c.id=m.comment_id \
+                         where {}", condition),
+               params,
+               |row| {
+                   let mut article = Article::default();
+                   article.id = try!(get_u64(row, 10));
+                   article.title = try!(row.get_checked(11));
+
+                   Ok((try!(get_u64(row, 1)), Message {
+                       id: try!(get_u64(row, 0)),
+                       mode: try!(get_i8(row, 2)),
+                       status: try!(get_i8(row, 3)),
+                       create_time: try!(get_time(row, 4)),
+                       from_user: user(try!(get_u64(row, 7)), try!(row.get_checked(8)),
+                                       try!(row.get_checked(9)), *constant::DEFAULT_DATETIME),
+                       article: article,
+                       comment: Comment {
+                           id: try!(get_u64(row, 5)),
+                           content: try!(row.get_checked(6)),
+                           ..Default::default()
+                       },
+                   }))
+               })
+    }
+}
+
+impl MessageRepo for LiteMessageRepo {
+    fn count(&self, filter: &MessageFilter) -> RepoResult<usize> {
+        let (condition, params) = message_condition(filter);
+        count(&lock(&self.0),
+              &format!("SELECT count(m.id) from message as m where {}", condition),
+              &refs(&params))
+    }
+
+    fn list(&self, filter: &MessageFilter, page: Option<usize>) -> RepoResult<Vec<Message>> {
+        let (condition, mut params) = message_condition(filter);
+        let limit = match page {
+            Some(page) => {
+                params.push(Box::new(((page - 1) * constant::PAGE_SIZE) as i64));
+                params.push(Box::new(constant::PAGE_SIZE as i64));
+                " limit ?,?"
+            },
+            None => "",
+        };
+        let messages = try!(self.select(&format!("{} order by m.id desc{}", condition, limit),
+                                        &refs(&params)));
+        Ok(messages.into_iter().map(|(_, message)| message).collect())
+    }
+
+    fn count_unread(&self, to_user_id: u64) -> RepoResult<usize> {
+        count(&lock(&self.0),
+              "SELECT count(id) from message where to_user_id=? and status=?",
+              &[&(to_user_id as i64), &(constant::MESSAGE::STATUS::INIT as i64)])
+    }
+
+    fn mark_read(&self, to_user_id: u64, last_id: Option<u64>) -> RepoResult<()> {
+        try!(lock(&self.0).execute(
+            "UPDATE message set status=? where to_user_id=? and status=? and id<=?",
+            &[&(constant::MESSAGE::STATUS::READ as i64),
+              &(to_user_id as i64),
+              &(constant::MESSAGE::STATUS::INIT as i64),
+              &last_id.map(|id| id as i64).unwrap_or(i64::max_value())]));
+        Ok(())
+    }
+
+    fn set_status(&self, to_user_id: u64, id: u64, status: i8) -> RepoResult<()> {
+        try!(lock(&self.0).execute(
+            "UPDATE message set status=? where id=? and to_user_id=?",
+            &[&(status as i64), &(id as i64), &(to_user_id as i64)]));
+        Ok(())
+    }
+
+    fn delete(&self, to_user_id: u64, id: u64) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM message where id=? and to_user_id=?",
+                                            &[&(id as i64), &(to_user_id as i64)]));
+        Ok(())
+    }
+
+    fn list_unmailed(&self, notify: i8,
+                     before: NaiveDateTime) -> RepoResult<Vec<(u64, Message)>> {
+        self.select("m.mail_time is null and m.create_time<? and \
+                     m.to_user_id in (SELECT id from user where notify=?) \
+                     order by m.to_user_id, m.id",
+                    &[&format_time(&before), &(notify as i64)])
+    }
+
+    fn set_mailed(&self, ids: &[u64], now: NaiveDateTime) -> RepoResult<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let placeholders = vec!["?"; ids.len()].join(",");
+        let mut params: Vec<Box<ToSql>> = vec![Box::new(format_time(&now))];
+        for &id in ids {
+            params.push(Box::new(id as i64));
+        }
+        try!(lock(&self.0).execute(
+            &format!("UPDATE message set mail_time=? where id in ({})", placeholders),
+            &refs(&params)));
+        Ok(())
+    }
+}
+
+impl LiteUserRepo {
+    // `condition` has a single `?` for `value`
+    fn find_by(&self, condition: &str, value: &ToSql) -> RepoResult<Option<User>> {
+        let users = try!(select(&lock(&self.0),
+                                &format!("SELECT {} from user where {}",
+                                         USER_COLUMNS, condition),
+                                &[value], user_from_row));
+        Ok(users.into_iter().next())
+    }
+}
+
+impl UserRepo for LiteUserRepo {
+    fn find(&self, id: u64) -> RepoResult<Option<User>> {
+        self.find_by("id=?", &(id as i64))
+    }
+
+    fn find_by_username(&self, username: &str) -> RepoResult<Option<User>> {
+        self.find_by("username=?", &username)
+    }
+
+    fn find_by_email(&self, email: &str) -> RepoResult<Option<User>> {
+        self.find_by("email=?", &email)
+    }
+
+    fn all(&self) -> RepoResult<Vec<User>> {
+        select(&lock(&self.0),
+               &format!("SELECT {} from user order by id", USER_COLUMNS),
+               &[], user_from_row)
+    }
+
+    fn count(&self) -> RepoResult<usize> {
+        count(&lock(&self.0), "SELECT count(id) from user", &[])
+    }
+
+    fn rank(&self, id: u64) -> RepoResult<usize> {
+        Ok(try!(count(&lock(&self.0), "SELECT count(id) from user where id < ?",
+                      &[&(id as i64)])) + 1)
+    }
+
+    fn create(&self, username: &str, email: &str, password_hash: &str,
+              notify: i8) -> RepoResult<Option<u64>> {
+        let now = format_time(&Local::now().naive_local());
+        let conn = lock(&self.0);
+        let result = conn.execute("INSERT INTO user(username, email, password, salt, \
+                                   notify, create_time) VALUES (?, ?, ?, ?, ?, ?)",
+                                  &[&username, &email, &password_hash, &"",
+                                    &(notify as i64), &now]);
+        match result {
+            Ok(_) => Ok(Some(conn.last_insert_rowid() as u64)),
+            Err(ref e) if is_duplicate(e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn password(&self, id: u64) -> RepoResult<Option<(String, String)>> {
+        let passwords = try!(select(&lock(&self.0),
+                                    "SELECT password, salt from user where id=?",
+                                    &[&(id as i64)],
+                                    |row| Ok((try!(row.get_checked(0)),
+                                               try!(row.get_checked(1))))));
+        Ok(passwords.into_iter().next())
+    }
+
+    fn set_password(&self, id: u64, password_hash: &str) -> RepoResult<()> {
+        try!(lock(&self.0).execute("UPDATE user set password=?, salt=? where id=?",
+                                            &[&password_hash, &"", &(id as i64)]));
+        Ok(())
+    }
+
+    fn set_status(&self, id: u64, status: i8) -> RepoResult<()> {
+        try!(lock(&self.0).execute("UPDATE user set status=? where id=?",
+                                            &[&(status as i64), &(id as i64)]));
+        Ok(())
+    }
+
+    fn set_role(&self, id: u64, role: i8) -> RepoResult<()> {
+        try!(lock(&self.0).execute("UPDATE user set role=? where id=?",
+                                            &[&(role as i64), &(id as i64)]));
+        Ok(())
+    }
+
+    fn set_notify(&self, id: u64, notify: i8) -> RepoResult<()> {
+        try!(lock(&self.0).execute("UPDATE user set notify=? where id=?",
+                                            &[&(notify as i64), &(id as i64)]));
+        Ok(())
+    }
+
+    fn save_github_user(&self, github_id: u64, username: &str, email: &str,
+                        avatar_url: &str) -> RepoResult<Option<u64>> {
+        let now = format_time(&Local::now().naive_local());
+        let github_id = github_id as i64;
+        let conn = lock(&self.0);
+
+        try!(conn.execute("INSERT OR IGNORE INTO github_user(id, username, email, avatar_url, \
+                           create_time, update_time) VALUES (?, ?, ?, ?, ?, ?)",
+                          &[&github_id, &username, &email, &avatar_url, &now, &now]));
+        try!(conn.execute("UPDATE github_user set username=?, email=?, avatar_url=?, \
+                           update_time=? where id=?",
+                          &[&username, &email, &avatar_url, &now, &github_id]));
+
+        // the row was written just above
+        let user_ids = try!(select(&conn, "SELECT user_id from github_user where id=?",
+                                   &[&github_id],
+                                   |row| {
+                                       let user_id: Option<i64> = try!(row.get_checked(0));
+                                       Ok(user_id.map(|id| id as u64))
+                                   }));
+        Ok(user_ids[0])
+    }
+
+    fn bind_github_user(&self, github_id: u64, user_id: u64) -> RepoResult<()> {
+        let now = format_time(&Local::now().naive_local());
+        try!(lock(&self.0).execute(
+            "UPDATE github_user set user_id=?, bind_time=? where id=?",
+            &[&(user_id as i64), &now, &(github_id as i64)]));
+        Ok(())
+    }
+
+    fn create_from_github(&self, github_id: u64, username: &str,
+                          email: &str) -> RepoResult<Option<u64>> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+
+        match trans.execute("INSERT INTO user(username, email, password, salt, create_time) \
+                             VALUES (?, ?, ?, ?, ?)",
+                            &[&username, &email, &"", &"", &now]) {
+            Ok(_) => {},
+            Err(ref e) if is_duplicate(e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let user_id = trans.last_insert_rowid();
+
+        try!(trans.execute("UPDATE github_user set user_id=?, bind_time=? where id=?",
+                           &[&user_id, &now, &(github_id as i64)]));
+
+        try!(trans.commit());
+        Ok(Some(user_id as u64))
+    }
+
+    fn create_password_reset(&self, user_id: u64, token_hash: &str,
+                             expire_time: NaiveDateTime) -> RepoResult<()> {
+        let now = format_time(&Local::now().naive_local());
+        try!(lock(&self.0).execute(
+            "INSERT INTO password_reset(user_id, token_hash, create_time, expire_time) \
+             VALUES (?, ?, ?, ?)",
+            &[&(user_id as i64), &token_hash, &now, &format_time(&expire_time)]));
+        Ok(())
+    }
+
+    fn find_password_reset(&self, token_hash: &str,
+                           now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>> {
+        let resets = try!(select(&lock(&self.0),
+                                 "SELECT id, user_id from password_reset where token_hash=? \
+                                  and used_time is null and expire_time>?",
+                                 &[&token_hash, &format_time(&now)],
+                                 |row| Ok((try!(get_u64(row, 0)), try!(get_u64(row, 1))))));
+        Ok(resets.into_iter().next())
+    }
+
+    fn reset_password(&self, reset_id: u64, user_id: u64,
+                      password_hash: &str) -> RepoResult<bool> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+
+        if try!(trans.execute("UPDATE password_reset set used_time=? \
+                               where id=? and used_time is null",
+                              &[&now, &(reset_id as i64)])) == 0 {
+            return Ok(false);
+        }
+
+        try!(trans.execute("UPDATE password_reset set used_time=? \
+                            where user_id=? and used_time is null",
+                           &[&now, &(user_id as i64)]));
+
+        try!(trans.execute("UPDATE user set password=?, salt=? where id=?",
+                           &[&password_hash, &"", &(user_id as i64)]));
+        try!(trans.execute("DELETE FROM session where user_id=?", &[&(user_id as i64)]));
+        try!(trans.execute("DELETE FROM access_token where user_id=?", &[&(user_id as i64)]));
+
+        try!(trans.commit());
+        Ok(true)
+    }
+}
+
+impl TagRepo for LiteTagRepo {
+    fn exists(&self, name: &str) -> RepoResult<bool> {
+        Ok(try!(count(&lock(&self.0), "SELECT count(id) from tag where name=?",
+                      &[&name])) > 0)
+    }
+
+    fn complete(&self, prefix: &str) -> RepoResult<Vec<String>> {
+        select(&lock(&self.0),
+               "SELECT t.name from tag as t join article_tag as at on at.tag_id=t.id \
+                where t.name like ? escape '\\' group by t.id \
+                order by count(at.article_id) desc limit 10",
+               &[&(escape_like(prefix) + "%")],
+               |row| Ok(try!(row.get_checked(0))))
+    }
+}
+
+impl SessionRepo for LiteSessionRepo {
+    fn create(&self, user_id: u64, token_hash: &str, ip: &str, user_agent: &str,
+              now: NaiveDateTime, expire_time: NaiveDateTime) -> RepoResult<()> {
+        let now = format_time(&now);
+        try!(lock(&self.0).execute(
+            "INSERT INTO session(user_id, token_hash, last_seen_ip, user_agent, \
+             create_time, last_seen_time, expire_time) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            &[&(user_id as i64), &token_hash, &ip, &user_agent, &now, &now,
+              &format_time(&expire_time)]));
+        Ok(())
+    }
+
+    fn find(&self, token_hash: &str, now: NaiveDateTime) -> RepoResult<Option<(u64, u64)>> {
+        let sessions = try!(select(&lock(&self.0),
+                                   "SELECT id, user_id from session where token_hash=? \
+                                    and expire_time>?",
+                                   &[&token_hash, &format_time(&now)],
+                                   |row| Ok((try!(get_u64(row, 0)), try!(get_u64(row, 1))))));
+        Ok(sessions.into_iter().next())
+    }
+
+    fn touch(&self, id: u64, ip: &str, user_agent: &str, now: NaiveDateTime,
+             before: NaiveDateTime) -> RepoResult<()> {
+        try!(lock(&self.0).execute(
+            "UPDATE session set last_seen_ip=?, user_agent=?, last_seen_time=? \
+             where id=? and last_seen_time<?",
+            &[&ip, &user_agent, &format_time(&now), &(id as i64), &format_time(&before)]));
+        Ok(())
+    }
+
+    fn list(&self, user_id: u64, now: NaiveDateTime) -> RepoResult<Vec<Session>> {
+        select(&lock(&self.0),
+               "SELECT id, last_seen_ip, user_agent, create_time, last_seen_time \
+                from session where user_id=? and expire_time>? \
+                order by last_seen_time desc",
+               &[&(user_id as i64), &format_time(&now)],
+               |row| Ok(Session {
+                   id: try!(get_u64(row, 0)),
+                   last_seen_ip: try!(row.get_checked(1)),
+                   user_agent: try!(row.get_checked(2)),
+                   create_time: try!(get_time(row, 3)),
+                   last_seen_time: try!(get_time(row, 4)),
+                   is_current: false,
+               }))
+    }
+
+    fn delete_by_token(&self, token_hash: &str) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM session where token_hash=?",
+                                            &[&token_hash]));
+        Ok(())
+    }
+
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM session where user_id=? and id=?",
+                                            &[&(user_id as i64), &(id as i64)]));
+        Ok(())
+    }
+
+    fn delete_all(&self, user_id: u64) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM session where user_id=?",
+                                            &[&(user_id as i64)]));
+        Ok(())
+    }
+
+    fn delete_others(&self, user_id: u64, keep_id: u64) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM session where user_id=? and id<>?",
+                                            &[&(user_id as i64), &(keep_id as i64)]));
+        Ok(())
+    }
+}
+
+impl TokenRepo for LiteTokenRepo {
+    fn create(&self, user_id: u64, name: &str, token_hash: &str, scopes: u8,
+              now: NaiveDateTime) -> RepoResult<()> {
+        try!(lock(&self.0).execute(
+            "INSERT INTO access_token(user_id, name, token_hash, scopes, create_time) \
+             VALUES (?, ?, ?, ?, ?)",
+            &[&(user_id as i64), &name, &token_hash, &(scopes as i64), &format_time(&now)]));
+        Ok(())
+    }
+
+    fn find(&self, token_hash: &str) -> RepoResult<Option<(u64, u8, u64)>> {
+        let tokens = try!(select(&lock(&self.0),
+                                 "SELECT id, scopes, user_id from access_token \
+                                  where token_hash=?",
+                                 &[&token_hash],
+                                 |row| Ok((try!(get_u64(row, 0)), try!(get_u64(row, 1)) as u8,
+                                        try!(get_u64(row, 2)))));
+        Ok(tokens.into_iter().next())
+    }
+
+    fn touch(&self, id: u64, now: NaiveDateTime, before: NaiveDateTime) -> RepoResult<()> {
+        try!(lock(&self.0).execute(
+            "UPDATE access_token set last_used_time=? where id=? \
+             and (last_used_time is null or last_used_time<?)",
+            &[&format_time(&now), &(id as i64), &format_time(&before)]));
+        Ok(())
+    }
+
+    fn list(&self, user_id: u64) -> RepoResult<Vec<AccessToken>> {
+        select(&lock(&self.0),
+               "SELECT id, name, scopes, create_time, last_used_time \
+                from access_token where user_id=? order by id desc",
+               &[&(user_id as i64)],
+               |row| Ok(AccessToken {
+                   id: try!(get_u64(row, 0)),
+                   name: try!(row.get_checked(1)),
+                   scopes: try!(get_u64(row, 2)) as u8,
+                   create_time: try!(get_time(row, 3)),
+                   last_used_time: try!(get_optional_time(row, 4)),
+               }))
+    }
+
+    fn delete(&self, user_id: u64, id: u64) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM access_token where user_id=? and id=?",
+                                            &[&(user_id as i64), &(id as i64)]));
+        Ok(())
+    }
+
+    fn delete_all(&self, user_id: u64) -> RepoResult<()> {
+        try!(lock(&self.0).execute("DELETE FROM access_token where user_id=?",
+                                            &[&(user_id as i64)]));
+        Ok(())
+    }
+}
+
+impl NewsRepo for LiteNewsRepo {
+    fn save(&self, items: &[NewsItem]) -> RepoResult<()> {
+        let now = format_time(&Local::now().naive_local());
+        let mut conn = lock(&self.0);
+        let trans = try!(conn.transaction());
+
+        for item in items {
+            let url_hash = util::sha256_hex(&news::normalize_url(&item.url));
+            try!(trans.execute("INSERT OR IGNORE INTO news(source, title, url, url_hash, \
+                                score, author, comments_count, comments_url, publish_time, \
+                                fetch_time) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                               &[&item.source, &item.title, &item.url, &url_hash,
+                                 &item.score, &item.author, &item.comments_count,
+                                 &item.comments_url, &format_time(&item.publish_time), &now]));
+            try!(trans.execute("UPDATE news set \
+                                score=CASE WHEN source=? THEN ? ELSE score END, \
+                                comments_count=CASE WHEN source=? THEN ? \
+                                ELSE comments_count END, \
+                                fetch_time=? where url_hash=?",
+                               &[&item.source, &item.score, &item.source, &item.comments_count,
+                                 &now, &url_hash]));
+        }
+
+        try!(trans.commit());
+        Ok(())
+    }
+
+    fn count(&self) -> RepoResult<usize> {
+        count(&lock(&self.0), "SELECT count(id) from news", &[])
+    }
+
+    fn list(&self, page: usize) -> RepoResult<Vec<NewsItem>> {
+        select(&lock(&self.0),
+               "SELECT source, title, url, author, score, comments_count, \
+                comments_url, publish_time from news \
+                order by publish_time desc, id desc limit ?,?",
+               &[&(((page - 1) * constant::PAGE_SIZE) as i64), &(constant::PAGE_SIZE as i64)],
+               |row| Ok(NewsItem {
+                   source: try!(row.get_checked(0)),
+                   title: try!(row.get_checked(1)),
+                   url: try!(row.get_checked(2)),
+                   author: try!(row.get_checked(3)),
+                   score: try!(row.get_checked(4)),
+                   comments_count: try!(row.get_checked(5)),
+                   comments_url: try!(row.get_checked(6)),
+                   publish_time: try!(get_time(row, 7)),
+               }))
+    }
+}
+
+// A panic while the connection was held must not take every later request
+// down with it; sqlite already rolled back whatever that one left undone.
+fn lock(conn: &Mutex<Connection>) -> MutexGuard<Connection> {
+    conn.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// every row a query returns, through `f`
+fn select<T, F>(conn: &Connection, query: &str, params: &[&ToSql], f: F) -> RepoResult<Vec<T>>
+    where F: FnMut(&Row) -> RepoResult<T> {
+    let mut stmt = try!(conn.prepare(query));
+    let rows = try!(stmt.query_and_then(params, f));
+    let mut result = Vec::new();
+    for row in rows {
+        result.push(try!(row));
+    }
+    Ok(result)
+}
+
+// the number a `SELECT count(...)` query returns
+fn count(conn: &Connection, query: &str, params: &[&ToSql]) -> RepoResult<usize> {
+    conn.query_row_and_then(query, params, |row| get_u64(row, 0).map(|count| count as usize))
+}
+
+fn refs(params: &[Box<ToSql>]) -> Vec<&ToSql> {
+    params.iter().map(|param| &**param).collect()
+}
+
+fn is_duplicate(e: &rusqlite::Error) -> bool {
+    match *e {
+        rusqlite::Error::SqliteFailure(ref e, _) => {
+            e.code == rusqlite::ErrorCode::ConstraintViolation
+        },
+        _ => false,
+    }
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+// The getters return bad data as errors instead of panicking, which would
+// poison the connection's lock.
+
+fn get_time(row: &Row, idx: i32) -> RepoResult<NaiveDateTime> {
+    let time: String = try!(row.get_checked(idx));
+    parse_time(&time)
+}
+
+fn get_optional_time(row: &Row, idx: i32) -> RepoResult<Option<NaiveDateTime>> {
+    let time: Option<String> = try!(row.get_checked(idx));
+    match time {
+        Some(time) => parse_time(&time).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn parse_time(time: &str) -> RepoResult<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+        .map_err(|e| DbError::Value(format!("bad time {:?}: {}", time, e)))
+}
+
+// sqlite integers are i64
+fn get_u64(row: &Row, idx: i32) -> RepoResult<u64> {
+    let value: i64 = try!(row.get_checked(idx));
+    Ok(value as u64)
+}
+
+fn get_i8(row: &Row, idx: i32) -> RepoResult<i8> {
+    let value: i64 = try!(row.get_checked(idx));
+    Ok(value as i8)
+}
+
+// a row of ARTICLE_COLUMNS
+fn article_from_row(row: &Row) -> RepoResult<Article> {
+    let category = try!(get_i8(row, 1));
+
+    Ok(Article {
+        id: try!(get_u64(row, 0)),
+        category: Category::from_value(category),
+        title: try!(row.get_checked(2)),
+        content: try!(row.get_checked(3)),
+        user: user(try!(get_u64(row, 10)), try!(row.get_checked(11)),
+                   try!(row.get_checked(12)), *constant::DEFAULT_DATETIME),
+        comments_count: try!(get_u64(row, 4)) as usize,
+        create_time: try!(get_time(row, 5)),
+        update_time: try!(get_time(row, 6)),
+        comments: Vec::new(),
+        flag: try!(get_u64(row, 7)) as u8,
+        priority: try!(get_i8(row, 8)),
+        status: try!(get_i8(row, 9)),
+        tags: Vec::new(),
+    })
+}
+
+// the `where` of `message as m` for a filter
+fn message_condition(filter: &MessageFilter) -> (String, Vec<Box<ToSql>>) {
+    let mut condition = "m.to_user_id=?".to_owned();
+    let mut params: Vec<Box<ToSql>> = vec![Box::new(filter.to_user_id as i64)];
+    if let Some(mode) = filter.mode {
+        condition.push_str(" and m.mode=?");
+        params.push(Box::new(mode as i64));
+    }
+    if filter.unread_only {
+        condition.push_str(" and m.status=?");
+        params.push(Box::new(constant::MESSAGE::STATUS::INIT as i64));
+    }
+    (condition, params)
+}
+
+// a row of USER_COLUMNS
+fn user_from_row(row: &Row) -> RepoResult<User> {
+    Ok(User {
+        role: try!(get_i8(row, 3)),
+        status: try!(get_i8(row, 4)),
+        notify: try!(get_i8(row, 5)),
+        ..user(try!(get_u64(row, 0)), try!(row.get_checked(1)), try!(row.get_checked(2)),
+               try!(get_time(row, 6)))
+    })
+}
+
+fn user(id: u64, username: String, email: String, create_time: NaiveDateTime) -> User {
+    User {
+        id: id,
+        avatar: gen_gravatar_url(&email),
+        username: username,
+        email: email,
+        create_time: create_time,
+        ..Default::default()
+    }
+}
+
+// a row of `id, article_id, parent_id, user_id, content, status,
+// create_time, update_time`; the author, article and parent only have
+// their id set
+fn comment_stub_from_row(row: &Row) -> RepoResult<Comment> {
+    let mut article = Article::default();
+    article.id = try!(get_u64(row, 1));
+    let parent_id: Option<i64> = try!(row.get_checked(2));
+
+    Ok(Comment {
+        id: try!(get_u64(row, 0)),
+        user: User { id: try!(get_u64(row, 3)), ..Default::default() },
+        content: try!(row.get_checked(4)),
+        status: try!(get_i8(row, 5)),
+        create_time: try!(get_time(row, 6)),
+        update_time: try!(get_optional_time(row, 7)),
+        article: Some(article),
+        parent: parent_id.map(|parent_id| Box::new(Comment {
+            id: parent_id as u64,
+            ..Default::default()
+        })),
+        ..Default::default()
+    })
+}
+
+// the lock on the connection keeps anybody else from changing the article
+// between the check and the write
+fn check_article(conn: &Connection, id: u64, author_id: Option<u64>,
+                 status: i8) -> RepoResult<Change> {
+    let rows = try!(select(conn, "SELECT user_id, status from article where id=?",
+                           &[&(id as i64)],
+                           |row| Ok((try!(get_u64(row, 0)), try!(get_i8(row, 1))))));
+    let (user_id, current_status) = match rows.into_iter().next() {
+        Some(row) => row,
+        None => return Ok(Change::NotFound),
+    };
+    if current_status != status {
+        return Ok(Change::NotFound);
+    }
+    match author_id {
+        Some(author_id) if author_id != user_id => Ok(Change::Forbidden),
+        _ => Ok(Change::Done),
+    }
+}
+
+fn save_tags(conn: &Connection, article_id: u64, tags: &[String]) -> RepoResult<()> {
+    let now = format_time(&Local::now().naive_local());
+    let article_id = article_id as i64;
+
+    try!(conn.execute("DELETE FROM article_tag where article_id=?", &[&article_id]));
+
+    for tag in tags {
+        try!(conn.execute("INSERT OR IGNORE INTO tag(name, create_time) VALUES (?, ?)",
+                          &[tag, &now]));
+        try!(conn.execute("INSERT INTO article_tag(article_id, tag_id) \
+                           SELECT ?, id from tag where name=?",
+                          &[&article_id, tag]));
+    }
+    Ok(())
+}
+
+// tag names of each article, keyed by article id
+fn get_tags(conn: &Connection, article_ids: &[u64]) -> RepoResult<HashMap<u64, Vec<String>>> {
+    let mut tags: HashMap<u64, Vec<String>> = HashMap::new();
+
+    if article_ids.is_empty() {
+        return Ok(tags);
+    }
+
+    let placeholders = vec!["?"; article_ids.len()].join(",");
+    let params: Vec<Box<ToSql>> = article_ids.iter()
+        .map(|&id| Box::new(id as i64) as Box<ToSql>)
+        .collect();
+    let rows = try!(select(conn,
+                           &format!("SELECT at.article_id, t.name from article_tag as at \
+                                     join tag as t on at.tag_id=t.id \
+                                     where at.article_id in ({}) order by t.name",
+                                    placeholders),
+                           &refs(&params),
+                           |row| Ok((try!(get_u64(row, 0)), try!(row.get_checked(1))))));
+
+    for (article_id, name) in rows {
+        let name: String = name;
+        tags.entry(article_id).or_insert_with(Vec::new).push(name);
+    }
+
+    Ok(tags)
+}
@@ -1,6 +1,6 @@
 use router::Router;
 use handlers;
-use base::framework::user_required;
+use base::framework::{user_required, admin_required};
 
 pub fn gen_router() -> Router {
     let mut router = Router::new();
@@ -12,18 +12,78 @@ pub fn gen_router() -> Router {
     router.post("/user/login", handlers::user::login);
     router.post("/user/github/bind", handlers::user::github_login);
     router.post("/user/logout", handlers::user::logout);
+    router.get("/user/password/forgot", handlers::user::forgot_load);
+    router.post("/user/password/forgot", handlers::user::forgot);
+    router.get("/user/password/reset/:token", handlers::user::reset_load);
+    router.post("/user/password/reset/:token", handlers::user::reset);
     router.get("/article/new", user_required(handlers::article::new_load));
     router.post("/article/new", user_required(handlers::article::new));
     router.get("/article/:article_id", handlers::article::show);
     router.get("/article/:article_id/edit", user_required(handlers::article::edit_load));
     router.post("/article/:article_id/edit", user_required(handlers::article::edit));
+    router.post("/article/:article_id/delete", user_required(handlers::article::delete));
+    router.post("/article/:article_id/restore", user_required(handlers::article::restore));
     router.get("/category/:category_id", handlers::home::category);
+    router.get("/search", handlers::home::search);
+    router.get("/tag/:name", handlers::tag::show);
+    router.get("/tags/complete", handlers::tag::complete);
     router.get("/user/:user_id", handlers::user::show);
     router.get("/user/:user_id/comments", handlers::user::show_comments);
     router.get("/user/:user_id/messages", user_required(handlers::user::show_messages));
+    router.post("/user/:user_id/messages/read_all",
+                user_required(handlers::user::read_all_messages));
+    router.post("/user/:user_id/messages/:message_id/read",
+                user_required(handlers::user::read_message));
+    router.post("/user/:user_id/messages/:message_id/unread",
+                user_required(handlers::user::unread_message));
+    router.post("/user/:user_id/messages/:message_id/delete",
+                user_required(handlers::user::delete_message));
+    router.post("/user/:user_id/notify", user_required(handlers::user::set_notify));
+    router.get("/user/:user_id/unsubscribe/:token", handlers::user::unsubscribe_load);
+    router.post("/user/:user_id/unsubscribe/:token", handlers::user::unsubscribe);
+    router.get("/user/:user_id/sessions", user_required(handlers::user::show_sessions));
+    router.post("/user/:user_id/sessions/logout_others",
+                user_required(handlers::user::logout_other_sessions));
+    router.post("/user/:user_id/sessions/:session_id/delete",
+                user_required(handlers::user::delete_session));
+    router.get("/user/:user_id/tokens", user_required(handlers::user::show_tokens));
+    router.post("/user/:user_id/tokens/new", user_required(handlers::user::new_token));
+    router.post("/user/:user_id/tokens/:token_id/delete",
+                user_required(handlers::user::delete_token));
     router.post("/comment/new", user_required(handlers::comment::new));
-    router.get("/rss", handlers::home::rss);
+    router.post("/comment/:comment_id/edit", user_required(handlers::comment::edit));
+    router.post("/comment/:comment_id/delete", user_required(handlers::comment::delete));
+    router.get("/rss", handlers::feed::rss);
+    router.get("/feed.atom", handlers::feed::atom);
+    router.get("/feed.json", handlers::feed::json);
+    router.get("/category/:category_id/rss", handlers::feed::category_rss);
+    router.get("/user/:user_id/rss", handlers::feed::user_rss);
+    router.get("/article/:article_id/rss", handlers::feed::article_rss);
     router.get("/news", handlers::home::news);
     router.get("/auth/github/callback", handlers::user::github_callback);
+    router.get("/admin", admin_required(handlers::admin::index));
+    router.post("/admin/article/:article_id/top", admin_required(handlers::admin::set_top));
+    router.post("/admin/article/:article_id/essence",
+                admin_required(handlers::admin::set_essence));
+    router.post("/admin/article/:article_id/priority",
+                admin_required(handlers::admin::set_priority));
+    router.post("/admin/article/:article_id/category",
+                admin_required(handlers::admin::set_category));
+    router
+}
+
+// mounted at /api/v1, see API.md
+pub fn gen_api_router() -> Router {
+    let mut router = Router::new();
+    router.get("/articles", handlers::api::list_articles);
+    router.post("/articles", handlers::api::create_article);
+    router.get("/articles/:article_id", handlers::api::show_article);
+    router.put("/articles/:article_id", handlers::api::edit_article);
+    router.get("/articles/:article_id/comments", handlers::api::list_comments);
+    router.post("/articles/:article_id/comments", handlers::api::create_comment);
+    router.get("/users/:user_id", handlers::api::show_user);
+    router.get("/messages", handlers::api::list_messages);
+    router.get("/messages/unread_count", handlers::api::unread_messages_count);
+    router.post("/messages/read", handlers::api::read_messages);
     router
 }
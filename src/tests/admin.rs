@@ -0,0 +1,50 @@
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use super::TestApp;
+
+#[test]
+fn members_are_refused() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(user_id, "Hello Rust China", "the first article");
+
+    let mut alice = app.client();
+    alice.login("alice");
+    let resp = alice.get("/admin");
+    assert_eq!(resp.status, status::Forbidden);
+    assert!(resp.body.contains("没有权限访问此页面"));
+
+    let resp = alice.post(&format!("/admin/article/{}/top", article_id), &[("on", "1")]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()), Some("非法请求"));
+    let article = app.repos.articles.find(article_id).unwrap().unwrap();
+    assert_eq!(article.flag & constant::ARTICLE::FLAG::TOP, 0);
+
+    // and whoever is not logged in is sent to log in first
+    assert_eq!(app.client().get("/admin").status, status::Found);
+}
+
+#[test]
+fn moderator_pins_an_article() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("moderator", constant::USER::ROLE::MODERATOR);
+    let article_id = app.create_article(user_id, "Hello Rust China", "the first article");
+    let update_time = app.repos.articles.find(article_id).unwrap().unwrap().update_time;
+
+    let mut moderator = app.client();
+    moderator.login("moderator");
+    let resp = moderator.get("/admin");
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("Hello Rust China"));
+
+    let resp = moderator.post(&format!("/admin/article/{}/top", article_id), &[("on", "1")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let article = app.repos.articles.find(article_id).unwrap().unwrap();
+    assert!(article.flag & constant::ARTICLE::FLAG::TOP > 0);
+    // it keeps its place by activity
+    assert_eq!(article.update_time, update_time);
+
+    let resp = moderator.post("/admin/article/999/top", &[("on", "1")]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()), Some("话题不存在"));
+}
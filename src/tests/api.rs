@@ -0,0 +1,154 @@
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use repo::ArticleFilter;
+use super::TestApp;
+
+#[test]
+fn list_articles() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(user_id, "Hello Rust China", "the first article");
+
+    let resp = app.client().get("/api/v1/articles");
+    assert_eq!(resp.status, status::Ok);
+    let json = resp.json();
+    let articles = json.find_path(&["data", "articles"]).and_then(|a| a.as_array()).unwrap();
+    assert_eq!(articles.len(), 1);
+    assert_eq!(articles[0].find("id"), Some(&Json::U64(article_id)));
+    assert_eq!(articles[0].find("title").and_then(|t| t.as_string()),
+               Some("Hello Rust China"));
+    // email addresses are never exposed
+    assert!(!resp.body.contains("alice@example.com"));
+}
+
+#[test]
+fn missing_article_is_json_404() {
+    let app = TestApp::new();
+
+    let resp = app.client().get("/api/v1/articles/1");
+    assert_eq!(resp.status, status::NotFound);
+    assert!(resp.json().find("error").is_some());
+}
+
+#[test]
+fn messages_use_site_login() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    let resp = app.client().get("/api/v1/messages");
+    assert_eq!(resp.status, status::Unauthorized);
+
+    let mut bob = app.client();
+    bob.login("bob");
+    bob.post("/comment/new", &[("article_id", &article_id.to_string()),
+                               ("content", "a reply to alice")]);
+
+    let mut alice = app.client();
+    alice.login("alice");
+    let resp = alice.get("/api/v1/messages");
+    assert_eq!(resp.status, status::Ok);
+    let json = resp.json();
+    let messages = json.find_path(&["data", "messages"]).and_then(|m| m.as_array()).unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].find("mode").and_then(|m| m.as_string()), Some("reply_article"));
+}
+
+#[test]
+fn edit_without_tags_keeps_them() {
+    let app = TestApp::new();
+    app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let mut alice = app.client();
+    alice.login("alice");
+
+    let category = constant::CATEGORY::ORIGINAL.to_string();
+    let resp = alice.post("/api/v1/articles", &[("category", &category),
+                                                ("title", "Hello Rust China"),
+                                                ("content", "the first article"),
+                                                ("tags", "rust web")]);
+    assert_eq!(resp.status, status::Created, "{}", resp.body);
+    let article_id = resp.json().find_path(&["data", "id"]).and_then(|id| id.as_u64()).unwrap();
+    let path = format!("/api/v1/articles/{}", article_id);
+
+    let resp = alice.put(&path, &[("category", &category),
+                                  ("title", "Hello again"),
+                                  ("content", "the first article, edited")]);
+    assert_eq!(resp.status, status::Ok, "{}", resp.body);
+    let article = app.repos.articles.find(article_id).unwrap().unwrap();
+    assert_eq!(article.title, "Hello again");
+    assert_eq!(article.tags, vec!["rust", "web"]);
+
+    // an empty field still clears them
+    alice.put(&path, &[("category", &category),
+                       ("title", "Hello again"),
+                       ("content", "the first article, edited"),
+                       ("tags", "")]);
+    assert!(app.repos.articles.find(article_id).unwrap().unwrap().tags.is_empty());
+}
+
+#[test]
+fn access_token_create_list_delete() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let mut alice = app.client();
+    alice.login("alice");
+
+    let resp = alice.post(&format!("/user/{}/tokens/new", user_id),
+                          &[("name", "my bot"), ("scope_read", "on")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let token = resp.json().find_path(&["data", "token"])
+        .and_then(|t| t.as_string()).unwrap().to_owned();
+    assert!(token.starts_with("rlc_"));
+
+    let resp = alice.get(&format!("/user/{}/tokens", user_id));
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("my bot"));
+    // only ever shown when it is created
+    assert!(!resp.body.contains(&token));
+
+    let tokens = app.repos.tokens.list(user_id).unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].scopes, constant::ACCESS_TOKEN::SCOPE::READ);
+
+    let resp = alice.post(&format!("/user/{}/tokens/{}/delete", user_id, tokens[0].id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    assert!(app.repos.tokens.list(user_id).unwrap().is_empty());
+
+    let mut bot = app.client();
+    bot.headers.push(("Authorization", format!("Bearer {}", token)));
+    assert_eq!(bot.get("/api/v1/messages").status, status::Unauthorized);
+}
+
+#[test]
+fn bearer_token_is_limited_to_its_scopes() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let mut alice = app.client();
+    alice.login("alice");
+    let resp = alice.post(&format!("/user/{}/tokens/new", user_id),
+                          &[("name", "reader"), ("scope_read", "on")]);
+    let token = resp.json().find_path(&["data", "token"])
+        .and_then(|t| t.as_string()).unwrap().to_owned();
+
+    // no cookies at all, only the token
+    let mut bot = app.client();
+    bot.cookies.clear();
+    bot.headers.push(("Authorization", format!("Bearer {}", token)));
+
+    let resp = bot.get("/api/v1/messages");
+    assert_eq!(resp.status, status::Ok, "{}", resp.body);
+    assert!(app.repos.tokens.list(user_id).unwrap()[0].last_used_time.is_some());
+
+    let category = constant::CATEGORY::ORIGINAL.to_string();
+    let resp = bot.post("/api/v1/articles", &[("category", &category),
+                                              ("title", "Hello Rust China"),
+                                              ("content", "the first article")]);
+    assert_eq!(resp.status, status::Forbidden, "{}", resp.body);
+    assert!(app.repos.articles.list(&ArticleFilter::User(user_id), None).unwrap().is_empty());
+
+    let mut stranger = app.client();
+    stranger.headers.push(("Authorization", "Bearer rlc_wrong".to_owned()));
+    assert_eq!(stranger.get("/api/v1/messages").status, status::Unauthorized);
+}
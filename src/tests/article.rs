@@ -0,0 +1,159 @@
+// Most of these call the handlers directly, on repos of their own; deleting
+// and restoring goes through the site.
+
+use chrono::*;
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use base::framework::{LoginUser, ActionError};
+use base::model::{ArticleForm, Comment};
+use handlers::article;
+use repo::{Repos, NewComment};
+use tests::{TestApp, memory_repos, create_user};
+
+fn form(title: &str) -> ArticleForm {
+    ArticleForm {
+        category: constant::CATEGORY::ORIGINAL as i64,
+        title: title.to_owned(),
+        content: "content".to_owned(),
+        tags: None,
+    }
+}
+
+fn add_comment(repos: &Repos, article_id: u64, user_id: u64,
+               parent_id: Option<u64>, content: &str) -> u64 {
+    repos.comments.create(&NewComment {
+        article_id: article_id,
+        user_id: user_id,
+        parent_id: parent_id,
+        content: content.to_owned(),
+        notify: Vec::new(),
+    }).unwrap()
+}
+
+fn login_user(id: u64, role: i8) -> LoginUser {
+    LoginUser::new(id, "", "", role, 0, "")
+}
+
+#[test]
+fn update_by_author() {
+    let repos = memory_repos();
+    let author_id = create_user(&repos, "author", constant::USER::ROLE::MEMBER);
+    let article_id = repos.articles.create(author_id, &form("old")).unwrap();
+
+    let user = login_user(author_id, constant::USER::ROLE::MEMBER);
+    article::update(&repos, &user, article_id, &form("new")).unwrap();
+    assert_eq!(repos.articles.find(article_id).unwrap().unwrap().title, "new");
+}
+
+#[test]
+fn update_by_someone_else() {
+    let repos = memory_repos();
+    let author_id = create_user(&repos, "author", constant::USER::ROLE::MEMBER);
+    let other_id = create_user(&repos, "other", constant::USER::ROLE::MEMBER);
+    let article_id = repos.articles.create(author_id, &form("old")).unwrap();
+
+    let user = login_user(other_id, constant::USER::ROLE::MEMBER);
+    match article::update(&repos, &user, article_id, &form("new")) {
+        Err(ActionError::Forbidden) => (),
+        other => panic!("expected Forbidden, got {:?}", other),
+    }
+    assert_eq!(repos.articles.find(article_id).unwrap().unwrap().title, "old");
+}
+
+#[test]
+fn update_missing_article() {
+    let repos = memory_repos();
+    let user_id = create_user(&repos, "author", constant::USER::ROLE::MEMBER);
+
+    let user = login_user(user_id, constant::USER::ROLE::MEMBER);
+    match article::update(&repos, &user, 1, &form("new")) {
+        Err(ActionError::NotFound) => (),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn prepare_comments_quotes_and_permissions() {
+    let repos = memory_repos();
+    let author_id = create_user(&repos, "author", constant::USER::ROLE::MEMBER);
+    let other_id = create_user(&repos, "other", constant::USER::ROLE::MEMBER);
+    let article_id = repos.articles.create(author_id, &form("title")).unwrap();
+    let first_id = add_comment(&repos, article_id, author_id, None, "first");
+    add_comment(&repos, article_id, other_id, Some(first_id), "second");
+
+    let mut comments = repos.comments.list_by_article(article_id).unwrap();
+    comments.push(Comment {
+        id: 3,
+        status: constant::COMMENT::STATUS::DELETED,
+        content: "gone".to_owned(),
+        create_time: Local::now().naive_local(),
+        ..Default::default()
+    });
+
+    let user = Some(login_user(author_id, constant::USER::ROLE::MEMBER));
+    let now = Local::now().naive_local();
+    let prepared = article::prepare_comments(comments, &user, 10, now);
+
+    // the author may still edit their own comment, not the reply
+    assert_eq!(prepared[0].source, Some("first".to_owned()));
+    assert!(prepared[0].can_delete);
+    assert_eq!(prepared[1].source, None);
+    assert!(!prepared[1].can_delete);
+
+    let parent = prepared[1].parent.as_ref().unwrap();
+    assert_eq!(parent.user.username, "author");
+    assert!(parent.content.contains("first"));
+
+    assert_eq!(prepared[2].content, "");
+    assert!(!prepared[2].can_delete);
+
+    // and not once the window is over
+    let comments = repos.comments.list_by_article(article_id).unwrap();
+    let prepared = article::prepare_comments(comments, &user, 10, now + Duration::minutes(11));
+    assert_eq!(prepared[0].source, None);
+    assert!(!prepared[0].can_delete);
+}
+
+#[test]
+fn delete_and_restore_keep_update_time() {
+    let app = TestApp::new();
+    let author_id = app.create_user("author", constant::USER::ROLE::MEMBER);
+    app.create_user("other", constant::USER::ROLE::MEMBER);
+    app.create_user("moderator", constant::USER::ROLE::MODERATOR);
+    let article_id = app.create_article(author_id, "Hello Rust China", "the first article");
+    let update_time = app.repos.articles.find(article_id).unwrap().unwrap().update_time;
+
+    let mut other = app.client();
+    other.login("other");
+    let resp = other.post(&format!("/article/{}/delete", article_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(1)), "{}", resp.body);
+    let article = app.repos.articles.find(article_id).unwrap().unwrap();
+    assert_eq!(article.status, constant::ARTICLE::STATUS::NORMAL);
+
+    let mut author = app.client();
+    author.login("author");
+    let resp = author.post(&format!("/article/{}/delete", article_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let article = app.repos.articles.find(article_id).unwrap().unwrap();
+    assert_eq!(article.status, constant::ARTICLE::STATUS::DELETED);
+    assert_eq!(article.update_time, update_time);
+
+    // already deleted
+    let resp = author.post(&format!("/article/{}/delete", article_id), &[]);
+    assert_eq!(resp.status, status::NotFound);
+    // and no longer editable
+    let user = login_user(author_id, constant::USER::ROLE::MEMBER);
+    match article::update(&app.repos, &user, article_id, &form("new")) {
+        Err(ActionError::NotFound) => (),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+
+    let mut moderator = app.client();
+    moderator.login("moderator");
+    let resp = moderator.post(&format!("/article/{}/restore", article_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let article = app.repos.articles.find(article_id).unwrap().unwrap();
+    assert_eq!(article.status, constant::ARTICLE::STATUS::NORMAL);
+    assert_eq!(article.update_time, update_time);
+}
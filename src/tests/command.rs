@@ -0,0 +1,89 @@
+// The admin commands, with the password fed in as if piped to stdin.
+
+use chrono::Local;
+use base::constant;
+use base::db::Database;
+use base::session;
+use command;
+use super::{TestApp, PASSWORD};
+
+fn run(db: &Database, args: &[&str], input: &str) -> Result<(), String> {
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    command::run_with(db, &args, &mut input.as_bytes(), false)
+}
+
+#[test]
+fn create_user_reads_the_password() {
+    let app = TestApp::new();
+    run(&app.db, &["user", "create", "carol", "carol@example.com"],
+        &format!("{}\n", PASSWORD)).unwrap();
+
+    let user = app.repos.users.find_by_username("carol").unwrap().unwrap();
+    assert_eq!(user.email, "carol@example.com");
+    app.client().login("carol");
+
+    // taken, too short, or on the command line
+    assert!(run(&app.db, &["user", "create", "carol", "other@example.com"],
+                PASSWORD).is_err());
+    assert!(run(&app.db, &["user", "create", "dave", "dave@example.com"], "short\n").is_err());
+    assert_eq!(run(&app.db, &["user", "create", "dave", "dave@example.com", PASSWORD], ""),
+               Err(command::USAGE.to_owned()));
+    assert!(app.repos.users.find_by_username("dave").unwrap().is_none());
+}
+
+#[test]
+fn ban_logs_out_and_set_role() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    session::create(&app.repos, user_id, "127.0.0.1", "TestPhone").unwrap();
+
+    run(&app.db, &["user", "ban", "alice"], "").unwrap();
+    let user = app.repos.users.find(user_id).unwrap().unwrap();
+    assert_eq!(user.status, constant::USER::STATUS::BANNED);
+    assert!(app.repos.sessions.list(user_id, Local::now().naive_local()).unwrap().is_empty());
+
+    run(&app.db, &["user", "unban", "alice"], "").unwrap();
+    run(&app.db, &["user", "set-role", "alice", "moderator"], "").unwrap();
+    let user = app.repos.users.find(user_id).unwrap().unwrap();
+    assert_eq!(user.status, constant::USER::STATUS::NORMAL);
+    assert_eq!(user.role, constant::USER::ROLE::MODERATOR);
+
+    assert!(run(&app.db, &["user", "set-role", "alice", "owner"], "").is_err());
+    assert!(run(&app.db, &["user", "ban", "nobody"], "").is_err());
+}
+
+#[test]
+fn article_commands() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(user_id, "Hello Rust China", "the first article");
+    let id = article_id.to_string();
+    let id = &*id;
+    let article = || app.repos.articles.find(article_id).unwrap().unwrap();
+
+    run(&app.db, &["article", "pin", id], "").unwrap();
+    run(&app.db, &["article", "essence", id], "").unwrap();
+    assert_eq!(article().flag, constant::ARTICLE::FLAG::TOP | constant::ARTICLE::FLAG::ESSENCE);
+    run(&app.db, &["article", "unpin", id], "").unwrap();
+    assert_eq!(article().flag, constant::ARTICLE::FLAG::ESSENCE);
+
+    run(&app.db, &["article", "delete", id], "").unwrap();
+    assert_eq!(article().status, constant::ARTICLE::STATUS::DELETED);
+    // only once, and deleted articles are not pinned
+    assert!(run(&app.db, &["article", "delete", id], "").is_err());
+    assert!(run(&app.db, &["article", "pin", id], "").is_err());
+    run(&app.db, &["article", "restore", id], "").unwrap();
+    assert_eq!(article().status, constant::ARTICLE::STATUS::NORMAL);
+
+    assert!(run(&app.db, &["article", "pin", "x"], "").is_err());
+}
+
+#[test]
+fn commands_wait_for_migrate() {
+    let db = Database::sqlite_memory();
+    let err = run(&db, &["user", "ban", "alice"], "").unwrap_err();
+    assert!(err.contains("rust_lang_cn migrate"), "{}", err);
+
+    run(&db, &["migrate"], "").unwrap();
+    assert!(run(&db, &["user", "create", "alice", "alice@example.com"], PASSWORD).is_ok());
+}
@@ -0,0 +1,124 @@
+// Editing and deleting comments through the site.
+
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use repo::MessageFilter;
+use super::{TestApp, Client};
+
+// bob replies to alice's article; returns (alice's id, the article, bob's comment)
+fn reply(app: &TestApp, bob: &mut Client) -> (u64, u64, u64) {
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    bob.login("bob");
+    let resp = bob.post("/comment/new", &[("article_id", &article_id.to_string()),
+                                          ("content", "the first reply")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let comment_id = app.repos.comments.list_by_article(article_id).unwrap()[0].id;
+    (alice_id, article_id, comment_id)
+}
+
+fn messages_of(app: &TestApp, user_id: u64) -> usize {
+    app.repos.messages.count(&MessageFilter {
+        to_user_id: user_id,
+        mode: None,
+        unread_only: false,
+    }).unwrap()
+}
+
+fn comments_count(app: &TestApp, article_id: u64) -> usize {
+    app.repos.articles.find(article_id).unwrap().unwrap().comments_count
+}
+
+#[test]
+fn edit_keeps_revisions() {
+    let app = TestApp::new();
+    let mut bob = app.client();
+    let (_, _, comment_id) = reply(&app, &mut bob);
+    let path = format!("/comment/{}/edit", comment_id);
+
+    let resp = bob.post(&path, &[("content", "the first reply, edited")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    bob.post(&path, &[("content", "the first reply, edited again")]);
+
+    let comment = app.repos.comments.find(comment_id).unwrap().unwrap();
+    assert_eq!(comment.content, "the first reply, edited again");
+    assert!(comment.update_time.is_some());
+    assert_eq!(app.repos.comments.revisions(comment_id).unwrap(),
+               vec!["the first reply", "the first reply, edited"]);
+}
+
+#[test]
+fn only_the_author_edits_or_deletes() {
+    let app = TestApp::new();
+    let mut bob = app.client();
+    let (alice_id, article_id, comment_id) = reply(&app, &mut bob);
+
+    // not even the author of the article
+    let mut alice = app.client();
+    alice.login("alice");
+    let resp = alice.post(&format!("/comment/{}/edit", comment_id),
+                          &[("content", "not my reply at all")]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()), Some("非法请求"));
+    let resp = alice.post(&format!("/comment/{}/delete", comment_id), &[]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()), Some("非法请求"));
+
+    let comment = app.repos.comments.find(comment_id).unwrap().unwrap();
+    assert_eq!(comment.content, "the first reply");
+    assert_eq!(comment.status, constant::COMMENT::STATUS::NORMAL);
+    assert!(app.repos.comments.revisions(comment_id).unwrap().is_empty());
+    assert_eq!(comments_count(&app, article_id), 1);
+    assert_eq!(messages_of(&app, alice_id), 1);
+}
+
+#[test]
+fn delete_takes_back_count_and_messages() {
+    let app = TestApp::new();
+    let mut bob = app.client();
+    let (alice_id, article_id, comment_id) = reply(&app, &mut bob);
+    assert_eq!(comments_count(&app, article_id), 1);
+    assert_eq!(messages_of(&app, alice_id), 1);
+
+    let resp = bob.post(&format!("/comment/{}/delete", comment_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let comment = app.repos.comments.find(comment_id).unwrap().unwrap();
+    assert_eq!(comment.status, constant::COMMENT::STATUS::DELETED);
+    assert_eq!(comments_count(&app, article_id), 0);
+    assert_eq!(messages_of(&app, alice_id), 0);
+
+    // once is enough
+    let resp = bob.post(&format!("/comment/{}/delete", comment_id), &[]);
+    assert_eq!(resp.status, status::NotFound);
+    assert_eq!(comments_count(&app, article_id), 0);
+    let resp = bob.post(&format!("/comment/{}/edit", comment_id),
+                        &[("content", "back from the dead")]);
+    assert_eq!(resp.status, status::NotFound);
+}
+
+#[test]
+fn edit_window_closes() {
+    let app = TestApp::with_config(|config| config.comment_edit_minutes = 0);
+    app.create_user("moderator", constant::USER::ROLE::MODERATOR);
+    let mut bob = app.client();
+    let (alice_id, article_id, comment_id) = reply(&app, &mut bob);
+
+    let resp = bob.post(&format!("/comment/{}/edit", comment_id),
+                        &[("content", "too late for this")]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()),
+               Some("回复发表0分钟后不能再编辑"));
+    let resp = bob.post(&format!("/comment/{}/delete", comment_id), &[]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()),
+               Some("回复发表0分钟后不能再删除"));
+    assert_eq!(app.repos.comments.find(comment_id).unwrap().unwrap().content,
+               "the first reply");
+
+    // moderators are not bound by it
+    let mut moderator = app.client();
+    moderator.login("moderator");
+    let resp = moderator.post(&format!("/comment/{}/delete", comment_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    assert_eq!(comments_count(&app, article_id), 0);
+    assert_eq!(messages_of(&app, alice_id), 0);
+}
@@ -0,0 +1,79 @@
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use super::{TestApp, TestResponse};
+
+fn header(resp: &TestResponse, name: &str) -> String {
+    let raw = resp.headers.get_raw(name).expect(name);
+    String::from_utf8(raw[0].clone()).unwrap()
+}
+
+#[test]
+fn feed_formats() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(user_id, "Hello Rust China", "the first article");
+    let link = format!("http://localhost:3000/article/{}", article_id);
+
+    let resp = app.client().get("/rss");
+    assert_eq!(resp.status, status::Ok);
+    assert!(header(&resp, "Content-Type").starts_with("application/xml"));
+    assert!(resp.body.contains("<title>Hello Rust China</title>"), "{}", resp.body);
+    assert!(resp.body.contains(&link));
+
+    let resp = app.client().get("/feed.atom");
+    assert_eq!(resp.status, status::Ok);
+    assert!(header(&resp, "Content-Type").starts_with("application/atom+xml"));
+    assert!(resp.body.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\""));
+    assert!(resp.body.contains("<title>Hello Rust China</title>"), "{}", resp.body);
+    assert!(resp.body.contains("<link rel=\"self\" href=\"http://localhost:3000/feed.atom\"/>"));
+    assert!(resp.body.contains(&link));
+
+    let resp = app.client().get("/feed.json");
+    assert_eq!(resp.status, status::Ok);
+    assert!(header(&resp, "Content-Type").starts_with("application/json"));
+    let json = resp.json();
+    assert_eq!(json.find("version").and_then(|v| v.as_string()),
+               Some("https://jsonfeed.org/version/1"));
+    let items = json.find("items").and_then(|i| i.as_array()).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].find("title"), Some(&Json::String("Hello Rust China".to_owned())));
+    assert_eq!(items[0].find("url"), Some(&Json::String(link)));
+}
+
+#[test]
+fn feed_answers_not_modified() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_article(user_id, "Hello Rust China", "the first article");
+    let article_id = app.create_article(user_id, "Goodbye", "the second article");
+
+    let resp = app.client().get("/feed.atom");
+    let etag = header(&resp, "ETag");
+    let last_modified = header(&resp, "Last-Modified");
+
+    let mut client = app.client();
+    client.headers.push(("If-None-Match", etag.clone()));
+    let resp = client.get("/feed.atom");
+    assert_eq!(resp.status, status::NotModified);
+    assert!(resp.body.is_empty());
+
+    let mut client = app.client();
+    client.headers.push(("If-Modified-Since", last_modified.clone()));
+    assert_eq!(client.get("/feed.atom").status, status::NotModified);
+
+    // deleting moves no time in the feed, the feed is still new
+    app.repos.articles.set_status(article_id, None, constant::ARTICLE::STATUS::NORMAL,
+                                  constant::ARTICLE::STATUS::DELETED).unwrap();
+
+    let resp = client.get("/feed.atom");
+    assert_eq!(resp.status, status::Ok);
+    assert!(!resp.body.contains("Goodbye"));
+    assert!(header(&resp, "Last-Modified") != last_modified);
+
+    let mut client = app.client();
+    client.headers.push(("If-None-Match", etag));
+    let resp = client.get("/feed.atom");
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("Hello Rust China"));
+}
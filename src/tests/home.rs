@@ -0,0 +1,107 @@
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use base::model::ArticleForm;
+use repo::{ArticleFilter, NewComment};
+use super::TestApp;
+
+#[test]
+fn index_lists_articles() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_article(user_id, "Hello Rust China", "the first article");
+
+    let resp = app.client().get("/");
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("Hello Rust China"));
+    assert!(resp.body.contains("alice"));
+}
+
+#[test]
+fn unknown_page_is_404() {
+    let app = TestApp::new();
+
+    let resp = app.client().get("/no/such/page");
+    assert_eq!(resp.status, status::NotFound);
+    assert!(resp.body.contains("页面不存在"));
+}
+
+#[test]
+fn article_page_shows_content() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(user_id, "Hello Rust China", "some **markdown** here");
+
+    let resp = app.client().get(&format!("/article/{}", article_id));
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("<strong>markdown</strong>"));
+
+    let resp = app.client().get(&format!("/article/{}", article_id + 1));
+    assert_eq!(resp.status, status::NotFound);
+}
+
+#[test]
+fn any_tag_name_has_a_page() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let form = ArticleForm {
+        category: constant::CATEGORY::ORIGINAL as i64,
+        title: "Hello Rust China".to_owned(),
+        content: "the first article".to_owned(),
+        tags: Some(vec!["complete".to_owned()]),
+    };
+    app.repos.articles.create(user_id, &form).unwrap();
+
+    let resp = app.client().get("/tag/complete");
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("Hello Rust China"));
+
+    let resp = app.client().get("/tags/complete?q=comp");
+    let json = resp.json();
+    assert_eq!(json.find_path(&["data", "tags"]),
+               Some(&Json::Array(vec![Json::String("complete".to_owned())])));
+}
+
+fn search(app: &TestApp, query: &str, comments: bool) -> Vec<String> {
+    let filter = ArticleFilter::Search { query: query.to_owned(), comments: comments };
+    app.repos.articles.list(&filter, None).unwrap().into_iter().map(|a| a.title).collect()
+}
+
+#[test]
+fn search_matches_literally_titles_first() {
+    let app = TestApp::new();
+    // MySQL searches its full text index, which has no wildcards to escape
+    if app.mysql.is_some() {
+        return;
+    }
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_article(user_id, "about unsafe code", "it is 100% safe, they said");
+    app.create_article(user_id, "100% safe", "nothing else");
+    app.create_article(user_id, "1000 safe ways", "nothing else");
+    app.create_article(user_id, "under_score", "nothing else");
+    app.create_article(user_id, "underscore", "nothing else");
+    let replied_id = app.create_article(user_id, "a question", "nothing else");
+    app.repos.comments.create(&NewComment {
+        article_id: replied_id,
+        user_id: user_id,
+        parent_id: None,
+        content: "the answer: 100% safe".to_owned(),
+        notify: Vec::new(),
+    }).unwrap();
+
+    // a title match beats a newer content match, % is no wildcard
+    assert_eq!(search(&app, "100%", false), vec!["100% safe", "about unsafe code"]);
+    assert_eq!(search(&app, "under_score", false), vec!["under_score"]);
+    // the reply brings in its article, after the title match
+    let found = search(&app, "100%", true);
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[0], "100% safe");
+    assert!(found.contains(&"a question".to_owned()));
+
+    let resp = app.client().get("/search?q=100%25&comments=1");
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("a question"));
+    assert!(!resp.body.contains("1000 safe ways"));
+    let resp = app.client().get("/search?q=100%25");
+    assert!(!resp.body.contains("a question"));
+}
@@ -0,0 +1,121 @@
+// The smtp transport against a fake relay on localhost that takes one mail
+// and reports the conversation.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use rustc_serialize::base64::FromBase64;
+use base::config::Config;
+use base::mail::{Mail, MyMailer};
+
+// accepts one connection and sends back every line the client wrote
+fn fake_relay(auth: bool) -> (u16, mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut lines = Vec::new();
+        let mut in_data = false;
+
+        writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_right().to_owned();
+            lines.push(line.clone());
+
+            let reply: &[u8] = if in_data {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                if auth { b"250-localhost\r\n250 AUTH PLAIN\r\n" } else { b"250 localhost\r\n" }
+            } else if line.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                writer.write_all(b"221 bye\r\n").unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).unwrap();
+        }
+        tx.send(lines).unwrap();
+    });
+
+    (port, rx)
+}
+
+fn smtp_mailer(port: u16, user: &str) -> MyMailer {
+    let mut config = Config::load("config-sample.toml").unwrap();
+    config.mail.transport = "smtp".to_owned();
+    config.mail.from = "Rust China <noreply@rust.cc>".to_owned();
+    config.mail.smtp_host = "127.0.0.1".to_owned();
+    config.mail.smtp_port = port;
+    config.mail.smtp_tls = false;
+    config.mail.smtp_user = user.to_owned();
+    config.mail.smtp_password = "secret".to_owned();
+    MyMailer::new(&config)
+}
+
+#[test]
+fn smtp_hands_the_mail_to_the_relay() {
+    let (port, conversation) = fake_relay(false);
+    let mail = Mail {
+        to: "alice <alice@example.com>".to_owned(),
+        subject: "重置密码".to_owned(),
+        body: "第一行\n.第二行".to_owned(),
+        headers: vec![("List-Unsubscribe".to_owned(), "<http://localhost/u>\r\nBcc: x".to_owned())],
+    };
+    smtp_mailer(port, "").send(&mail).unwrap();
+    let lines = conversation.recv().unwrap();
+
+    assert_eq!(lines[0], "EHLO rust.cc");
+    // no AUTH without a user
+    assert_eq!(lines[1], "MAIL FROM:<noreply@rust.cc>");
+    assert_eq!(lines[2], "RCPT TO:<alice@example.com>");
+    assert_eq!(lines[3], "DATA");
+    assert_eq!(lines.last().unwrap(), "QUIT");
+
+    let data = &lines[4..lines.len() - 2];
+    assert!(data.contains(&"From: Rust China <noreply@rust.cc>".to_owned()));
+    assert!(data.contains(&"To: alice <alice@example.com>".to_owned()));
+    assert!(data.contains(&"Subject: =?UTF-8?B?6YeN572u5a+G56CB?=".to_owned()));
+    assert!(data.contains(&"Content-Transfer-Encoding: base64".to_owned()));
+    // a header value can't smuggle in another header
+    assert!(data.contains(&"List-Unsubscribe: <http://localhost/u>  Bcc: x".to_owned()));
+    assert!(!data.iter().any(|line| line.starts_with("Bcc:")));
+
+    let blank = data.iter().position(|line| line.is_empty()).unwrap();
+    let body = data[blank + 1..].concat().from_base64().unwrap();
+    assert_eq!(String::from_utf8(body).unwrap(), "第一行\n.第二行");
+}
+
+#[test]
+fn smtp_authenticates_with_the_configured_user() {
+    let (port, conversation) = fake_relay(true);
+    let mail = Mail {
+        to: "alice@example.com".to_owned(),
+        subject: "hello".to_owned(),
+        body: "hi".to_owned(),
+        headers: Vec::new(),
+    };
+    smtp_mailer(port, "mailer").send(&mail).unwrap();
+    let lines = conversation.recv().unwrap();
+
+    // base64 of "\0mailer\0secret"
+    assert_eq!(lines[1], "AUTH PLAIN AG1haWxlcgBzZWNyZXQ=");
+    assert_eq!(lines[2], "MAIL FROM:<noreply@rust.cc>");
+}
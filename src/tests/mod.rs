@@ -0,0 +1,328 @@
+// Drives the whole site through iron-test, with the same chains `serve`
+// builds, against a fresh SQLite database in memory for each `TestApp`, so
+// no database server is needed. With RUST_LANG_CN_DATABASE_BACKEND=mysql the
+// same tests run on MySQL instead: each `TestApp` creates a database of its
+// own on the server of config-sample.toml and the usual
+// RUST_LANG_CN_DATABASE_* variables, and drops it afterwards. That server's
+// account must be allowed to create databases.
+
+mod home;
+mod user;
+mod api;
+mod article;
+mod notify;
+mod news;
+mod password;
+mod mail;
+mod feed;
+mod comment;
+mod admin;
+mod command;
+
+use std::env;
+use std::process;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use iron::{Headers, status};
+use iron::status::Status;
+use hyper::header::SetCookie;
+use iron_test::{request, response};
+use hbsi::{HandlebarsEngine, DirectorySource};
+use chrono::NaiveDateTime;
+use mount::Mount;
+use mysql as my;
+use url::form_urlencoded;
+use rustc_serialize::json::Json;
+use base::config::Config;
+use base::db::Database;
+use base::mail::{Mail, Mailer, MyMailer};
+use base::migration;
+use base::constant;
+use base::model::ArticleForm;
+use repo::Repos;
+use repo::memory::Memory;
+
+// every fixture user has this password
+pub const PASSWORD: &'static str = "password123";
+
+const CSRF_TOKEN: &'static str = "test_csrf_token";
+
+static NEXT_DATABASE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+pub struct TestApp {
+    pub db: Database,
+    pub repos: Repos,
+    config: Config,
+    hbse: Arc<HandlebarsEngine>,
+    mailer: MyMailer,
+    mails: Arc<MemoryMailer>,
+    handler: Mount,
+    // only kept to be dropped with the app
+    #[allow(dead_code)]
+    mysql: Option<MysqlDatabase>,
+}
+
+impl TestApp {
+    pub fn new() -> TestApp {
+        TestApp::with_config(|_| {})
+    }
+
+    // with config-sample.toml as `change` leaves it
+    pub fn with_config<F: FnOnce(&mut Config)>(change: F) -> TestApp {
+        let mut config = Config::load("config-sample.toml").unwrap();
+        change(&mut config);
+
+        let (db, mysql) = match env::var("RUST_LANG_CN_DATABASE_BACKEND") {
+            Ok(ref backend) if backend == "mysql" => {
+                let (db, mysql) = MysqlDatabase::create(&mut config);
+                (db, Some(mysql))
+            },
+            _ => (Database::sqlite_memory(), None),
+        };
+        migration::run(&db).unwrap();
+
+        let mut hbse = HandlebarsEngine::new();
+        hbse.add(Box::new(DirectorySource::new("templates/", ".hbs")));
+        hbse.reload().unwrap();
+        let hbse = Arc::new(hbse);
+
+        // nothing is sent, the mails are kept for `sent_mails`
+        let mails = Arc::new(MemoryMailer(Mutex::new(Vec::new())));
+        let mailer = MyMailer::with(mails.clone());
+
+        let site = Arc::new(RwLock::new(config.site.clone()));
+        let handler = ::app(&config, &db, site, hbse.clone(), mailer.clone());
+
+        TestApp {
+            repos: Repos::new(&db),
+            db: db,
+            config: config,
+            hbse: hbse,
+            mailer: mailer,
+            mails: mails,
+            handler: handler,
+            mysql: mysql,
+        }
+    }
+
+    // a client without any login, keeping cookies between its requests
+    pub fn client(&self) -> Client {
+        Client {
+            app: self,
+            cookies: vec![("csrf_token".to_owned(), CSRF_TOKEN.to_owned())],
+            headers: Vec::new(),
+        }
+    }
+
+    // `role` is one of constant::USER::ROLE, the password is `PASSWORD`
+    pub fn create_user(&self, username: &str, role: i8) -> u64 {
+        create_user(&self.repos, username, role)
+    }
+
+    // one round of the notify worker, as if it ran at `now`
+    pub fn send_notify_mails(&self, now: NaiveDateTime) -> usize {
+        ::base::notify::send_mails(&self.config, &self.repos, &self.mailer, &self.hbse, now)
+            .unwrap()
+    }
+
+    // every mail the site sent so far, oldest first
+    pub fn sent_mails(&self) -> Vec<Mail> {
+        self.mails.0.lock().unwrap().clone()
+    }
+
+    pub fn create_article(&self, user_id: u64, title: &str, content: &str) -> u64 {
+        let form = ArticleForm {
+            category: constant::CATEGORY::ORIGINAL as i64,
+            title: title.to_owned(),
+            content: content.to_owned(),
+            tags: None,
+        };
+        self.repos.articles.create(user_id, &form).unwrap()
+    }
+}
+
+// empty repos of their own, for tests that call handlers directly
+pub fn memory_repos() -> Repos {
+    Memory::new().repos()
+}
+
+// a database on the MySQL server that lives as long as its `TestApp`
+struct MysqlDatabase {
+    server: my::Pool,
+    name: String,
+}
+
+impl MysqlDatabase {
+    // points `config` at the new database, which has tables.sql but no
+    // migrations yet
+    fn create(config: &mut Config) -> (Database, MysqlDatabase) {
+        let name = format!("rust_lang_cn_test_{}_{}", process::id(),
+                           NEXT_DATABASE.fetch_add(1, Ordering::SeqCst));
+
+        let server = {
+            let db_config = &config.database;
+            let mut builder = my::OptsBuilder::default();
+            builder.user(Some(&*db_config.user))
+                .pass(Some(&*db_config.password))
+                .ip_or_hostname(Some(&*db_config.host))
+                .tcp_port(db_config.port);
+            my::Pool::new_manual(1, 1, builder).unwrap()
+        };
+        server.prep_exec(format!("CREATE DATABASE `{}` CHARACTER SET utf8mb4 \
+                                  COLLATE utf8mb4_unicode_ci", name), ()).unwrap();
+
+        config.database.backend = "mysql".to_owned();
+        config.database.name = name.clone();
+        let db = Database::connect(config).unwrap();
+        if let Database::Mysql(ref pool) = db {
+            for statement in migration::statements(include_str!("../../tables.sql")) {
+                pool.prep_exec(statement, ()).unwrap();
+            }
+        }
+
+        (db, MysqlDatabase { server: server, name: name })
+    }
+}
+
+impl Drop for MysqlDatabase {
+    fn drop(&mut self) {
+        if let Err(e) = self.server.prep_exec(format!("DROP DATABASE `{}`", self.name), ()) {
+            println!("failed to drop test database {}: {}", self.name, e);
+        }
+    }
+}
+
+pub fn create_user(repos: &Repos, username: &str, role: i8) -> u64 {
+    let user_id = repos.users.create(username, &format!("{}@example.com", username),
+                                     &::base::password::hash(PASSWORD),
+                                     constant::USER::NOTIFY::OFF)
+        .unwrap().unwrap();
+    repos.users.set_role(user_id, role).unwrap();
+    user_id
+}
+
+pub struct MemoryMailer(Mutex<Vec<Mail>>);
+
+impl Mailer for MemoryMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        self.0.lock().unwrap().push(mail.clone());
+        Ok(())
+    }
+}
+
+pub struct Client<'a> {
+    app: &'a TestApp,
+    cookies: Vec<(String, String)>,
+    // sent with every request
+    headers: Vec<(&'static str, String)>,
+}
+
+impl<'a> Client<'a> {
+    pub fn get(&mut self, path: &str) -> TestResponse {
+        let headers = self.headers();
+        let result = request::get(&full_url(path), headers, &self.app.handler);
+        self.finish(result)
+    }
+
+    // posts a form the way the site's javascript does, with the csrf token
+    pub fn post(&mut self, path: &str, form: &[(&str, &str)]) -> TestResponse {
+        let headers = self.form_headers();
+        let body = form_body(form);
+        let result = request::post(&full_url(path), headers, &body, &self.app.handler);
+        self.finish(result)
+    }
+
+    // like `post`, for the api's PUT routes
+    pub fn put(&mut self, path: &str, form: &[(&str, &str)]) -> TestResponse {
+        let headers = self.form_headers();
+        let body = form_body(form);
+        let result = request::put(&full_url(path), headers, &body, &self.app.handler);
+        self.finish(result)
+    }
+
+    // logs in through /user/login, as a fixture user made by `create_user`
+    pub fn login(&mut self, username: &str) {
+        let resp = self.post("/user/login", &[("username", username), ("password", PASSWORD)]);
+        assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    }
+
+    fn headers(&self) -> Headers {
+        let cookie = self.cookies.iter()
+            .map(|&(ref name, ref value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>()
+            .join("; ");
+        let mut headers = Headers::new();
+        headers.set_raw("Cookie", vec![cookie.into_bytes()]);
+        for &(name, ref value) in &self.headers {
+            headers.set_raw(name, vec![value.clone().into_bytes()]);
+        }
+        headers
+    }
+
+    // the token the page got in its csrf-token meta tag
+    pub fn csrf_token(&self) -> String {
+        self.cookies.iter()
+            .find(|&&(ref name, _)| name == "csrf_token")
+            .map(|&(_, ref value)| value.clone())
+            .unwrap_or(CSRF_TOKEN.to_owned())
+    }
+
+    fn form_headers(&self) -> Headers {
+        let mut headers = self.headers();
+        headers.set_raw("Content-Type", vec![b"application/x-www-form-urlencoded".to_vec()]);
+        if !self.headers.iter().any(|&(name, _)| name == "X-CSRF-Token") {
+            headers.set_raw("X-CSRF-Token", vec![self.csrf_token().into_bytes()]);
+        }
+        headers.set_raw("X-Requested-With", vec![b"XMLHttpRequest".to_vec()]);
+        headers
+    }
+
+    fn finish(&mut self, result: ::iron::IronResult<::iron::Response>) -> TestResponse {
+        // errors nobody turned into a page still carry a response
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(err) => err.response,
+        };
+
+        if let Some(&SetCookie(ref cookies)) = resp.headers.get::<SetCookie>() {
+            for cookie in cookies {
+                self.cookies.retain(|&(ref name, _)| *name != cookie.name);
+                if !cookie.value.is_empty() {
+                    self.cookies.push((cookie.name.clone(), cookie.value.clone()));
+                }
+            }
+        }
+
+        TestResponse {
+            // iron answers 404 when nobody set a status
+            status: resp.status.unwrap_or(status::NotFound),
+            headers: resp.headers.clone(),
+            body: response::extract_body_to_string(resp),
+        }
+    }
+}
+
+pub struct TestResponse {
+    pub status: Status,
+    pub headers: Headers,
+    pub body: String,
+}
+
+impl TestResponse {
+    pub fn json(&self) -> Json {
+        match Json::from_str(&self.body) {
+            Ok(json) => json,
+            Err(e) => panic!("not json ({}): {}", e, self.body),
+        }
+    }
+}
+
+fn form_body(form: &[(&str, &str)]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form.iter())
+        .finish()
+}
+
+fn full_url(path: &str) -> String {
+    format!("http://localhost:3000{}", path)
+}
@@ -0,0 +1,254 @@
+// The news sources, against a fake http client that answers with canned
+// bodies instead of the real internet.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::*;
+use base::http::HttpClient;
+use base::news::{self, NewsItem, NewsSource};
+use base::news::hacker_news::HackerNews;
+use base::news::feed_source::FeedSource;
+use base::news::reddit::Reddit;
+use super::TestApp;
+
+const HN_API: &'static str = "https://hacker-news.firebaseio.com/v0";
+const FEED_URL: &'static str = "https://blog.rust-lang.org/feed.xml";
+const REDDIT_URL: &'static str = "https://www.reddit.com/r/rust/hot.json";
+
+const RSS: &'static str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <channel>
+    <title>This Week in Rust</title>
+    <link>https://this-week-in-rust.org/</link>
+    <item>
+      <title>This Week in Rust 191</title>
+      <link>https://this-week-in-rust.org/blog/2017/07/18/this-week-in-rust-191/</link>
+      <dc:creator>TWiR Contributors</dc:creator>
+      <comments>https://www.reddit.com/r/rust/comments/6o0ptw/</comments>
+      <pubDate>Tue, 18 Jul 2017 04:00:00 +0000</pubDate>
+    </item>
+    <item>
+      <title>An item without a link</title>
+    </item>
+  </channel>
+</rss>"#;
+
+const ATOM: &'static str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Rust Blog</title>
+  <entry>
+    <title>Announcing Rust 1.19</title>
+    <link rel="replies" href="https://internals.rust-lang.org/t/5555"/>
+    <link href="https://blog.rust-lang.org/2017/07/20/Rust-1.19.html"/>
+    <author><name>The Rust Core Team</name></author>
+    <published>2017-07-20T00:00:00+00:00</published>
+    <updated>2017-07-21T00:00:00+00:00</updated>
+  </entry>
+  <entry>
+    <title>An entry that only links to itself</title>
+    <link rel="self" href="https://blog.rust-lang.org/self"/>
+  </entry>
+</feed>"#;
+
+const REDDIT: &'static str = r#"{"kind": "Listing", "data": {"children": [
+  {"kind": "t3", "data": {"stickied": true, "title": "Hey Rustaceans! Got a question?",
+                          "url": "https://www.reddit.com/r/rust/comments/1/",
+                          "author": "mods", "score": 30, "num_comments": 100,
+                          "permalink": "/r/rust/comments/1/", "created_utc": 1500000000.0}},
+  {"kind": "t3", "data": {"stickied": false, "title": "Announcing Rust 1.19",
+                          "url": "https://blog.rust-lang.org/2017/07/20/Rust-1.19.html#fn1",
+                          "author": "alice", "score": 300, "num_comments": 50,
+                          "permalink": "/r/rust/comments/2/", "created_utc": 1500508800.0}},
+  {"kind": "t3", "data": {"title": "A post without a url", "author": "bob"}}
+]}}"#;
+
+// answers the urls it was given, any other url is a 404
+struct FakeClient(HashMap<String, String>);
+
+impl FakeClient {
+    fn new(pages: &[(&str, &str)]) -> Arc<FakeClient> {
+        Arc::new(FakeClient(pages.iter()
+                            .map(|&(url, body)| (url.to_owned(), body.to_owned()))
+                            .collect()))
+    }
+}
+
+impl HttpClient for FakeClient {
+    fn get(&self, url: &str) -> Result<String, String> {
+        self.0.get(url).cloned().ok_or_else(|| format!("GET {}: 404 Not Found", url))
+    }
+}
+
+fn titles(items: &[NewsItem]) -> Vec<&str> {
+    items.iter().map(|item| &*item.title).collect()
+}
+
+fn hn_item(id: u64) -> String {
+    format!("{}/item/{}.json", HN_API, id)
+}
+
+#[test]
+fn hacker_news_keeps_rust_stories_in_order() {
+    let client = FakeClient::new(&[
+        (&*format!("{}/topstories.json", HN_API), "[1, 2, 3, 4, 5, 6]"),
+        (&*hn_item(1), r#"{"id": 1, "type": "story", "by": "alice", "score": 42,
+                         "descendants": 7, "time": 1500000000,
+                         "title": "Announcing Rust 1.19", "url": "https://blog.rust-lang.org/"}"#),
+        (&*hn_item(2), r#"{"id": 2, "type": "story", "by": "bob", "score": 10,
+                         "time": 1500000000, "title": "Trust in Go",
+                         "url": "https://example.com/go"}"#),
+        (&*hn_item(3), r#"{"id": 3, "type": "story", "by": "carol", "score": 5,
+                         "time": 1500000000, "title": "Ask HN: Is Rust ready?"}"#),
+        (&*hn_item(4), r#"{"id": 4, "deleted": true, "title": "Rust is dead"}"#),
+        // 5 is missing, 6 is not json
+        (&*hn_item(6), "Rust <html>"),
+    ]);
+
+    let source = HackerNews::new("hacker_news", client, &format!("{}/", HN_API));
+    let items = source.fetch().unwrap();
+    assert_eq!(titles(&items), vec!["Announcing Rust 1.19", "Ask HN: Is Rust ready?"]);
+
+    let first = &items[0];
+    assert_eq!(first.source, "hacker_news");
+    assert_eq!(first.url, "https://blog.rust-lang.org/");
+    assert_eq!(first.author, "alice");
+    assert_eq!(first.score, Some(42));
+    assert_eq!(first.comments_count, Some(7));
+    assert_eq!(first.comments_url.as_ref().map(|u| &**u),
+               Some("https://news.ycombinator.com/item?id=1"));
+
+    // without a url of its own a story links to its discussion
+    assert_eq!(items[1].url, "https://news.ycombinator.com/item?id=3");
+    assert_eq!(items[1].comments_count, Some(0));
+}
+
+#[test]
+fn hacker_news_fails_without_top_stories() {
+    let source = HackerNews::new("hacker_news", FakeClient::new(&[]), HN_API);
+    assert!(source.fetch().is_err());
+
+    let client = FakeClient::new(&[(&*format!("{}/topstories.json", HN_API),
+                                    r#"{"error": "Permission denied"}"#)]);
+    let source = HackerNews::new("hacker_news", client, HN_API);
+    let err = source.fetch().err().unwrap();
+    assert!(err.contains("bad topstories.json"), "{}", err);
+}
+
+#[test]
+fn hacker_news_without_stories_is_empty() {
+    let client = FakeClient::new(&[(&*format!("{}/topstories.json", HN_API), "[]")]);
+    let source = HackerNews::new("hacker_news", client, HN_API);
+    assert!(source.fetch().unwrap().is_empty());
+}
+
+fn utc(s: &str) -> NaiveDateTime {
+    DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Local).naive_local()
+}
+
+#[test]
+fn rss_items_with_a_link() {
+    let source = FeedSource::new("twir", FakeClient::new(&[(FEED_URL, RSS)]), FEED_URL);
+    let items = source.fetch().unwrap();
+    assert_eq!(titles(&items), vec!["This Week in Rust 191"]);
+
+    let item = &items[0];
+    assert_eq!(item.source, "twir");
+    assert_eq!(item.url, "https://this-week-in-rust.org/blog/2017/07/18/this-week-in-rust-191/");
+    assert_eq!(item.author, "TWiR Contributors");
+    assert_eq!(item.score, None);
+    assert_eq!(item.comments_url.as_ref().map(|u| &**u),
+               Some("https://www.reddit.com/r/rust/comments/6o0ptw/"));
+    assert_eq!(item.publish_time, utc("2017-07-18T04:00:00+00:00"));
+}
+
+#[test]
+fn atom_entries_with_an_alternate_link() {
+    let source = FeedSource::new("rust_blog", FakeClient::new(&[(FEED_URL, ATOM)]), FEED_URL);
+    let items = source.fetch().unwrap();
+    assert_eq!(titles(&items), vec!["Announcing Rust 1.19"]);
+
+    let item = &items[0];
+    assert_eq!(item.url, "https://blog.rust-lang.org/2017/07/20/Rust-1.19.html");
+    assert_eq!(item.author, "The Rust Core Team");
+    assert_eq!(item.comments_url, None);
+    // published wins over updated
+    assert_eq!(item.publish_time, utc("2017-07-20T00:00:00+00:00"));
+}
+
+#[test]
+fn feed_errors() {
+    let source = FeedSource::new("rust_blog", FakeClient::new(&[]), FEED_URL);
+    assert!(source.fetch().is_err());
+
+    let client = FakeClient::new(&[(FEED_URL, "<html><body>moved</body></html>")]);
+    let err = FeedSource::new("rust_blog", client, FEED_URL).fetch().err().unwrap();
+    assert!(err.contains("unknown feed root"), "{}", err);
+
+    let client = FakeClient::new(&[(FEED_URL, "<rss version=\"2.0\"></rss>")]);
+    assert!(FeedSource::new("rust_blog", client, FEED_URL).fetch().is_err());
+
+    let client = FakeClient::new(&[(FEED_URL, "not xml at all")]);
+    assert!(FeedSource::new("rust_blog", client, FEED_URL).fetch().is_err());
+}
+
+#[test]
+fn reddit_listing_without_stickied_posts() {
+    let source = Reddit::new("reddit", FakeClient::new(&[(REDDIT_URL, REDDIT)]), REDDIT_URL);
+    let items = source.fetch().unwrap();
+    assert_eq!(titles(&items), vec!["Announcing Rust 1.19"]);
+
+    let item = &items[0];
+    assert_eq!(item.source, "reddit");
+    assert_eq!(item.author, "alice");
+    assert_eq!(item.score, Some(300));
+    assert_eq!(item.comments_count, Some(50));
+    assert_eq!(item.comments_url.as_ref().map(|u| &**u),
+               Some("https://www.reddit.com/r/rust/comments/2/"));
+    assert_eq!(item.publish_time, utc("2017-07-20T00:00:00+00:00"));
+}
+
+#[test]
+fn reddit_errors() {
+    let source = Reddit::new("reddit", FakeClient::new(&[]), REDDIT_URL);
+    assert!(source.fetch().is_err());
+
+    let client = FakeClient::new(&[(REDDIT_URL, "<html>too many requests</html>")]);
+    assert!(Reddit::new("reddit", client, REDDIT_URL).fetch().is_err());
+
+    let client = FakeClient::new(&[(REDDIT_URL, r#"{"error": 429}"#)]);
+    let err = Reddit::new("reddit", client, REDDIT_URL).fetch().err().unwrap();
+    assert!(err.contains("bad listing"), "{}", err);
+}
+
+#[test]
+fn normalize_url_drops_fragment_and_trailing_slash() {
+    assert_eq!(news::normalize_url(" https://blog.rust-lang.org/2017/#fn1 "),
+               "https://blog.rust-lang.org/2017");
+    assert_eq!(news::normalize_url("https://blog.rust-lang.org/2017"),
+               "https://blog.rust-lang.org/2017");
+    assert_eq!(news::normalize_url("https://example.com/?a=1"), "https://example.com/?a=1");
+}
+
+#[test]
+fn the_same_link_from_two_sources_is_saved_once() {
+    let app = TestApp::new();
+    let blog = FeedSource::new("rust_blog", FakeClient::new(&[(FEED_URL, ATOM)]), FEED_URL);
+    let reddit = Reddit::new("reddit", FakeClient::new(&[(REDDIT_URL, REDDIT)]), REDDIT_URL);
+
+    app.repos.news.save(&blog.fetch().unwrap()).unwrap();
+    let mut from_reddit = reddit.fetch().unwrap();
+    app.repos.news.save(&from_reddit).unwrap();
+
+    assert_eq!(app.repos.news.count().unwrap(), 1);
+    let items = app.repos.news.list(1).unwrap();
+    // the first source keeps it, and reddit's numbers are not taken over
+    assert_eq!(items[0].source, "rust_blog");
+    assert_eq!(items[0].score, None);
+
+    // a source only refreshes the numbers of its own items
+    from_reddit[0].source = "rust_blog".to_owned();
+    from_reddit[0].score = Some(1);
+    app.repos.news.save(&from_reddit).unwrap();
+    assert_eq!(app.repos.news.count().unwrap(), 1);
+    assert_eq!(app.repos.news.list(1).unwrap()[0].score, Some(1));
+}
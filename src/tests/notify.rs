@@ -0,0 +1,176 @@
+use chrono::*;
+use iron::status;
+use rustc_serialize::json::Json;
+use base::constant;
+use super::TestApp;
+
+#[test]
+fn instant_mail_has_every_new_reply() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    let mut alice = app.client();
+    alice.login("alice");
+    let resp = alice.post(&format!("/user/{}/notify", alice_id),
+                          &[("notify", &constant::USER::NOTIFY::INSTANT.to_string())]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+
+    reply_as_bob(&app, article_id, &["the first reply", "the second reply"]);
+
+    let now = Local::now().naive_local() + Duration::minutes(1);
+    assert_eq!(app.send_notify_mails(now), 1);
+    let mails = app.sent_mails();
+    assert_eq!(mails[0].to, "alice@example.com");
+    assert!(mails[0].body.contains("the first reply"));
+    assert!(mails[0].body.contains("the second reply"));
+
+    // nothing is mailed twice
+    assert_eq!(app.send_notify_mails(now), 0);
+}
+
+#[test]
+fn mails_quote_plain_text() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    app.repos.users.set_notify(alice_id, constant::USER::NOTIFY::INSTANT).unwrap();
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    reply_as_bob(&app, article_id,
+                 &["**thanks** @alice, see [the book](https://doc.rust-lang.org/book/)"]);
+
+    app.send_notify_mails(Local::now().naive_local() + Duration::minutes(1));
+    let body = &app.sent_mails()[0].body;
+    assert!(body.contains("thanks @alice, see the book"), "{}", body);
+    assert!(!body.contains("**") && !body.contains("]("), "{}", body);
+}
+
+#[test]
+fn digest_comes_once_a_day() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    app.repos.users.set_notify(alice_id, constant::USER::NOTIFY::DIGEST).unwrap();
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    reply_as_bob(&app, article_id, &["a reply to alice"]);
+
+    let tomorrow = Local::now().naive_local() + Duration::days(1);
+    assert_eq!(app.send_notify_mails(tomorrow), 1);
+    let mails = app.sent_mails();
+    assert!(mails[0].subject.contains("每日消息汇总"));
+    assert!(mails[0].body.contains("a reply to alice"));
+    assert_eq!(app.send_notify_mails(tomorrow), 0);
+}
+
+#[test]
+fn messages_read_on_the_site_are_not_mailed() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    app.repos.users.set_notify(alice_id, constant::USER::NOTIFY::INSTANT).unwrap();
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    reply_as_bob(&app, article_id, &["a reply to alice"]);
+
+    let mut alice = app.client();
+    alice.login("alice");
+    alice.post(&format!("/user/{}/messages/read_all", alice_id), &[]);
+
+    assert_eq!(app.send_notify_mails(Local::now().naive_local() + Duration::minutes(1)), 0);
+    assert!(app.sent_mails().is_empty());
+}
+
+#[test]
+fn unsubscribe_link_turns_mails_off() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    app.repos.users.set_notify(alice_id, constant::USER::NOTIFY::INSTANT).unwrap();
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    reply_as_bob(&app, article_id, &["the first reply"]);
+    let now = Local::now().naive_local() + Duration::minutes(1);
+    assert_eq!(app.send_notify_mails(now), 1);
+
+    let mails = app.sent_mails();
+    let url = mails[0].body.lines().find(|line| line.contains("/unsubscribe/")).unwrap();
+    let path = url.trim().trim_left_matches("http://localhost:3000");
+    assert!(mails[0].headers.contains(&("List-Unsubscribe".to_owned(),
+                                        format!("<http://localhost:3000{}>", path))));
+
+    let mut client = app.client();
+    assert_eq!(client.get(&format!("/user/{}/unsubscribe/wrong", alice_id)).status,
+               status::NotFound);
+    assert_eq!(client.post(&format!("/user/{}/unsubscribe/wrong", alice_id), &[]).status,
+               status::NotFound);
+
+    // opening the link only asks
+    let resp = client.get(path);
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("确认退订"));
+    assert_eq!(app.repos.users.find(alice_id).unwrap().unwrap().notify,
+               constant::USER::NOTIFY::INSTANT);
+
+    // no login and no csrf token needed, as for the one click of a mail client
+    client.cookies.clear();
+    let resp = client.post(path, &[("List-Unsubscribe", "One-Click")]);
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("已退订"));
+    assert_eq!(app.repos.users.find(alice_id).unwrap().unwrap().notify,
+               constant::USER::NOTIFY::OFF);
+
+    reply_as_bob(&app, article_id, &["the second reply"]);
+    let now = Local::now().naive_local() + Duration::minutes(1);
+    assert_eq!(app.send_notify_mails(now), 0);
+
+    // turning mails back on doesn't bring up what came meanwhile
+    app.repos.users.set_notify(alice_id, constant::USER::NOTIFY::INSTANT).unwrap();
+    assert_eq!(app.send_notify_mails(now), 0);
+}
+
+#[test]
+fn nobody_is_mailed_before_choosing() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    reply_as_bob(&app, article_id, &["a reply to alice"]);
+
+    assert_eq!(app.send_notify_mails(Local::now().naive_local() + Duration::days(1)), 0);
+    assert!(app.sent_mails().is_empty());
+}
+
+#[test]
+fn mails_can_be_chosen_at_registration() {
+    let app = TestApp::new();
+    let mut client = app.client();
+    let resp = client.post("/user/register",
+                           &[("username", "alice"), ("email", "alice@example.com"),
+                             ("password", "password123"),
+                             ("notify", &constant::USER::NOTIFY::INSTANT.to_string())]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    let resp = client.post("/user/register",
+                           &[("username", "carol"), ("email", "carol@example.com"),
+                             ("password", "password123")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+
+    let notify = |username| {
+        app.repos.users.find_by_username(username).unwrap().unwrap().notify
+    };
+    assert_eq!(notify("alice"), constant::USER::NOTIFY::INSTANT);
+    assert_eq!(notify("carol"), constant::USER::NOTIFY::OFF);
+}
+
+fn reply_as_bob(app: &TestApp, article_id: u64, contents: &[&str]) {
+    let mut bob = app.client();
+    bob.login("bob");
+    for content in contents {
+        let resp = bob.post("/comment/new", &[("article_id", &article_id.to_string()),
+                                               ("content", *content)]);
+        assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    }
+}
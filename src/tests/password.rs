@@ -0,0 +1,37 @@
+use crypto::md5;
+use crypto::digest::Digest;
+use base::password::{self, Verified};
+
+#[test]
+fn scrypt_round_trip() {
+    let hash = password::hash("correct horse");
+    assert!(hash.starts_with("$rscrypt$"), "{}", hash);
+    assert_eq!(password::verify("correct horse", "", &hash), Verified::Ok);
+    // a new salt every time
+    assert!(password::hash("correct horse") != hash);
+}
+
+#[test]
+fn legacy_md5_needs_upgrade() {
+    let mut sh = md5::Md5::new();
+    sh.input_str("correct horse");
+    sh.input_str("salt");
+    let hash = sh.result_str();
+
+    assert_eq!(password::verify("correct horse", "salt", &hash), Verified::NeedsUpgrade);
+    assert_eq!(password::verify("correct horse", "other salt", &hash), Verified::Fail);
+}
+
+#[test]
+fn wrong_password_fails() {
+    let hash = password::hash("correct horse");
+    assert_eq!(password::verify("battery staple", "", &hash), Verified::Fail);
+
+    let mut sh = md5::Md5::new();
+    sh.input_str("correct horsesalt");
+    assert_eq!(password::verify("battery staple", "salt", &sh.result_str()), Verified::Fail);
+
+    // nor does anything no hasher made
+    assert_eq!(password::verify("correct horse", "", "$rpbkdf2$0$AAAA$AAAA$"), Verified::Fail);
+    assert_eq!(password::verify("correct horse", "", ""), Verified::Fail);
+}
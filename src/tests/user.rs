@@ -0,0 +1,310 @@
+use iron::status;
+use chrono::*;
+use rustc_serialize::json::Json;
+use base::constant;
+use base::util;
+use super::{TestApp, Client, PASSWORD};
+
+#[test]
+fn login_with_wrong_password_fails() {
+    let app = TestApp::new();
+    app.create_user("alice", constant::USER::ROLE::MEMBER);
+
+    let resp = app.client().post("/user/login", &[("username", "alice"),
+                                                  ("password", "wrong password")]);
+    let json = resp.json();
+    assert_eq!(json.find("status"), Some(&Json::U64(1)));
+    assert_eq!(json.find("message").and_then(|m| m.as_string()),
+               Some("对不起，用户名或密码不对"));
+}
+
+#[test]
+fn post_without_csrf_token_is_refused() {
+    let app = TestApp::new();
+    app.create_user("alice", constant::USER::ROLE::MEMBER);
+
+    let mut client = app.client();
+    client.cookies.clear();
+    let resp = client.post("/user/login", &[("username", "alice"),
+                                            ("password", super::PASSWORD)]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(1)));
+}
+
+#[test]
+fn bearer_header_does_not_skip_csrf_on_the_site() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+
+    let mut client = app.client();
+    client.login("alice");
+    client.cookies.retain(|&(ref name, _)| name != "csrf_token");
+    client.headers.push(("Authorization", "Bearer whatever".to_owned()));
+    let resp = client.post(&format!("/user/{}/messages/read_all", user_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(1)), "{}", resp.body);
+}
+
+#[test]
+fn login_and_logout_rotate_csrf_token() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let path = format!("/user/{}/messages/read_all", user_id);
+
+    let mut client = app.client();
+    let planted = client.csrf_token();
+    client.login("alice");
+    let logged_in = client.csrf_token();
+    assert!(logged_in != planted);
+
+    // the token from before the login is no good any more
+    client.headers.push(("X-CSRF-Token", planted));
+    assert_eq!(client.post(&path, &[]).json().find("status"), Some(&Json::U64(1)));
+    client.headers.clear();
+    let resp = client.post(&path, &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+
+    let resp = client.post("/user/logout", &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    assert!(client.csrf_token() != logged_in);
+}
+
+#[test]
+fn real_ip_only_from_trusted_proxies() {
+    // iron-test requests come from 127.0.0.1, which config-sample.toml trusts
+    let trusting = TestApp::new();
+    let untrusting = TestApp::with_config(|config| config.trusted_proxies.clear());
+
+    for &(app, shown) in &[(&trusting, true), (&untrusting, false)] {
+        let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+        let mut client = app.client();
+        client.headers.push(("X-Real-IP", "203.0.113.7".to_owned()));
+        client.login("alice");
+        let resp = client.get(&format!("/user/{}/sessions", user_id));
+        assert_eq!(resp.status, status::Ok);
+        assert_eq!(resp.body.contains("203.0.113.7"), shown);
+    }
+}
+
+#[test]
+fn messages_need_login() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let path = format!("/user/{}/messages", user_id);
+
+    let resp = app.client().get(&path);
+    assert_eq!(resp.status, status::Found);
+
+    let mut client = app.client();
+    client.login("alice");
+    let resp = client.get(&path);
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("alice"));
+}
+
+#[test]
+fn reply_notifies_author() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    let mut bob = app.client();
+    bob.login("bob");
+    let resp = bob.post("/comment/new", &[("article_id", &article_id.to_string()),
+                                          ("content", "welcome @alice")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+
+    let mut alice = app.client();
+    alice.login("alice");
+    let resp = alice.get(&format!("/user/{}/messages", alice_id));
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("bob"));
+    assert!(resp.body.contains("Hello Rust China"));
+}
+
+#[test]
+fn messages_stay_unread_until_marked() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(alice_id, "Hello Rust China", "the first article");
+
+    let mut bob = app.client();
+    bob.login("bob");
+    for content in &["the first reply", "the second reply"] {
+        bob.post("/comment/new", &[("article_id", &article_id.to_string()),
+                                   ("content", *content)]);
+    }
+
+    let mut alice = app.client();
+    alice.login("alice");
+    let path = format!("/user/{}/messages", alice_id);
+    assert_eq!(alice.get(&path).status, status::Ok);
+    assert_eq!(unread_count(&mut alice), 2);
+
+    let resp = alice.get("/api/v1/messages");
+    let json = resp.json();
+    let messages = json.find_path(&["data", "messages"]).and_then(|m| m.as_array()).unwrap();
+    let first_id = messages[0].find("id").and_then(|id| id.as_u64()).unwrap();
+    let second_id = messages[1].find("id").and_then(|id| id.as_u64()).unwrap();
+
+    let resp = alice.post(&format!("{}/{}/read", path, first_id), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    assert_eq!(unread_count(&mut alice), 1);
+
+    alice.post(&format!("{}/{}/unread", path, first_id), &[]);
+    assert_eq!(unread_count(&mut alice), 2);
+
+    // bob can't touch alice's messages
+    bob.post(&format!("{}/{}/delete", path, second_id), &[]);
+    assert_eq!(unread_count(&mut alice), 2);
+
+    alice.post(&format!("{}/{}/delete", path, second_id), &[]);
+    assert_eq!(unread_count(&mut alice), 1);
+
+    alice.post(&format!("{}/read_all", path), &[]);
+    assert_eq!(unread_count(&mut alice), 0);
+    assert!(alice.get(&path).body.contains("the first reply"));
+}
+
+#[test]
+fn messages_filter_by_mode() {
+    let app = TestApp::new();
+    let alice_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let bob_id = app.create_user("bob", constant::USER::ROLE::MEMBER);
+    app.create_user("carol", constant::USER::ROLE::MEMBER);
+    let article_id = app.create_article(bob_id, "Hello Rust China", "the first article");
+
+    let mut carol = app.client();
+    carol.login("carol");
+    carol.post("/comment/new", &[("article_id", &article_id.to_string()),
+                                 ("content", "what do you think, @alice?")]);
+
+    let mut alice = app.client();
+    alice.login("alice");
+    let path = format!("/user/{}/messages", alice_id);
+    let resp = alice.get(&format!("{}?mode={}", path, constant::MESSAGE::MODE::MENTION));
+    assert!(resp.body.contains("what do you think"));
+    let resp = alice.get(&format!("{}?mode={}", path, constant::MESSAGE::MODE::REPLY_ARTICLE));
+    assert!(!resp.body.contains("what do you think"));
+    assert_eq!(alice.get(&format!("{}?mode=42", path)).status, status::NotFound);
+}
+
+// what the unread badge shows
+#[test]
+fn forgot_and_reset_password() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    let mut alice = app.client();
+    alice.login("alice");
+    app.repos.tokens.create(user_id, "bot", &util::sha256_hex("rlc_bot"),
+                            constant::ACCESS_TOKEN::SCOPE::READ,
+                            Local::now().naive_local()).unwrap();
+
+    // an unknown address looks the same but gets no mail
+    let mut client = app.client();
+    let resp = client.post("/user/password/forgot", &[("email", "nobody@example.com")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    assert!(app.sent_mails().is_empty());
+
+    client.post("/user/password/forgot", &[("email", "alice@example.com")]);
+    client.post("/user/password/forgot", &[("email", "alice@example.com")]);
+    let mails = app.sent_mails();
+    assert_eq!(mails.len(), 2);
+    assert_eq!(mails[0].to, "alice@example.com");
+    let first = reset_token(&mails[0].body);
+    let second = reset_token(&mails[1].body);
+    assert!(first != second);
+
+    // the link only lasts PASSWORD_RESET_EXPIRE_HOURS
+    let now = Local::now().naive_local();
+    let expired = now + Duration::hours(constant::PASSWORD_RESET_EXPIRE_HOURS) +
+        Duration::minutes(1);
+    assert!(app.repos.users.find_password_reset(&util::sha256_hex(&first), now)
+            .unwrap().is_some());
+    assert!(app.repos.users.find_password_reset(&util::sha256_hex(&first), expired)
+            .unwrap().is_none());
+
+    let path = format!("/user/password/reset/{}", first);
+    assert_eq!(client.get(&path).status, status::Ok);
+    let resp = client.post(&path, &[("password", "new password")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+
+    // each link works once, and the other one is revoked with it
+    let resp = client.post(&path, &[("password", "another password")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(1)), "{}", resp.body);
+    let other_path = format!("/user/password/reset/{}", second);
+    assert_eq!(client.get(&other_path).status, status::NotFound);
+    let resp = client.post(&other_path, &[("password", "another password")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(1)), "{}", resp.body);
+
+    // whoever held the old password is locked out everywhere
+    assert!(app.repos.sessions.list(user_id, Local::now().naive_local()).unwrap().is_empty());
+    assert!(app.repos.tokens.list(user_id).unwrap().is_empty());
+    assert_eq!(alice.get(&format!("/user/{}/sessions", user_id)).status, status::Found);
+
+    let resp = app.client().post("/user/login", &[("username", "alice"),
+                                                  ("password", PASSWORD)]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(1)));
+    let resp = app.client().post("/user/login", &[("username", "alice"),
+                                                  ("password", "new password")]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+}
+
+#[test]
+fn revoke_sessions() {
+    let app = TestApp::new();
+    let user_id = app.create_user("alice", constant::USER::ROLE::MEMBER);
+    app.create_user("bob", constant::USER::ROLE::MEMBER);
+    let sessions_path = format!("/user/{}/sessions", user_id);
+
+    let mut devices = Vec::new();
+    for name in &["TestPhone", "TestLaptop", "TestTablet"] {
+        let mut client = app.client();
+        client.headers.push(("User-Agent", name.to_string()));
+        client.login("alice");
+        devices.push(client);
+    }
+    let (mut phone, mut laptop, mut tablet) = (devices.remove(0), devices.remove(0),
+                                               devices.remove(0));
+
+    let resp = laptop.get(&sessions_path);
+    assert_eq!(resp.status, status::Ok);
+    assert!(resp.body.contains("TestPhone") && resp.body.contains("TestTablet"), "{}", resp.body);
+    assert_eq!(resp.body.matches("当前设备").count(), 1);
+    let sessions = app.repos.sessions.list(user_id, Local::now().naive_local()).unwrap();
+    assert_eq!(sessions.len(), 3);
+    let phone_session = sessions.iter().find(|s| s.user_agent == "TestPhone").unwrap().id;
+
+    // nobody else may end them
+    let mut bob = app.client();
+    bob.login("bob");
+    let resp = bob.post(&format!("{}/{}/delete", sessions_path, phone_session), &[]);
+    assert_eq!(resp.json().find("message").and_then(|m| m.as_string()), Some("非法请求"));
+    assert_eq!(phone.get(&sessions_path).status, status::Ok);
+
+    let resp = laptop.post(&format!("{}/{}/delete", sessions_path, phone_session), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    // the revoked cookie is no login any more
+    assert_eq!(phone.get(&sessions_path).status, status::Found);
+    assert_eq!(tablet.get(&sessions_path).status, status::Ok);
+
+    let resp = laptop.post(&format!("{}/logout_others", sessions_path), &[]);
+    assert_eq!(resp.json().find("status"), Some(&Json::U64(0)), "{}", resp.body);
+    assert_eq!(tablet.get(&sessions_path).status, status::Found);
+    assert_eq!(laptop.get(&sessions_path).status, status::Ok);
+    let sessions = app.repos.sessions.list(user_id, Local::now().naive_local()).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].user_agent, "TestLaptop");
+}
+
+// the token of the link in a reset mail
+fn reset_token(body: &str) -> String {
+    let prefix = "http://localhost:3000/user/password/reset/";
+    let start = body.find(prefix).expect(body) + prefix.len();
+    body[start..].split_whitespace().next().unwrap().to_owned()
+}
+
+fn unread_count(client: &mut Client) -> u64 {
+    let resp = client.get("/api/v1/messages/unread_count");
+    resp.json().find_path(&["data", "count"]).and_then(|c| c.as_u64()).unwrap()
+}
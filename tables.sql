@@ -1,3 +1,6 @@
+-- Base schema. Later changes live in migrations/ and are applied with
+-- `rust_lang_cn migrate`; add new tables and columns there, not here.
+
 CREATE TABLE `user` (
   `id` int(11) NOT NULL AUTO_INCREMENT,
   `username` varchar(32) COLLATE utf8mb4_unicode_ci NOT NULL,
@@ -0,0 +1,137 @@
+-- tables.sql and migrations 1 to 12 for the sqlite backend. `rust_lang_cn
+-- migrate` creates them in an empty database; later changes live in
+-- migrations/sqlite/ next to their MySQL version.
+-- Times are TEXT as "%Y-%m-%d %H:%M:%S".
+
+CREATE TABLE user (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  username TEXT NOT NULL COLLATE NOCASE UNIQUE,
+  email TEXT NOT NULL COLLATE NOCASE UNIQUE,
+  password TEXT NOT NULL,
+  salt TEXT NOT NULL,
+  role INTEGER NOT NULL DEFAULT 0,
+  status INTEGER NOT NULL DEFAULT 0,
+  create_time TEXT NOT NULL
+);
+
+CREATE TABLE article (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  category INTEGER NOT NULL DEFAULT 0,
+  title TEXT NOT NULL,
+  content TEXT NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES user (id),
+  comments_count INTEGER NOT NULL DEFAULT 0,
+  create_time TEXT NOT NULL,
+  status INTEGER NOT NULL DEFAULT 0,
+  priority INTEGER NOT NULL DEFAULT 0,
+  flag INTEGER NOT NULL DEFAULT 0,
+  update_time TEXT NOT NULL
+);
+CREATE INDEX article_user_id ON article (user_id);
+
+CREATE TABLE tag (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE,
+  create_time TEXT NOT NULL
+);
+
+CREATE TABLE article_tag (
+  article_id INTEGER NOT NULL REFERENCES article (id),
+  tag_id INTEGER NOT NULL REFERENCES tag (id),
+  PRIMARY KEY (article_id, tag_id)
+);
+CREATE INDEX article_tag_tag_id ON article_tag (tag_id);
+
+CREATE TABLE comment (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  article_id INTEGER NOT NULL REFERENCES article (id),
+  user_id INTEGER NOT NULL REFERENCES user (id),
+  parent_id INTEGER REFERENCES comment (id),
+  content TEXT NOT NULL,
+  status INTEGER NOT NULL DEFAULT 0,
+  create_time TEXT NOT NULL,
+  update_time TEXT
+);
+CREATE INDEX comment_article_id ON comment (article_id);
+CREATE INDEX comment_user_id ON comment (user_id);
+
+CREATE TABLE message (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  article_id INTEGER REFERENCES article (id),
+  comment_id INTEGER REFERENCES comment (id),
+  from_user_id INTEGER REFERENCES user (id),
+  to_user_id INTEGER NOT NULL REFERENCES user (id),
+  content TEXT,
+  mode INTEGER NOT NULL DEFAULT 0,
+  status INTEGER NOT NULL DEFAULT 0,
+  create_time TEXT NOT NULL
+);
+CREATE INDEX message_to_user_id ON message (to_user_id);
+CREATE INDEX message_comment_id ON message (comment_id);
+
+CREATE TABLE password_reset (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES user (id),
+  token_hash TEXT NOT NULL UNIQUE,
+  create_time TEXT NOT NULL,
+  expire_time TEXT NOT NULL,
+  used_time TEXT
+);
+
+CREATE TABLE session (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES user (id),
+  token_hash TEXT NOT NULL UNIQUE,
+  last_seen_ip TEXT NOT NULL,
+  user_agent TEXT NOT NULL,
+  create_time TEXT NOT NULL,
+  last_seen_time TEXT NOT NULL,
+  expire_time TEXT NOT NULL
+);
+CREATE INDEX session_user_id ON session (user_id);
+
+CREATE TABLE comment_revision (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  comment_id INTEGER NOT NULL REFERENCES comment (id),
+  content TEXT NOT NULL,
+  create_time TEXT NOT NULL
+);
+CREATE INDEX comment_revision_comment_id ON comment_revision (comment_id);
+
+CREATE TABLE access_token (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES user (id),
+  name TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  scopes INTEGER NOT NULL DEFAULT 0,
+  create_time TEXT NOT NULL,
+  last_used_time TEXT
+);
+CREATE INDEX access_token_user_id ON access_token (user_id);
+
+CREATE TABLE news (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  source TEXT NOT NULL,
+  title TEXT NOT NULL,
+  url TEXT NOT NULL,
+  url_hash TEXT NOT NULL UNIQUE,
+  score INTEGER,
+  author TEXT NOT NULL,
+  comments_count INTEGER,
+  comments_url TEXT,
+  publish_time TEXT NOT NULL,
+  fetch_time TEXT NOT NULL
+);
+CREATE INDEX news_publish_time ON news (publish_time);
+
+CREATE TABLE github_user (
+  id INTEGER PRIMARY KEY,
+  username TEXT NOT NULL,
+  email TEXT NOT NULL,
+  avatar_url TEXT NOT NULL,
+  user_id INTEGER REFERENCES user (id),
+  create_time TEXT NOT NULL,
+  update_time TEXT NOT NULL,
+  bind_time TEXT
+);
+CREATE INDEX github_user_user_id ON github_user (user_id);